@@ -1,11 +1,13 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use serde_json::json;
 
 mod client;
 mod commands;
+mod output;
 
 use client::Client;
+use output::{OutputFormat, TreeFormat};
 
 #[derive(Parser)]
 #[command(name = "deskctl")]
@@ -44,6 +46,106 @@ enum Commands {
     #[command(subcommand)]
     Query(QueryCommands),
 
+    /// Database maintenance
+    #[command(subcommand)]
+    Db(DbCommands),
+
+    /// Runtime preferences that override the config file
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Export task history, tasks and workflows
+    Export {
+        /// Table to export (tasks, task_history, workflows); repeatable, all by default
+        #[arg(short, long = "table")]
+        tables: Vec<String>,
+
+        /// Output format (jsonl, csv)
+        #[arg(short, long, default_value = "jsonl")]
+        format: String,
+
+        /// Only records at or after this time (e.g. 2025-01-01)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only records before this time
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Only these task or event types; repeatable
+        #[arg(long = "type")]
+        types: Vec<String>,
+
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Import data written by `deskctl export`
+    Import {
+        /// File to import
+        file: String,
+
+        /// Input format (jsonl, csv); inferred from the file extension by default
+        #[arg(short, long)]
+        format: Option<String>,
+
+        /// Target table (required for csv)
+        #[arg(short, long)]
+        table: Option<String>,
+    },
+
+    /// Search task history and clipboard history
+    Search {
+        /// Words to search for; all must match, `word*` matches prefixes
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Source to search (task_history, clipboard); repeatable, all by default
+        #[arg(short, long = "source")]
+        sources: Vec<String>,
+
+        /// Only entries at or after this time (e.g. 2025-01-01)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only entries before this time
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Maximum number of results
+        #[arg(short, long)]
+        limit: Option<usize>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Operation counts, success rates and latency percentiles
+    Stats {
+        /// Time window counting back from now (e.g. 30m, 24h, 7d)
+        #[arg(short, long, default_value = "24h")]
+        window: String,
+
+        /// Start of the window instead of --window (e.g. 2025-01-01)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End of the window
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Break down by planned_input_method (the input method tried first)
+        /// or compositor; repeatable
+        #[arg(short, long = "by")]
+        group_by: Vec<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
     /// AT-SPI Element operations
     #[command(subcommand)]
     Element(ElementCommands),
@@ -108,6 +210,152 @@ enum WorkflowCommands {
 enum QueryCommands {
     /// Get task history
     History { limit: Option<usize> },
+
+    /// Run a read-only SQL query against the daemon database
+    Sql {
+        /// SELECT statement to run
+        sql: String,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+
+        /// Maximum number of rows to return
+        #[arg(short, long)]
+        limit: Option<usize>,
+
+        /// Query time limit in milliseconds
+        #[arg(short, long)]
+        timeout_ms: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Back up the database (to the backup directory unless a path is given)
+    Backup {
+        /// Output file for the backup
+        path: Option<String>,
+
+        /// List existing backups instead of creating one
+        #[arg(short, long, conflicts_with = "path")]
+        list: bool,
+    },
+
+    /// Restore the database from a backup file
+    Restore {
+        /// Backup file to restore
+        file: String,
+    },
+
+    /// Rotate the encryption key for clipboard history and workflow steps
+    Rekey,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Show all preferences, or the value of one
+    Get {
+        /// Preference key (e.g. input_timing.typing_delay_ms)
+        key: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Override a setting without restarting the daemon
+    Set {
+        /// Preference key
+        key: String,
+
+        /// New value; lists are comma-separated
+        value: String,
+    },
+
+    /// Remove an override and go back to the config file value
+    Unset {
+        /// Preference key
+        key: String,
+    },
+
+    /// Show every effective setting
+    Show {
+        /// Also show where each value came from
+        #[arg(long)]
+        origin: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Re-read the daemon's config file without restarting it
+    Reload {
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Show which profile applies to an application and the settings it gets;
+    /// the focused application when none is given
+    Profile {
+        /// AT-SPI application name
+        #[arg(long)]
+        app_name: Option<String>,
+
+        /// X11/XWayland window class
+        #[arg(long)]
+        window_class: Option<String>,
+
+        /// Wayland app_id
+        #[arg(long)]
+        app_id: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Change the daemon's log verbosity until it restarts or reloads a new log_level
+    LogLevel {
+        /// Level (error, warn, info, debug, trace) or a filter such as deskd=debug,zbus=info
+        level: String,
+    },
+}
+
+/// The element an `element` subcommand acts on
+#[derive(Args)]
+struct ElementTarget {
+    /// Element name (a selector with --selector, a handle with --handle)
+    name: String,
+
+    /// Treat NAME as a selector
+    #[arg(long)]
+    selector: bool,
+
+    /// Treat NAME as an element handle from `element find`
+    #[arg(long, conflicts_with = "selector")]
+    handle: bool,
+
+    /// Let names differ in case, spacing, mnemonics (_Save) and ellipses
+    #[arg(long, conflicts_with_all = ["selector", "handle"])]
+    fuzzy: bool,
+}
+
+impl ElementTarget {
+    /// The command's own request `fields` plus those naming the element
+    fn to_json(&self, fields: serde_json::Value) -> serde_json::Value {
+        let key = match (self.selector, self.handle) {
+            (true, _) => "selector",
+            (_, true) => "handle",
+            _ => "name",
+        };
+        let mut data = fields;
+        data[key] = json!(self.name);
+        data["fuzzy"] = json!(self.fuzzy.then_some(true));
+        data
+    }
 }
 
 #[derive(Subcommand)]
@@ -118,15 +366,36 @@ enum ElementCommands {
         #[arg(short, long)]
         name: Option<String>,
 
-        /// Element role to search for
+        /// Element role to search for (e.g. "push button")
         #[arg(short, long)]
         role: Option<String>,
+
+        /// State the element must have (e.g. enabled, focused); repeatable
+        #[arg(long = "state")]
+        states: Vec<String>,
+
+        /// Only search this application
+        #[arg(short, long)]
+        app: Option<String>,
+
+        /// Selector such as 'dialog push-button[name="OK"]:enabled', instead
+        /// of name, role, state and app
+        #[arg(long, conflicts_with_all = ["name", "role", "states", "app"])]
+        selector: Option<String>,
+
+        /// Let names differ in case, spacing, mnemonics (_Save) and ellipses
+        #[arg(long, conflicts_with = "selector")]
+        fuzzy: bool,
+
+        /// Levels below each application to search
+        #[arg(long)]
+        max_depth: Option<usize>,
     },
 
     /// Click an element by name
     Click {
-        /// Element name
-        name: String,
+        #[command(flatten)]
+        target: ElementTarget,
 
         /// Mouse button (left, right, middle)
         #[arg(short, long, default_value = "left")]
@@ -135,14 +404,14 @@ enum ElementCommands {
 
     /// Double-click an element by name
     DoubleClick {
-        /// Element name
-        name: String,
+        #[command(flatten)]
+        target: ElementTarget,
     },
 
     /// Type text into an element
     Type {
-        /// Element name
-        name: String,
+        #[command(flatten)]
+        target: ElementTarget,
 
         /// Text to type
         text: String,
@@ -150,16 +419,185 @@ enum ElementCommands {
         /// Type securely (no logging)
         #[arg(short, long)]
         secure: bool,
+
+        /// How to enter the text (keystrokes, editable_text, clipboard_paste);
+        /// defaults to the application profile's strategies
+        #[arg(long)]
+        strategy: Option<String>,
     },
 
     /// Focus an element by name
     Focus {
-        /// Element name
-        name: String,
+        #[command(flatten)]
+        target: ElementTarget,
     },
 
     /// Get the currently focused element
     GetFocused,
+
+    /// Wait until an element has keyboard focus
+    WaitFocus {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// Give up after this many milliseconds (default: the focus timeout)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// List the actions an element offers, its default action first
+    Actions {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Invoke one of an element's actions by name
+    DoAction {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// Action name, as listed by `element actions`
+        action: String,
+    },
+
+    /// Read an element's text, all of it or a range of characters
+    Text {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// First character (default: the start of the text)
+        #[arg(long)]
+        start: Option<i32>,
+
+        /// Character after the last (default: the end of the text)
+        #[arg(long)]
+        end: Option<i32>,
+    },
+
+    /// Show an element's caret offset and selection
+    Caret {
+        #[command(flatten)]
+        target: ElementTarget,
+    },
+
+    /// Move an element's caret
+    SetCaret {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// Character offset
+        offset: i32,
+    },
+
+    /// Select characters START..END of an element's text; an empty range clears
+    Select {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// First character
+        start: i32,
+
+        /// Character after the last
+        end: i32,
+    },
+
+    /// Insert text into an element through EditableText
+    Insert {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// Text to insert
+        text: String,
+
+        /// Character offset (default: at the caret)
+        #[arg(long)]
+        at: Option<i32>,
+    },
+
+    /// Delete characters of an element's text through EditableText
+    DeleteText {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// First character (default: the start of the text)
+        #[arg(long)]
+        start: Option<i32>,
+
+        /// Character after the last (default: the end of the text)
+        #[arg(long)]
+        end: Option<i32>,
+    },
+
+    /// Replace characters of an element's text through EditableText
+    Replace {
+        #[command(flatten)]
+        target: ElementTarget,
+
+        /// New text
+        text: String,
+
+        /// First character (default: the start of the text)
+        #[arg(long)]
+        start: Option<i32>,
+
+        /// Character after the last (default: the end of the text)
+        #[arg(long)]
+        end: Option<i32>,
+    },
+
+    /// Show the accessibility tree of the desktop, an application or a window
+    Tree {
+        /// Only this application
+        #[arg(short, long)]
+        app: Option<String>,
+
+        /// Only top-level windows whose name contains this
+        #[arg(short, long)]
+        window: Option<String>,
+
+        /// Levels below each application (or window) to show
+        #[arg(short, long)]
+        depth: Option<usize>,
+
+        /// Only objects matching this selector, with their ancestors
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Also store the tree as a snapshot under this name
+        #[arg(long)]
+        save: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: TreeFormat,
+    },
+
+    /// List stored tree snapshots
+    Snapshots {
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+
+    /// Show a stored tree snapshot
+    Snapshot {
+        /// Snapshot id
+        id: i64,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: TreeFormat,
+    },
+
+    /// Delete a stored tree snapshot
+    DeleteSnapshot {
+        /// Snapshot id
+        id: i64,
+    },
 }
 
 #[derive(Subcommand)]
@@ -237,11 +675,12 @@ async fn main() -> Result<()> {
         Commands::Status => {
             // Try to connect to daemon
             match client
-                .send_request(&json!({"type": "ListDesktops"}).to_string())
+                .send_request(&json!({"type": "Health"}).to_string())
                 .await
             {
-                Ok(_) => {
+                Ok(response) => {
                     println!("Daemon is running");
+                    print_recovery_warning(&response);
                     Ok(())
                 }
                 Err(e) => {
@@ -255,6 +694,69 @@ async fn main() -> Result<()> {
         Commands::Clipboard(cmd) => handle_clipboard_command(&client, cmd).await,
         Commands::Workflow(cmd) => handle_workflow_command(&client, cmd).await,
         Commands::Query(cmd) => handle_query_command(&client, cmd).await,
+        Commands::Db(cmd) => handle_db_command(&client, cmd).await,
+        Commands::Config(cmd) => handle_config_command(&client, cmd).await,
+        Commands::Export {
+            tables,
+            format,
+            since,
+            until,
+            types,
+            output,
+        } => {
+            let request = json!({"type": "ExportData", "data": {
+                "tables": (!tables.is_empty()).then_some(tables),
+                "format": format,
+                "since": since,
+                "until": until,
+                "types": (!types.is_empty()).then_some(types),
+            }});
+            handle_export(&client, request, output).await
+        }
+        Commands::Import {
+            file,
+            format,
+            table,
+        } => handle_import(&client, &file, format, table).await,
+        Commands::Search {
+            query,
+            sources,
+            since,
+            until,
+            limit,
+            format,
+        } => {
+            let request = json!({"type": "Search", "data": {
+                "query": query.join(" "),
+                "sources": (!sources.is_empty()).then_some(sources),
+                "since": since,
+                "until": until,
+                "limit": limit,
+            }});
+            let response = client.send_request(&request.to_string()).await?;
+            output::print_records(
+                &response,
+                "results",
+                &["source", "timestamp", "session_id", "task_id", "snippet"],
+                format,
+            )
+        }
+        Commands::Stats {
+            window,
+            since,
+            until,
+            group_by,
+            format,
+        } => {
+            let request = json!({"type": "GetStats", "data": {
+                "window": window,
+                "since": since,
+                "until": until,
+                "group_by": (!group_by.is_empty()).then_some(&group_by),
+            }});
+            let response = client.send_request(&request.to_string()).await?;
+            output::print_stats(&response, &group_by, format)
+        }
         Commands::Element(cmd) => handle_element_command(&client, cmd).await,
         Commands::Keyboard(cmd) => handle_keyboard_command(&client, cmd).await,
         Commands::Mouse(cmd) => handle_mouse_command(&client, cmd).await,
@@ -263,6 +765,30 @@ async fn main() -> Result<()> {
     }
 }
 
+/// Surface a database recovery reported by the daemon's health check
+fn print_recovery_warning(response: &str) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(response) else {
+        return;
+    };
+    let recovery = &value["data"]["data"]["database_recovery"];
+    if recovery.is_null() {
+        return;
+    }
+
+    println!(
+        "WARNING: database was corrupt at startup ({})",
+        recovery["problem"].as_str().unwrap_or("unknown problem")
+    );
+    println!(
+        "  corrupt file moved to: {}",
+        recovery["quarantined_path"].as_str().unwrap_or("?")
+    );
+    match recovery["restored_from"].as_str() {
+        Some(backup) => println!("  restored from backup: {}", backup),
+        None => println!("  no valid backup found, database was recreated empty"),
+    }
+}
+
 async fn handle_input_command(client: &Client, cmd: InputCommands) -> Result<()> {
     let request = match cmd {
         InputCommands::Type { text } => json!({"type": "Type", "data": {"text": text}}),
@@ -315,6 +841,20 @@ async fn handle_query_command(client: &Client, cmd: QueryCommands) -> Result<()>
         QueryCommands::History { limit } => {
             json!({"type": "GetTaskHistory", "data": {"limit": limit}})
         }
+        QueryCommands::Sql {
+            sql,
+            format,
+            limit,
+            timeout_ms,
+        } => {
+            let request = json!({"type": "DbQuery", "data": {
+                "sql": sql,
+                "max_rows": limit,
+                "timeout_ms": timeout_ms,
+            }});
+            let response = client.send_request(&request.to_string()).await?;
+            return output::print_rows(&response, format);
+        }
     };
 
     let response = client.send_request(&request.to_string()).await?;
@@ -322,33 +862,263 @@ async fn handle_query_command(client: &Client, cmd: QueryCommands) -> Result<()>
     Ok(())
 }
 
-async fn handle_element_command(client: &Client, cmd: ElementCommands) -> Result<()> {
+async fn handle_config_command(client: &Client, cmd: ConfigCommands) -> Result<()> {
+    let request = match &cmd {
+        ConfigCommands::Get { key: None, .. } => json!({"type": "ListPreferences"}),
+        ConfigCommands::Get { key: Some(key), .. } => {
+            json!({"type": "GetPreference", "data": {"key": key}})
+        }
+        ConfigCommands::Set { key, value } => {
+            // Numbers and booleans go through as JSON; anything else as text
+            let value =
+                serde_json::from_str::<serde_json::Value>(value).unwrap_or_else(|_| json!(value));
+            json!({"type": "SetPreference", "data": {"key": key, "value": value}})
+        }
+        ConfigCommands::Unset { key } => {
+            json!({"type": "SetPreference", "data": {"key": key, "value": null}})
+        }
+        ConfigCommands::Show { .. } => json!({"type": "GetConfig"}),
+        ConfigCommands::Reload { .. } => json!({"type": "ReloadConfig"}),
+        ConfigCommands::Profile {
+            app_name,
+            window_class,
+            app_id,
+            ..
+        } => json!({
+            "type": "GetProfile",
+            "data": {"app_name": app_name, "window_class": window_class, "app_id": app_id}
+        }),
+        ConfigCommands::LogLevel { level } => {
+            json!({"type": "SetLogLevel", "data": {"level": level}})
+        }
+    };
+    let response = client.send_request(&request.to_string()).await?;
+
+    match cmd {
+        ConfigCommands::Get { key: None, format } => output::print_records(
+            &response,
+            "preferences",
+            &["key", "value", "source", "type", "description"],
+            format,
+        ),
+        ConfigCommands::Get {
+            key: Some(_),
+            format,
+        } => output::print_value(&response, "preference", "value", format),
+        ConfigCommands::Set { .. } | ConfigCommands::Unset { .. } => {
+            output::print_value(&response, "preference", "value", OutputFormat::Table)
+        }
+        ConfigCommands::Show { origin, format } => {
+            let columns: &[&str] = if origin {
+                &["key", "value", "origin"]
+            } else {
+                &["key", "value"]
+            };
+            output::print_records(&response, "config", columns, format)
+        }
+        ConfigCommands::Reload { format } => output::print_reload(&response, format),
+        ConfigCommands::Profile { format, .. } => output::print_profile(&response, format),
+        ConfigCommands::LogLevel { .. } => {
+            output::print_value(&response, "log_level", "current", OutputFormat::Table)
+        }
+    }
+}
+
+async fn handle_db_command(client: &Client, cmd: DbCommands) -> Result<()> {
     let request = match cmd {
-        ElementCommands::Find { name, role } => {
-            json!({"type": "FindElement", "data": {"name": name, "role": role}})
+        DbCommands::Backup { list: true, .. } => json!({"type": "DbListBackups"}),
+        DbCommands::Backup { path, .. } => {
+            // The daemon resolves paths relative to its own working directory
+            let path = path.map(|p| absolute_path(&p)).transpose()?;
+            json!({"type": "DbBackup", "data": {"path": path}})
         }
-        ElementCommands::Click { name, button } => {
-            json!({"type": "ClickElement", "data": {"name": name, "button": Some(button)}})
+        DbCommands::Restore { file } => {
+            json!({"type": "DbRestore", "data": {"path": absolute_path(&file)?}})
         }
-        ElementCommands::DoubleClick { name } => {
-            json!({"type": "DoubleClickElement", "data": {"name": name}})
+        DbCommands::Rekey => json!({"type": "DbRekey"}),
+    };
+
+    let response = client.send_request(&request.to_string()).await?;
+    println!("{}", response);
+    Ok(())
+}
+
+async fn handle_export(
+    client: &Client,
+    request: serde_json::Value,
+    output: Option<String>,
+) -> Result<()> {
+    let response = client.send_request(&request.to_string()).await?;
+    let value: serde_json::Value = serde_json::from_str(&response)?;
+    if value["type"] == "Error" {
+        anyhow::bail!("{}", value["data"]["error"].as_str().unwrap_or(&response));
+    }
+
+    let export = &value["data"]["data"];
+    let content = export["content"].as_str().unwrap_or_default();
+    match output {
+        Some(path) => std::fs::write(&path, content)?,
+        None => print!("{}", content),
+    }
+
+    if let Some(counts) = export["counts"].as_object() {
+        for (table, count) in counts {
+            eprintln!("Exported {} {} records", count, table);
         }
-        ElementCommands::Type {
+    }
+    Ok(())
+}
+
+async fn handle_import(
+    client: &Client,
+    file: &str,
+    format: Option<String>,
+    table: Option<String>,
+) -> Result<()> {
+    let content = std::fs::read_to_string(file)?;
+    let format = format.unwrap_or_else(|| {
+        if file.ends_with(".csv") {
+            "csv".to_string()
+        } else {
+            "jsonl".to_string()
+        }
+    });
+
+    let request = json!({"type": "ImportData", "data": {
+        "content": content,
+        "format": format,
+        "table": table,
+    }});
+    let response = client.send_request(&request.to_string()).await?;
+    println!("{}", response);
+    Ok(())
+}
+
+/// Resolve a user-supplied path against the current directory
+fn absolute_path(path: &str) -> Result<String> {
+    Ok(std::path::absolute(path)?.display().to_string())
+}
+
+async fn handle_element_command(client: &Client, cmd: ElementCommands) -> Result<()> {
+    let request = match &cmd {
+        ElementCommands::Find {
             name,
+            role,
+            states,
+            app,
+            selector,
+            fuzzy,
+            max_depth,
+        } => {
+            let states = (!states.is_empty()).then_some(states);
+            json!({"type": "FindElement", "data": {
+                "name": name,
+                "role": role,
+                "states": states,
+                "app": app,
+                "selector": selector,
+                "fuzzy": fuzzy.then_some(true),
+                "max_depth": max_depth,
+            }})
+        }
+        ElementCommands::Click { target, button } => {
+            json!({"type": "ClickElement", "data": target.to_json(json!({"button": Some(button)}))})
+        }
+        ElementCommands::DoubleClick { target } => {
+            json!({"type": "DoubleClickElement", "data": target.to_json(json!({}))})
+        }
+        ElementCommands::Type {
+            target,
             text,
             secure,
+            strategy,
         } => {
-            json!({"type": "TypeIntoElement", "data": {"name": name, "text": text, "secure": Some(secure)}})
+            json!({"type": "TypeIntoElement", "data": target.to_json(json!({"text": text, "secure": Some(secure), "strategy": strategy}))})
         }
-        ElementCommands::Focus { name } => {
-            json!({"type": "FocusElement", "data": {"name": name}})
+        ElementCommands::Focus { target } => {
+            json!({"type": "FocusElement", "data": target.to_json(json!({}))})
         }
         ElementCommands::GetFocused => json!({"type": "GetFocusedElement"}),
+        ElementCommands::WaitFocus { target, timeout } => {
+            json!({"type": "WaitForFocus", "data": target.to_json(json!({"timeout_ms": timeout}))})
+        }
+        ElementCommands::Actions { target, .. } => {
+            json!({"type": "ListActions", "data": target.to_json(json!({}))})
+        }
+        ElementCommands::DoAction { target, action } => {
+            json!({"type": "DoAction", "data": target.to_json(json!({"action": action}))})
+        }
+        ElementCommands::Text { target, start, end } => {
+            json!({"type": "GetText", "data": target.to_json(json!({"start": start, "end": end}))})
+        }
+        ElementCommands::Caret { target } => {
+            json!({"type": "GetCaret", "data": target.to_json(json!({}))})
+        }
+        ElementCommands::SetCaret { target, offset } => {
+            json!({"type": "SetCaret", "data": target.to_json(json!({"offset": offset}))})
+        }
+        ElementCommands::Select { target, start, end } => {
+            json!({"type": "SetSelection", "data": target.to_json(json!({"start": start, "end": end}))})
+        }
+        ElementCommands::Insert { target, text, at } => {
+            json!({"type": "InsertText", "data": target.to_json(json!({"text": text, "position": at}))})
+        }
+        ElementCommands::DeleteText { target, start, end } => {
+            json!({"type": "DeleteText", "data": target.to_json(json!({"start": start, "end": end}))})
+        }
+        ElementCommands::Replace {
+            target,
+            text,
+            start,
+            end,
+        } => {
+            json!({"type": "ReplaceText", "data": target.to_json(json!({"text": text, "start": start, "end": end}))})
+        }
+        ElementCommands::Tree {
+            app,
+            window,
+            depth,
+            filter,
+            save,
+            ..
+        } => json!({"type": "GetTree", "data": {
+            "app": app,
+            "window": window,
+            "max_depth": depth,
+            "filter": filter,
+            "snapshot": save,
+        }}),
+        ElementCommands::Snapshots { .. } => json!({"type": "ListTreeSnapshots"}),
+        ElementCommands::Snapshot { id, .. } => {
+            json!({"type": "GetTreeSnapshot", "data": {"id": id}})
+        }
+        ElementCommands::DeleteSnapshot { id } => {
+            json!({"type": "DeleteTreeSnapshot", "data": {"id": id}})
+        }
     };
 
     let response = client.send_request(&request.to_string()).await?;
-    println!("{}", response);
-    Ok(())
+    match cmd {
+        ElementCommands::Tree { format, .. } | ElementCommands::Snapshot { format, .. } => {
+            output::print_tree(&response, format)
+        }
+        ElementCommands::Actions { format, .. } => output::print_records(
+            &response,
+            "actions",
+            &["name", "localized_name", "description", "keybinding"],
+            format,
+        ),
+        ElementCommands::Snapshots { format } => output::print_records(
+            &response,
+            "snapshots",
+            &["id", "name", "scope", "node_count", "taken_at"],
+            format,
+        ),
+        _ => {
+            println!("{}", response);
+            Ok(())
+        }
+    }
 }
 
 async fn handle_keyboard_command(client: &Client, cmd: KeyboardCommands) -> Result<()> {
@@ -0,0 +1,437 @@
+// Output formatting for tabular daemon responses
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// Output format for query results
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Print a daemon response carrying `columns`/`rows` in the chosen format.
+/// Error responses are returned as errors so the process exits non-zero.
+pub fn print_rows(response: &str, format: OutputFormat) -> Result<()> {
+    let data = response_data(response)?;
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(&data)?);
+        return Ok(());
+    }
+
+    let columns: Vec<String> = data["columns"]
+        .as_array()
+        .map(|cols| cols.iter().map(cell_text).collect())
+        .unwrap_or_default();
+    let rows: Vec<Vec<String>> = data["rows"]
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .map(|row| {
+                    row.as_array()
+                        .map(|cells| cells.iter().map(cell_text).collect())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    print_tabular(&columns, &rows, format);
+
+    if data["truncated"].as_bool().unwrap_or(false) {
+        eprintln!("(output truncated at {} rows)", rows.len());
+    }
+
+    Ok(())
+}
+
+/// Print the list of objects under `key` in a daemon response, one row per
+/// object with the given fields as columns
+pub fn print_records(
+    response: &str,
+    key: &str,
+    fields: &[&str],
+    format: OutputFormat,
+) -> Result<()> {
+    let data = response_data(response)?;
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(&data[key])?);
+        return Ok(());
+    }
+
+    let columns: Vec<String> = fields.iter().map(|f| f.to_string()).collect();
+    let rows: Vec<Vec<String>> = data[key]
+        .as_array()
+        .map(|records| {
+            records
+                .iter()
+                .map(|record| fields.iter().map(|f| cell_text(&record[*f])).collect())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    print_tabular(&columns, &rows, format);
+    Ok(())
+}
+
+/// Print a single field of the object under `key`; JSON output prints the
+/// whole object
+pub fn print_value(response: &str, key: &str, field: &str, format: OutputFormat) -> Result<()> {
+    let data = response_data(response)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&data[key])?),
+        OutputFormat::Table | OutputFormat::Csv => println!("{}", cell_text(&data[key][field])),
+    }
+    Ok(())
+}
+
+/// Print a `GetStats` report, one row per operation (and grouping)
+pub fn print_stats(response: &str, group_by: &[String], format: OutputFormat) -> Result<()> {
+    let data = response_data(response)?;
+    let stats = &data["stats"];
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(stats)?);
+        return Ok(());
+    }
+
+    let groups: Vec<&str> = ["planned_input_method", "compositor"]
+        .into_iter()
+        .filter(|group| group_by.iter().any(|g| g == group))
+        .collect();
+    let latency = ["p50_ms", "p95_ms", "p99_ms", "max_ms"];
+
+    let mut columns = vec!["operation".to_string()];
+    columns.extend(groups.iter().map(|g| g.to_string()));
+    columns.extend(["count", "failed", "success"].map(String::from));
+    columns.extend(latency.map(String::from));
+
+    let rows: Vec<Vec<String>> = stats["operations"]
+        .as_array()
+        .map(|operations| {
+            operations
+                .iter()
+                .map(|op| {
+                    let mut row = vec![cell_text(&op["operation"])];
+                    row.extend(groups.iter().map(|g| cell_text(&op[*g])));
+                    row.push(cell_text(&op["count"]));
+                    row.push(cell_text(&op["failed"]));
+                    row.push(format!(
+                        "{:.1}%",
+                        op["success_rate"].as_f64().unwrap_or_default() * 100.0
+                    ));
+                    row.extend(latency.iter().map(|field| {
+                        op[*field]
+                            .as_f64()
+                            .map_or("-".to_string(), |ms| format!("{:.1}", ms))
+                    }));
+                    row
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if let OutputFormat::Table = format {
+        println!(
+            "{} operations since {}{}",
+            stats["total"],
+            cell_text(&stats["since"]),
+            stats["until"]
+                .as_str()
+                .map(|until| format!(" until {}", until))
+                .unwrap_or_default()
+        );
+    }
+    print_tabular(&columns, &rows, format);
+    Ok(())
+}
+
+/// Print a `ReloadConfig` report: what changed and what still needs a restart
+pub fn print_reload(response: &str, format: OutputFormat) -> Result<()> {
+    let data = response_data(response)?;
+    let report = &data["reload"];
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(report)?);
+        return Ok(());
+    }
+
+    let files: Vec<String> = report["files"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(cell_text)
+        .collect();
+    if files.is_empty() {
+        println!("No config file found; using defaults");
+    } else {
+        println!("Reloaded {}", files.join(", "));
+    }
+    for warning in report["warnings"].as_array().into_iter().flatten() {
+        println!("warning: {}", cell_text(warning));
+    }
+
+    let sections = [
+        ("applied", "Applied"),
+        (
+            "overridden",
+            "Overridden by a preference (see `deskctl config get`)",
+        ),
+        ("restart_required", "Restart deskd to apply"),
+    ];
+    let mut changed = false;
+    for (field, title) in sections {
+        let keys: Vec<String> = report[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(cell_text)
+            .collect();
+        if !keys.is_empty() {
+            println!("{}: {}", title, keys.join(", "));
+            changed = true;
+        }
+    }
+    if !changed {
+        println!("No changes");
+    }
+    Ok(())
+}
+
+/// Print a `GetProfile` result: the application, its profile and the
+/// settings requests to it use
+pub fn print_profile(response: &str, format: OutputFormat) -> Result<()> {
+    let data = response_data(response)?;
+    if let OutputFormat::Json = format {
+        println!("{}", serde_json::to_string_pretty(&data)?);
+        return Ok(());
+    }
+
+    let app: Vec<String> = data["app"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| format!("{}={}", key, cell_text(value)))
+        .collect();
+    if app.is_empty() {
+        println!("Application: unknown (no focused window reported)");
+    } else {
+        println!("Application: {}", app.join(", "));
+    }
+
+    let settings = &data["settings"];
+    match settings["profile"].as_str() {
+        Some(profile) => println!("Profile: {}", profile),
+        None => println!("Profile: none (global settings)"),
+    }
+    let timing = &settings["input_timing"];
+    let atspi = &settings["atspi"];
+    let rows = [
+        ("typing_delay_ms", &timing["typing_delay_ms"]),
+        ("click_delay_ms", &timing["click_delay_ms"]),
+        ("focus_timeout_ms", &timing["focus_timeout_ms"]),
+        ("input_methods", &settings["method_order"]),
+        ("typing_strategy", &settings["typing_strategy"]),
+        ("enable_cache", &atspi["enable_cache"]),
+        (
+            "cache_expiration_seconds",
+            &atspi["cache_expiration_seconds"],
+        ),
+    ];
+    for (key, value) in rows {
+        println!("  {:<26}{}", key, cell_text(value));
+    }
+    Ok(())
+}
+
+/// Output format for accessibility tree dumps
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TreeFormat {
+    Text,
+    Json,
+}
+
+/// Print a `GetTree` result, or the stored snapshot under `snapshot` in a
+/// `GetTreeSnapshot` result. Text output is one object per line, indented
+/// by depth, with roles and states spelled as selectors spell them.
+pub fn print_tree(response: &str, format: TreeFormat) -> Result<()> {
+    let data = response_data(response)?;
+    let snapshot = &data["snapshot"];
+    if let TreeFormat::Json = format {
+        let shown = if snapshot.is_null() { &data } else { snapshot };
+        println!("{}", serde_json::to_string_pretty(shown)?);
+        return Ok(());
+    }
+
+    if snapshot.is_null() {
+        print_tree_nodes(&data["tree"], 0);
+        println!("({} objects)", cell_text(&data["nodes"]));
+        if data["incomplete"]
+            .as_array()
+            .is_some_and(|apps| !apps.is_empty())
+        {
+            println!(
+                "Incomplete: {} did not answer in time",
+                cell_text(&data["incomplete"])
+            );
+        }
+        if let Some(id) = data["snapshot_id"].as_i64() {
+            println!("Saved as snapshot {}", id);
+        }
+    } else {
+        println!(
+            "Snapshot {} \"{}\" of {}, taken {}",
+            cell_text(&snapshot["id"]),
+            cell_text(&snapshot["name"]),
+            cell_text(&snapshot["scope"]),
+            cell_text(&snapshot["taken_at"])
+        );
+        print_tree_nodes(&snapshot["tree"], 0);
+        println!("({} objects)", cell_text(&snapshot["node_count"]));
+    }
+    Ok(())
+}
+
+fn print_tree_nodes(nodes: &Value, depth: usize) {
+    for node in nodes.as_array().into_iter().flatten() {
+        let mut line = format!(
+            "{}{} {:?}",
+            "  ".repeat(depth),
+            kebab_case(node["role"].as_str().unwrap_or_default()),
+            node["name"].as_str().unwrap_or_default()
+        );
+        if let Some(description) = node["description"].as_str() {
+            line.push_str(&format!(" description={:?}", description));
+        }
+        let states: Vec<String> = node["states"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(kebab_case)
+            .collect();
+        if !states.is_empty() {
+            line.push_str(&format!(" [{}]", states.join(",")));
+        }
+        let bounds = &node["bounds"];
+        if bounds.is_object() {
+            line.push_str(&format!(
+                " @{},{} {}x{}",
+                bounds["x"], bounds["y"], bounds["width"], bounds["height"]
+            ));
+        }
+        for key in ["actions", "interfaces"] {
+            if node[key].as_array().is_some_and(|items| !items.is_empty()) {
+                line.push_str(&format!(" {}={}", key, cell_text(&node[key])));
+            }
+        }
+        println!("{}", line);
+        print_tree_nodes(&node["children"], depth + 1);
+    }
+}
+
+/// `PushButton` -> `push-button`
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+    }
+    kebab
+}
+
+/// The `data` payload of a daemon response; error responses become errors
+fn response_data(response: &str) -> Result<Value> {
+    let mut value: Value =
+        serde_json::from_str(response).context("Invalid response from daemon")?;
+
+    if value["type"] == "Error" {
+        anyhow::bail!(
+            "{}",
+            value["data"]["error"].as_str().unwrap_or("unknown error")
+        );
+    }
+
+    Ok(value["data"]["data"].take())
+}
+
+fn print_tabular(columns: &[String], rows: &[Vec<String>], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => print_table(columns, rows),
+        OutputFormat::Csv => print_csv(columns, rows),
+        OutputFormat::Json => unreachable!(),
+    }
+}
+
+/// Render a single JSON value as plain text
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) if items.iter().all(Value::is_string) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(","),
+        Value::Object(map) if map.contains_key("base64") => {
+            map["base64"].as_str().unwrap_or_default().to_string()
+        }
+        other => other.to_string(),
+    }
+}
+
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.chars().count()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if let Some(width) = widths.get_mut(i) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+    }
+
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+
+    println!("{}", line(columns));
+    println!(
+        "{}",
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-")
+    );
+    for row in rows {
+        println!("{}", line(row));
+    }
+    println!("({} rows)", rows.len());
+}
+
+fn print_csv(columns: &[String], rows: &[Vec<String>]) {
+    println!("{}", csv_line(columns));
+    for row in rows {
+        println!("{}", csv_line(row));
+    }
+}
+
+fn csv_line(cells: &[String]) -> String {
+    cells
+        .iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,236 @@
+// Element actions via the AT-SPI Action interface
+//
+// Widgets name what they can do: "click", "press", "activate", "expand or
+// contract", and whatever else their toolkit defines. Invoking an action goes
+// through the application, so it works on widgets that are covered by other
+// windows or scrolled out of view, where a synthesized click would land on
+// the wrong thing.
+use super::element::{self, Element};
+use anyhow::{bail, Result};
+use atspi::connection::AccessibilityConnection;
+use atspi::proxy::action::ActionProxy;
+use atspi::Interface;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Action names that mean a click on any element
+const CLICK_ACTIONS: &[&str] = &["click", "press"];
+/// Roles whose activating action is a click too. On entries, links and menu
+/// items "activate" does something else: submitting, following, opening.
+const BUTTON_ROLES: &[&str] = &["PushButton", "ToggleButton", "CheckBox", "RadioButton"];
+const BUTTON_ACTIONS: &[&str] = &["activate", "toggle"];
+
+/// One action an element offers; the first is its default action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementAction {
+    /// Machine-readable name, e.g. "click"
+    pub name: String,
+    /// Name in the desktop's language, as screen readers announce it
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub localized_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// "mnemonic;sequence;shortcut", e.g. "N;Alt+F:N;Ctrl+N"
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub keybinding: String,
+}
+
+/// The object's actions, in the order the application lists them. Objects
+/// without the Action interface have none.
+pub async fn actions(
+    connection: &AccessibilityConnection,
+    bus_name: &str,
+    path: &str,
+) -> Result<Vec<ElementAction>> {
+    if !element::interfaces(connection, bus_name, path)
+        .await?
+        .contains(Interface::Action)
+    {
+        return Ok(Vec::new());
+    }
+    listed(connection, bus_name, path).await
+}
+
+/// Actions of an object known to have the Action interface
+pub(super) async fn listed(
+    connection: &AccessibilityConnection,
+    bus_name: &str,
+    path: &str,
+) -> Result<Vec<ElementAction>> {
+    let proxy = action_proxy(connection, bus_name, path).await?;
+    let mut actions = Vec::new();
+    for (index, (localized_name, description, keybinding)) in
+        proxy.get_actions().await?.into_iter().enumerate()
+    {
+        let name = proxy.get_name(index as i32).await?;
+        actions.push(ElementAction {
+            localized_name: match localized_name == name {
+                true => String::new(),
+                false => localized_name,
+            },
+            name,
+            description,
+            keybinding,
+        });
+    }
+    Ok(actions)
+}
+
+/// Invoke the element's action with this name, returning the name the
+/// element gives it
+pub async fn do_action(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    action: &str,
+) -> Result<String> {
+    let actions = actions(connection, &element.bus_name, &element.path).await?;
+    let Some(index) = action_index(&actions, action) else {
+        match actions.is_empty() {
+            true => bail!("{} has no actions", element.name),
+            false => bail!(
+                "{} has no action {:?}; it has {}",
+                element.name,
+                action,
+                names(&actions)
+            ),
+        }
+    };
+    invoke(connection, element, index, &actions[index].name).await?;
+    Ok(actions[index].name.clone())
+}
+
+/// The element's action that clicks it, as its index and name. The default
+/// action is not assumed to be one.
+pub async fn click_action(
+    connection: &AccessibilityConnection,
+    element: &Element,
+) -> Result<Option<(usize, String)>> {
+    let actions = actions(connection, &element.bus_name, &element.path).await?;
+    Ok(click_action_index(&element.role, &actions)
+        .map(|index| (index, actions[index].name.clone())))
+}
+
+/// Invoke the action at `index`, failing when the element refuses it
+pub(super) async fn invoke(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    index: usize,
+    name: &str,
+) -> Result<()> {
+    info!(
+        "Invoking action {:?} on {} ({})",
+        name, element.name, element.path
+    );
+    let proxy = action_proxy(connection, &element.bus_name, &element.path).await?;
+    if !proxy.do_action(index as i32).await? {
+        bail!("{} refused action {:?}", element.name, name);
+    }
+    Ok(())
+}
+
+async fn action_proxy<'a>(
+    connection: &'a AccessibilityConnection,
+    bus_name: &'a str,
+    path: &'a str,
+) -> Result<ActionProxy<'a>> {
+    Ok(ActionProxy::builder(connection.connection())
+        .destination(bus_name)?
+        .path(path)?
+        .build()
+        .await?)
+}
+
+/// Index of the named action. Names match ignoring case, and spaces, dashes
+/// and underscores are interchangeable ("expand-or-contract"); the localized
+/// name is tried after the machine-readable one.
+fn action_index(actions: &[ElementAction], wanted: &str) -> Option<usize> {
+    let wanted = normalize(wanted);
+    actions
+        .iter()
+        .position(|action| normalize(&action.name) == wanted)
+        .or_else(|| {
+            actions.iter().position(|action| {
+                !action.localized_name.is_empty() && normalize(&action.localized_name) == wanted
+            })
+        })
+}
+
+/// Index of the action a click on an element with this role performs
+fn click_action_index(role: &str, actions: &[ElementAction]) -> Option<usize> {
+    let button_actions = match BUTTON_ROLES.contains(&role) {
+        true => BUTTON_ACTIONS,
+        false => &[],
+    };
+    CLICK_ACTIONS.iter().chain(button_actions).find_map(|name| {
+        actions
+            .iter()
+            .position(|action| normalize(&action.name) == *name)
+    })
+}
+
+fn normalize(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| match c {
+            '-' | '_' => ' ',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+fn names(actions: &[ElementAction]) -> String {
+    actions
+        .iter()
+        .map(|action| format!("{:?}", action.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(name: &str, localized_name: &str) -> ElementAction {
+        ElementAction {
+            name: name.to_string(),
+            localized_name: localized_name.to_string(),
+            description: String::new(),
+            keybinding: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_action_index() {
+        let actions = [
+            action("activate", "Aktivieren"),
+            action("expand or contract", "Auf- oder zuklappen"),
+            action("customize", ""),
+        ];
+        assert_eq!(action_index(&actions, "activate"), Some(0));
+        assert_eq!(action_index(&actions, "Expand-Or-Contract"), Some(1));
+        assert_eq!(action_index(&actions, "expand_or_contract"), Some(1));
+        assert_eq!(action_index(&actions, "aktivieren"), Some(0));
+        assert_eq!(action_index(&actions, " customize "), Some(2));
+        assert_eq!(action_index(&actions, "click"), None);
+        assert_eq!(action_index(&actions, ""), None);
+        assert_eq!(names(&actions[..2]), r#""activate", "expand or contract""#);
+    }
+
+    #[test]
+    fn test_click_action_index() {
+        let entry = [action("activate", ""), action("click", "")];
+        assert_eq!(click_action_index("Entry", &entry), Some(1));
+        assert_eq!(click_action_index("Entry", &entry[..1]), None);
+        assert_eq!(click_action_index("Link", &[action("jump", "")]), None);
+        assert_eq!(click_action_index("MenuItem", &entry[..1]), None);
+
+        assert_eq!(click_action_index("PushButton", &entry[..1]), Some(0));
+        assert_eq!(
+            click_action_index("PushButton", &[action("Press", "")]),
+            Some(0)
+        );
+        let check_box = [action("activate", ""), action("toggle", "")];
+        assert_eq!(click_action_index("CheckBox", &check_box), Some(0));
+        assert_eq!(click_action_index("CheckBox", &[]), None);
+    }
+}
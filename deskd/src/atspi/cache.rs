@@ -1,21 +1,82 @@
 // Element caching with TTL
-use super::element::Element;
+//
+// Entries also go as soon as an accessibility event shows they changed (see
+// events.rs); the TTL bounds how long a missed event can go unnoticed.
+use super::element::{self, Element};
+use crate::db::async_db::AsyncDatabase;
+use atspi::connection::AccessibilityConnection;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 /// Element cache with time-to-live expiration
 #[allow(dead_code)]
 pub struct ElementCache {
     cache: RwLock<HashMap<String, CachedElement>>,
     ttl: Duration,
+    store: Option<PersistentStore>,
+    counters: CacheCounters,
 }
 
 #[allow(dead_code)]
 struct CachedElement {
     element: Element,
-    cached_at: Instant,
+    expires_at: Instant,
+    /// Object paths of the element's ancestors, root first; `None` when unknown
+    ancestors: Option<Vec<String>>,
+}
+
+/// Cached elements an accessibility event made stale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalidation {
+    pub bus_name: String,
+    pub path: String,
+    pub scope: InvalidationScope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidationScope {
+    /// The object's own role, states or description changed
+    Object,
+    /// Objects were added, removed or renamed. Any cached lookup may now
+    /// match another element as well, wherever it is, so all of them go.
+    Lookups,
+    /// The object and everything below it moved, were hidden or destroyed
+    Subtree,
+}
+
+impl Invalidation {
+    /// Whether the event affects a cached element. Without a known ancestry
+    /// any change below an object of the element's application does.
+    pub fn affects(&self, element: &Element, ancestors: Option<&[String]>) -> bool {
+        // Other applications' objects share paths but not bus names
+        let in_app = element.bus_name == self.bus_name;
+        let is_object = in_app && element.path == self.path;
+        let is_below =
+            || in_app && ancestors.is_none_or(|ancestors| ancestors.contains(&self.path));
+        match self.scope {
+            InvalidationScope::Object => is_object,
+            InvalidationScope::Lookups => true,
+            InvalidationScope::Subtree => is_object || is_below(),
+        }
+    }
+}
+
+/// Write-through backing store in the `element_cache` table
+struct PersistentStore {
+    database: AsyncDatabase,
+    desktop_id: String,
+}
+
+#[derive(Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+    invalidated: AtomicU64,
 }
 
 #[allow(dead_code)]
@@ -25,53 +86,196 @@ impl ElementCache {
         Self {
             cache: RwLock::new(HashMap::new()),
             ttl: Duration::from_secs(ttl_seconds),
+            store: None,
+            counters: CacheCounters::default(),
+        }
+    }
+
+    /// Create an element cache that persists entries for one desktop in the database
+    pub fn persistent(ttl_seconds: u64, database: AsyncDatabase, desktop_id: &str) -> Self {
+        Self {
+            store: Some(PersistentStore {
+                database,
+                desktop_id: desktop_id.to_string(),
+            }),
+            ..Self::new(ttl_seconds)
         }
     }
 
     /// Get an element from cache if not expired
     pub async fn get(&self, key: &str) -> Option<Element> {
-        let cache = self.cache.read().await;
-
-        if let Some(cached) = cache.get(key) {
-            if cached.cached_at.elapsed() < self.ttl {
-                debug!("Cache hit for: {}", key);
-                return Some(cached.element.clone());
-            } else {
-                debug!("Cache expired for: {}", key);
+        let cached = {
+            let cache = self.cache.read().await;
+
+            match cache.get(key) {
+                Some(cached) if cached.expires_at > Instant::now() => Some(cached.element.clone()),
+                Some(_) => {
+                    debug!("Cache expired for: {}", key);
+                    None
+                }
+                None => None,
             }
+        };
+
+        if let Some(element) = cached {
+            debug!("Cache hit for: {}", key);
+            self.record_hit(key).await;
+            return Some(element);
+        }
+
+        if let Some(element) = self.load_persisted(key).await {
+            debug!("Cache hit (persisted) for: {}", key);
+            self.record_hit(key).await;
+            return Some(element);
+        }
+
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Get an element and confirm it still exists on the accessibility bus.
+    /// Stale entries are evicted so a reused object path can't cause a wrong click.
+    pub async fn get_verified(
+        &self,
+        key: &str,
+        connection: &AccessibilityConnection,
+    ) -> Option<Element> {
+        let element = self.get(key).await?;
+
+        if element::is_live(connection, &element).await {
+            return Some(element);
         }
 
+        debug!("Cached element is stale: {}", key);
+        self.counters.stale.fetch_add(1, Ordering::Relaxed);
+        self.invalidate(key).await;
         None
     }
 
     /// Store an element in the cache
     pub async fn set(&self, key: String, element: Element) {
+        self.insert(key, element, None, self.ttl).await;
+    }
+
+    /// Store an element with the object paths of its ancestors, root first,
+    /// so changes elsewhere in its application leave it cached
+    pub async fn set_in_tree(
+        &self,
+        key: String,
+        element: Element,
+        ancestors: Vec<String>,
+        ttl: Duration,
+    ) {
+        self.insert(key, element, Some(ancestors), ttl).await;
+    }
+
+    async fn insert(
+        &self,
+        key: String,
+        element: Element,
+        ancestors: Option<Vec<String>>,
+        ttl: Duration,
+    ) {
         debug!("Caching element: {}", key);
 
+        if let Some(store) = &self.store {
+            let expires_at = chrono::Utc::now()
+                + chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::zero());
+            if let Err(e) = store
+                .database
+                .store_cached_element(
+                    store.desktop_id.clone(),
+                    key.clone(),
+                    element.clone(),
+                    ancestors.clone(),
+                    expires_at,
+                )
+                .await
+            {
+                warn!("Failed to persist cached element {}: {:#}", key, e);
+            }
+        }
+
         let mut cache = self.cache.write().await;
         cache.insert(
             key,
             CachedElement {
                 element,
-                cached_at: Instant::now(),
+                expires_at: Instant::now() + ttl,
+                ancestors,
             },
         );
     }
 
+    /// Drop the entries an accessibility event made stale, returning how
+    /// many were cached in memory
+    pub async fn apply(&self, invalidation: &Invalidation) -> usize {
+        if let Some(store) = &self.store {
+            match store
+                .database
+                .invalidate_cached_elements(store.desktop_id.clone(), invalidation.clone())
+                .await
+            {
+                Ok(0) => {}
+                Ok(removed) => debug!("Removed {} persisted elements", removed),
+                Err(e) => warn!("Failed to invalidate persisted elements: {:#}", e),
+            }
+        }
+
+        let mut cache = self.cache.write().await;
+        let before_count = cache.len();
+        cache.retain(|_, cached| {
+            !invalidation.affects(&cached.element, cached.ancestors.as_deref())
+        });
+        let removed = before_count - cache.len();
+
+        if removed > 0 {
+            debug!(
+                "Invalidated {} cached elements for {:?} of {}{}",
+                removed, invalidation.scope, invalidation.bus_name, invalidation.path
+            );
+            self.counters
+                .invalidated
+                .fetch_add(removed as u64, Ordering::Relaxed);
+        }
+        removed
+    }
+
     /// Invalidate a specific cache entry
     pub async fn invalidate(&self, key: &str) {
         debug!("Invalidating cache for: {}", key);
 
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .database
+                .delete_cached_element(store.desktop_id.clone(), key.to_string())
+                .await
+            {
+                warn!("Failed to delete persisted element {}: {:#}", key, e);
+            }
+        }
+
         let mut cache = self.cache.write().await;
         cache.remove(key);
     }
 
     /// Clear all expired entries
     pub async fn clear_expired(&self) {
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .database
+                .purge_expired_elements(store.desktop_id.clone())
+                .await
+            {
+                warn!("Failed to purge expired persisted elements: {:#}", e);
+            }
+        }
+
         let mut cache = self.cache.write().await;
 
         let before_count = cache.len();
-        cache.retain(|_, cached| cached.cached_at.elapsed() < self.ttl);
+        let now = Instant::now();
+        cache.retain(|_, cached| cached.expires_at > now);
         let after_count = cache.len();
 
         if before_count != after_count {
@@ -86,6 +290,16 @@ impl ElementCache {
     pub async fn clear_all(&self) {
         info!("Clearing all cache entries");
 
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .database
+                .clear_cached_elements(store.desktop_id.clone())
+                .await
+            {
+                warn!("Failed to clear persisted elements: {:#}", e);
+            }
+        }
+
         let mut cache = self.cache.write().await;
         cache.clear();
     }
@@ -94,31 +308,101 @@ impl ElementCache {
     pub async fn stats(&self) -> CacheStats {
         let cache = self.cache.read().await;
         let total = cache.len();
+        let now = Instant::now();
         let expired = cache
             .values()
-            .filter(|cached| cached.cached_at.elapsed() >= self.ttl)
+            .filter(|cached| cached.expires_at <= now)
             .count();
 
         CacheStats {
             total_entries: total,
             expired_entries: expired,
             active_entries: total - expired,
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            stale_evictions: self.counters.stale.load(Ordering::Relaxed),
+            invalidations: self.counters.invalidated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Load a live entry from the persistent store into memory
+    async fn load_persisted(&self, key: &str) -> Option<Element> {
+        let store = self.store.as_ref()?;
+
+        let stored = match store
+            .database
+            .load_cached_element(store.desktop_id.clone(), key.to_string())
+            .await
+        {
+            Ok(stored) => stored?,
+            Err(e) => {
+                warn!("Failed to load persisted element {}: {:#}", key, e);
+                return None;
+            }
+        };
+
+        if stored.is_expired() {
+            if let Err(e) = store
+                .database
+                .record_element_cache_miss(store.desktop_id.clone(), key.to_string())
+                .await
+            {
+                debug!("Failed to record cache miss for {}: {:#}", key, e);
+            }
+            return None;
+        }
+
+        let remaining = (stored.expires_at - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .min(self.ttl);
+
+        let mut cache = self.cache.write().await;
+        cache.insert(
+            key.to_string(),
+            CachedElement {
+                element: stored.element.clone(),
+                expires_at: Instant::now() + remaining,
+                ancestors: stored.ancestors,
+            },
+        );
+
+        Some(stored.element)
+    }
+
+    async fn record_hit(&self, key: &str) {
+        self.counters.hits.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(store) = &self.store {
+            if let Err(e) = store
+                .database
+                .record_element_cache_hit(store.desktop_id.clone(), key.to_string())
+                .await
+            {
+                debug!("Failed to record cache hit for {}: {:#}", key, e);
+            }
         }
     }
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CacheStats {
     pub total_entries: usize,
     pub expired_entries: usize,
     pub active_entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub stale_evictions: u64,
+    /// Entries dropped because an accessibility event changed them
+    pub invalidations: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::atspi::element::ElementBounds;
+    use crate::db::testing::TestDatabase;
 
     #[tokio::test]
     async fn test_cache_basic() {
@@ -135,7 +419,10 @@ mod tests {
                 width: 80,
                 height: 30,
             }),
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
             path: "/org/a11y/atspi/accessible/1234".to_string(),
+            handle: String::new(),
         };
 
         // Set element
@@ -161,7 +448,10 @@ mod tests {
             description: "".to_string(),
             states: vec![],
             bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
             path: "/org/a11y/atspi/accessible/1234".to_string(),
+            handle: String::new(),
         };
 
         cache.set("test_key".to_string(), element).await;
@@ -186,7 +476,10 @@ mod tests {
             description: "".to_string(),
             states: vec![],
             bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
             path: "/org/a11y/atspi/accessible/1234".to_string(),
+            handle: String::new(),
         };
 
         cache.set("test_key".to_string(), element).await;
@@ -206,7 +499,10 @@ mod tests {
             description: "".to_string(),
             states: vec![],
             bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
             path: "/org/a11y/atspi/accessible/1234".to_string(),
+            handle: String::new(),
         };
 
         cache.set("key1".to_string(), element.clone()).await;
@@ -216,4 +512,121 @@ mod tests {
         assert_eq!(stats.total_entries, 2);
         assert_eq!(stats.active_entries, 2);
     }
+
+    #[tokio::test]
+    async fn test_cache_event_invalidation() {
+        const TTL: Duration = Duration::from_secs(60);
+        let cache = ElementCache::new(60);
+        let at = |path: &str| Element {
+            name: "OK".to_string(),
+            role: "PushButton".to_string(),
+            description: "".to_string(),
+            states: vec![],
+            bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
+            path: path.to_string(),
+            handle: String::new(),
+        };
+        let root = "/org/a11y/atspi/accessible/root".to_string();
+        let dialog = "/org/a11y/atspi/accessible/7".to_string();
+
+        cache
+            .set_in_tree("dialog".to_string(), at(&dialog), vec![root.clone()], TTL)
+            .await;
+        cache
+            .set_in_tree(
+                "ok".to_string(),
+                at("/ok"),
+                vec![root.clone(), dialog.clone()],
+                TTL,
+            )
+            .await;
+        cache
+            .set_in_tree("other".to_string(), at("/other"), vec![root.clone()], TTL)
+            .await;
+        cache.set("unknown".to_string(), at("/unknown")).await;
+
+        let invalidation = |bus_name: &str, scope| Invalidation {
+            bus_name: bus_name.to_string(),
+            path: dialog.clone(),
+            scope,
+        };
+
+        // Another application's objects share paths but not bus names
+        assert_eq!(
+            cache
+                .apply(&invalidation(":1.7", InvalidationScope::Subtree))
+                .await,
+            0
+        );
+        assert_eq!(
+            cache
+                .apply(&invalidation(":1.42", InvalidationScope::Object))
+                .await,
+            1
+        );
+        assert!(cache.get("dialog").await.is_none());
+        assert!(cache.get("ok").await.is_some());
+
+        // Below the dialog: its button, and the entry of unknown ancestry
+        assert_eq!(
+            cache
+                .apply(&invalidation(":1.42", InvalidationScope::Subtree))
+                .await,
+            2
+        );
+        assert!(cache.get("ok").await.is_none());
+        assert!(cache.get("unknown").await.is_none());
+        assert!(cache.get("other").await.is_some());
+
+        // A new object anywhere may be a second match for any lookup
+        assert_eq!(
+            cache
+                .apply(&invalidation(":1.7", InvalidationScope::Lookups))
+                .await,
+            1
+        );
+        assert!(cache.get("other").await.is_none());
+        assert_eq!(cache.stats().await.invalidations, 4);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_cache_survives_restart() {
+        let test_database = TestDatabase::new().await;
+        let database = AsyncDatabase::new(test_database.shared());
+
+        let element = Element {
+            name: "Save".to_string(),
+            role: "PushButton".to_string(),
+            description: "".to_string(),
+            states: vec![],
+            bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
+            path: "/org/a11y/atspi/accessible/99".to_string(),
+            handle: String::new(),
+        };
+
+        let cache = ElementCache::persistent(60, database.clone(), "desk-1");
+        cache.set("save".to_string(), element).await;
+        drop(cache);
+
+        // A fresh cache for the same desktop sees the entry, another desktop does not
+        let restarted = ElementCache::persistent(60, database.clone(), "desk-1");
+        assert_eq!(restarted.get("save").await.unwrap().name, "Save");
+        let other = ElementCache::persistent(60, database.clone(), "desk-2");
+        assert!(other.get("save").await.is_none());
+
+        let stats = restarted.stats().await;
+        assert_eq!(stats.hits, 1);
+        assert_eq!(other.stats().await.misses, 1);
+
+        let stored = database
+            .load_cached_element("desk-1".to_string(), "save".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.hit_count, 1);
+    }
 }
@@ -1,8 +1,37 @@
 // Element discovery and tree traversal
-use anyhow::Result;
-use atspi::{connection::AccessibilityConnection, Role, State};
+use super::fuzzy::{self, similarity, MIN_SIMILARITY};
+use super::handle::{fingerprint, ElementHandle};
+use super::selector::{Selector, TreeNode};
+use anyhow::{bail, Context, Result};
+use atspi::proxy::accessible::{AccessibleProxy, ObjectRefExt};
+use atspi::proxy::component::ComponentProxy;
+use atspi::{
+    connection::AccessibilityConnection, CoordType, Interface, InterfaceSet, ObjectRef, Role,
+    State, StateSet,
+};
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+/// The AT-SPI registry, whose root object's children are the applications
+const REGISTRY_BUS: &str = "org.a11y.atspi.Registry";
+const ROOT_PATH: &str = "/org/a11y/atspi/accessible/root";
+/// Path AT-SPI uses for "no object"
+const NULL_PATH: &str = "/org/a11y/atspi/accessible/null";
+
+/// Levels below an application's root searched unless the finder says otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 50;
+/// Time allowed for walking one application; what a hung application
+/// answered by then is kept, and the walk is flagged as incomplete
+pub(super) const APPLICATION_TIMEOUT: Duration = Duration::from_secs(5);
+/// Objects visited per application before the walk gives up
+const MAX_NODES_PER_APPLICATION: usize = 20_000;
+/// Candidates listed when a lookup is not unique
+const MAX_CANDIDATES: usize = 10;
+/// Parents followed from an element before giving up on finding its application
+const MAX_ANCESTORS: usize = 200;
 
 /// Represents a UI element in the accessibility tree
 #[allow(dead_code)]
@@ -13,7 +42,13 @@ pub struct Element {
     pub description: String,
     pub states: Vec<String>,
     pub bounds: Option<ElementBounds>,
+    #[serde(default)]
+    pub bus_name: String, // D-Bus name of the owning application
+    #[serde(default)]
+    pub app_name: String, // Name of the owning application
     pub path: String, // Unique identifier (object path)
+    #[serde(default)]
+    pub handle: String, // Names this element in later requests (see handle.rs)
 }
 
 #[allow(dead_code)]
@@ -32,6 +67,10 @@ pub struct ElementSelector {
     pub name: Option<String>,
     pub role: Option<Role>,
     pub states: Option<Vec<State>>,
+    /// Only search the application with this name
+    pub app: Option<String>,
+    /// Also accept names differing in case, spacing, mnemonics and ellipses
+    pub fuzzy: bool,
 }
 
 #[allow(dead_code)]
@@ -41,6 +80,8 @@ impl ElementSelector {
             name: Some(name.to_string()),
             role: None,
             states: None,
+            app: None,
+            fuzzy: false,
         }
     }
 
@@ -49,6 +90,8 @@ impl ElementSelector {
             name: None,
             role: Some(role),
             states: None,
+            app: None,
+            fuzzy: false,
         }
     }
 
@@ -57,6 +100,113 @@ impl ElementSelector {
             name: Some(name.to_string()),
             role: Some(role),
             states: None,
+            app: None,
+            fuzzy: false,
+        }
+    }
+
+    /// Restrict the search to one application
+    pub fn in_app(mut self, app: &str) -> Self {
+        self.app = Some(app.to_string());
+        self
+    }
+}
+
+/// Parse a role from its AT-SPI name (`push button`) or variant name
+/// (`PushButton`, `push_button`)
+pub fn parse_role(role: &str) -> Option<Role> {
+    let wanted = normalize(role);
+    (0..=u8::MAX as u32)
+        .filter_map(|n| Role::try_from(n).ok())
+        .find(|role| {
+            normalize(role.name()) == wanted || normalize(&format!("{:?}", role)) == wanted
+        })
+}
+
+/// Parse a state from its AT-SPI name (`has-tooltip`) or variant name
+/// (`HasTooltip`)
+pub fn parse_state(state: &str) -> Option<State> {
+    let wanted = normalize(state);
+    (0..64)
+        .filter_map(|bit| StateSet::from_bits(1 << bit).ok())
+        .filter_map(|set| set.iter().next())
+        .find(|state| normalize(&format!("{:?}", state)) == wanted)
+}
+
+/// Lowercase letters and digits only, so naming styles compare equal
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// An element offered when a lookup did not find exactly one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candidate {
+    /// Similarity of the element's name to the requested one, 0 to 1
+    pub score: f64,
+    #[serde(flatten)]
+    pub element: Element,
+}
+
+/// A lookup that matched no element or several
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct LookupError {
+    pub message: String,
+    /// Number of elements that matched
+    pub matches: usize,
+    /// Best first
+    pub candidates: Vec<Candidate>,
+    /// Applications only partly searched, so the element may be among the
+    /// objects not reached
+    pub incomplete: Vec<String>,
+}
+
+/// An element handle that no longer names the element it was made for
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct StaleError {
+    pub message: String,
+    /// The one element that now has the handle's fingerprint, if any
+    pub candidate: Option<Element>,
+}
+
+/// Elements a search found
+#[derive(Debug, Default)]
+pub struct FoundElements {
+    /// In tree order
+    pub elements: Vec<Element>,
+    /// Applications only partly searched, so more elements may match
+    pub incomplete: Vec<String>,
+}
+
+/// Objects of a walked tree, with the D-Bus reference of each node
+#[derive(Default)]
+pub(super) struct Tree {
+    pub(super) nodes: Vec<TreeNode>,
+    pub(super) objects: Vec<ObjectRef>,
+    /// Applications whose walk was cut short, in walk order
+    pub(super) incomplete: Vec<String>,
+}
+
+impl Tree {
+    pub(super) fn handle(&self, index: usize) -> ElementHandle {
+        let node = &self.nodes[index];
+        let mut ancestors = Vec::new();
+        let mut parent = node.parent;
+        while let Some(index) = parent {
+            ancestors.push(self.nodes[index].role);
+            parent = self.nodes[index].parent;
+        }
+        ancestors.reverse();
+
+        let object = &self.objects[index];
+        ElementHandle {
+            bus_name: object.name.to_string(),
+            path: object.path.to_string(),
+            fingerprint: fingerprint(&node.app, node.role, &node.name, &ancestors),
         }
     }
 }
@@ -64,33 +214,596 @@ impl ElementSelector {
 /// Element discovery functions
 #[allow(dead_code)]
 pub struct ElementFinder {
-    _connection: AccessibilityConnection,
+    pub(super) connection: Arc<AccessibilityConnection>,
+    pub(super) max_depth: usize,
 }
 
 #[allow(dead_code)]
 impl ElementFinder {
-    pub fn new(connection: AccessibilityConnection) -> Self {
+    pub fn new(connection: Arc<AccessibilityConnection>) -> Self {
         Self {
-            _connection: connection,
+            connection,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
-    /// Find elements matching the selector
-    pub async fn find_elements(&self, selector: &ElementSelector) -> Result<Vec<Element>> {
-        info!("Searching for elements: {:?}", selector);
+    /// Levels below an application's root to search (its windows are level 1)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Find elements matching the selector, in tree order. Applications
+    /// that fail or stop answering are skipped rather than failing the
+    /// search, and listed as incomplete.
+    pub async fn find_elements(&self, selector: &Selector) -> Result<FoundElements> {
+        info!("Searching for elements: {}", selector);
 
-        // TODO: Implement actual element discovery
-        // This is a stub for Phase 2
-        Ok(Vec::new())
+        let tree = self.walk(selector).await?;
+        let mut elements = Vec::new();
+        for index in selector.select(&tree.nodes) {
+            match self.tree_element(&tree, index).await {
+                Ok(element) => elements.push(element),
+                Err(e) => debug!("Matched element disappeared: {:#}", e),
+            }
+        }
+
+        debug!("Found {} matching elements", elements.len());
+        Ok(FoundElements {
+            elements,
+            incomplete: tree.incomplete,
+        })
     }
 
     /// Find the first element matching the selector
-    pub async fn find_element(&self, selector: &ElementSelector) -> Result<Option<Element>> {
-        let elements = self.find_elements(selector).await?;
-        Ok(elements.into_iter().next())
+    pub async fn find_element(&self, selector: &Selector) -> Result<Option<Element>> {
+        let found = self.find_elements(selector).await?;
+        Ok(found.elements.into_iter().next())
+    }
+
+    /// Find the one element a request acts on. No match or several is a
+    /// `LookupError` listing candidates ranked by similarity to `name`, the
+    /// name the element was requested by; with no match, only elements with
+    /// a similar name are listed, so selector lookups get none. A single
+    /// match only counts when every application was searched in full, since
+    /// another could be among the objects not reached.
+    pub async fn find_unique(&self, selector: &Selector, name: Option<&str>) -> Result<Element> {
+        info!("Looking up element: {}", selector);
+
+        let tree = self.walk(selector).await?;
+        let matches = selector.select(&tree.nodes);
+        if let ([index], true) = (&matches[..], tree.incomplete.is_empty()) {
+            return self
+                .tree_element(&tree, *index)
+                .await
+                .context("Matched element disappeared");
+        }
+
+        let (message, ranked) =
+            rank_candidates(&tree.nodes, &matches, selector, name, &tree.incomplete)?;
+        let mut candidates = Vec::new();
+        for (index, score) in ranked {
+            if let Ok(element) = self.tree_element(&tree, index).await {
+                candidates.push(Candidate {
+                    score: (score * 100.0).round() / 100.0,
+                    element,
+                });
+            }
+        }
+        Err(LookupError {
+            message,
+            matches: matches.len(),
+            candidates,
+            incomplete: tree.incomplete,
+        }
+        .into())
+    }
+
+    /// The element a handle names, if it is still the element the handle was
+    /// made for. Otherwise the handle is stale: a `StaleError`, with the one
+    /// element now carrying the handle's fingerprint if there is exactly one.
+    pub async fn resolve(&self, handle: &str) -> Result<Element> {
+        let handle: ElementHandle = handle.parse()?;
+        let reason = match self.check(&handle).await {
+            Ok(Some(element)) => return Ok(element),
+            Ok(None) => "it has changed".to_string(),
+            Err(e) => format!("it is no longer reachable ({:#})", e),
+        };
+        info!("Element handle {} is stale: {}", handle, reason);
+
+        let candidate = self.relocate(&handle).await;
+        let message = match &candidate {
+            Some(_) => format!(
+                "Stale element {}: {}; found it again elsewhere",
+                handle, reason
+            ),
+            None => format!("Stale element {}: {}", handle, reason),
+        };
+        Err(StaleError { message, candidate }.into())
+    }
+
+    /// The element at the handle's path, if its fingerprint still matches
+    async fn check(&self, handle: &ElementHandle) -> Result<Option<Element>> {
+        let proxy = AccessibleProxy::builder(self.connection.connection())
+            .destination(handle.bus_name.as_str())?
+            .path(handle.path.as_str())?
+            .build()
+            .await?;
+        let (role, name, states) = identity(&proxy).await?;
+        if states.contains(State::Defunct) {
+            return Ok(None);
+        }
+        let (app, ancestors) = self.ancestry(&proxy, &handle.path).await?;
+        if fingerprint(&app, role, &name, &ancestors) != handle.fingerprint {
+            return Ok(None);
+        }
+
+        let node = TreeNode {
+            role,
+            name,
+            description: None,
+            app: Arc::from(app),
+            states,
+            parent: None,
+        };
+        let element = self
+            .element(&handle.bus_name, &handle.path, &node, handle)
+            .await?;
+        Ok(Some(element))
+    }
+
+    /// The element at a bus name and path, with a handle naming it
+    pub async fn element_at(&self, bus_name: &str, path: &str) -> Result<Element> {
+        let proxy = AccessibleProxy::builder(self.connection.connection())
+            .destination(bus_name)?
+            .path(path)?
+            .build()
+            .await?;
+        let (role, name, states) = identity(&proxy).await?;
+        let (app, ancestors) = self.ancestry(&proxy, path).await?;
+        let handle = ElementHandle {
+            bus_name: bus_name.to_string(),
+            path: path.to_string(),
+            fingerprint: fingerprint(&app, role, &name, &ancestors),
+        };
+
+        let node = TreeNode {
+            role,
+            name,
+            description: None,
+            app: Arc::from(app),
+            states,
+            parent: None,
+        };
+        self.element(bus_name, path, &node, &handle).await
+    }
+
+    /// Application name and ancestor roles, from the application root down
+    async fn ancestry(
+        &self,
+        proxy: &AccessibleProxy<'_>,
+        path: &str,
+    ) -> Result<(String, Vec<Role>)> {
+        if path == ROOT_PATH {
+            return Ok((proxy.name().await?, Vec::new()));
+        }
+        let mut ancestors = Vec::new();
+        let mut parent = proxy.parent().await?;
+        for _ in 0..MAX_ANCESTORS {
+            if parent.path.as_str() == NULL_PATH {
+                bail!("detached from its application");
+            }
+            let proxy = parent
+                .as_accessible_proxy(self.connection.connection())
+                .await?;
+            ancestors.push(proxy.get_role().await?);
+            if parent.path.as_str() == ROOT_PATH {
+                ancestors.reverse();
+                return Ok((proxy.name().await?, ancestors));
+            }
+            parent = proxy.parent().await?;
+        }
+        bail!("more than {} levels below its application", MAX_ANCESTORS)
+    }
+
+    /// Object paths of an element's ancestors, from the application root down
+    pub async fn ancestor_paths(&self, bus_name: &str, path: &str) -> Result<Vec<String>> {
+        let mut ancestors = Vec::new();
+        if path == ROOT_PATH {
+            return Ok(ancestors);
+        }
+        let proxy = AccessibleProxy::builder(self.connection.connection())
+            .destination(bus_name)?
+            .path(path)?
+            .build()
+            .await?;
+        let mut parent = proxy.parent().await?;
+        for _ in 0..MAX_ANCESTORS {
+            if parent.path.as_str() == NULL_PATH {
+                bail!("detached from its application");
+            }
+            ancestors.push(parent.path.to_string());
+            if parent.path.as_str() == ROOT_PATH {
+                ancestors.reverse();
+                return Ok(ancestors);
+            }
+            parent = parent
+                .as_accessible_proxy(self.connection.connection())
+                .await?
+                .parent()
+                .await?;
+        }
+        bail!("more than {} levels below its application", MAX_ANCESTORS)
+    }
+
+    /// The element now carrying the handle's fingerprint, when toolkits
+    /// have recreated it under another path or the application restarted
+    async fn relocate(&self, handle: &ElementHandle) -> Option<Element> {
+        let tree = self.walk(&Selector::any()).await.ok()?;
+        let matches: Vec<usize> = (0..tree.nodes.len())
+            .filter(|&index| tree.handle(index).fingerprint == handle.fingerprint)
+            .collect();
+        match matches[..] {
+            [index] => self.tree_element(&tree, index).await.ok(),
+            _ => None,
+        }
+    }
+
+    /// Walk every application the selector can match
+    async fn walk(&self, selector: &Selector) -> Result<Tree> {
+        self.walk_with(selector, selector.uses_description()).await
+    }
+
+    /// Walk every application the selector can match, fetching descriptions
+    /// whether or not the selector needs them
+    pub(super) async fn walk_with(
+        &self,
+        selector: &Selector,
+        with_description: bool,
+    ) -> Result<Tree> {
+        let mut tree = Tree::default();
+        for (app, app_name) in self.applications().await? {
+            let Some(app_name) = app_name else {
+                // Whether the selector could match it is unknown
+                tree.incomplete.push(app.name.to_string());
+                continue;
+            };
+            if !selector.may_match_app(&app_name) {
+                continue;
+            }
+            let walked = tree.nodes.len();
+            let walk = self.walk_application(&app, &app_name, with_description, &mut tree);
+            match timeout(APPLICATION_TIMEOUT, walk).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => {
+                    debug!("Skipping application {}: {:#}", app_name, e);
+                    tree.incomplete.push(app_name);
+                }
+                Err(_) => {
+                    // Kept, but :nth() positions after it may be off
+                    warn!(
+                        "Application {} did not answer within {:?}; searching the {} objects walked",
+                        app_name,
+                        APPLICATION_TIMEOUT,
+                        tree.nodes.len() - walked
+                    );
+                    tree.incomplete.push(app_name);
+                    continue;
+                }
+            }
+            tree.nodes.truncate(walked);
+            tree.objects.truncate(walked);
+        }
+        Ok(tree)
+    }
+
+    /// Root objects of the applications registered with AT-SPI, and their
+    /// names. The name is `None` for an application that did not answer
+    /// within `APPLICATION_TIMEOUT`.
+    pub async fn applications(&self) -> Result<Vec<(ObjectRef, Option<String>)>> {
+        let registry = AccessibleProxy::builder(self.connection.connection())
+            .destination(REGISTRY_BUS)?
+            .path(ROOT_PATH)?
+            .build()
+            .await?;
+        let children = timeout(APPLICATION_TIMEOUT, registry.get_children())
+            .await
+            .context("The AT-SPI registry did not answer")?
+            .context("Failed to list AT-SPI applications")?;
+
+        let mut applications = Vec::new();
+        for app in children {
+            let name = async {
+                let proxy = app
+                    .as_accessible_proxy(self.connection.connection())
+                    .await?;
+                anyhow::Ok(proxy.name().await.unwrap_or_default())
+            };
+            let name = match timeout(APPLICATION_TIMEOUT, name).await {
+                Ok(Ok(name)) => Some(name),
+                Ok(Err(_)) => continue,
+                Err(_) => {
+                    warn!(
+                        "Application {} did not answer within {:?}",
+                        app.name.as_str(),
+                        APPLICATION_TIMEOUT
+                    );
+                    None
+                }
+            };
+            applications.push((app, name));
+        }
+        Ok(applications)
+    }
+
+    /// Depth-first walk of one application, children in order, appending
+    /// every object to the tree
+    async fn walk_application(
+        &self,
+        app: &ObjectRef,
+        app_name: &str,
+        with_description: bool,
+        tree: &mut Tree,
+    ) -> Result<()> {
+        let app_name: Arc<str> = Arc::from(app_name);
+        let mut stack = vec![(app.clone(), 0, None)];
+        let mut visited = 0;
+
+        while let Some((object, depth, parent)) = stack.pop() {
+            visited += 1;
+            if visited > MAX_NODES_PER_APPLICATION {
+                warn!(
+                    "Stopped searching {} after {} objects",
+                    app_name, MAX_NODES_PER_APPLICATION
+                );
+                tree.incomplete.push(app_name.to_string());
+                break;
+            }
+
+            let proxy = object
+                .as_accessible_proxy(self.connection.connection())
+                .await?;
+            let (role, name, states) = match identity(&proxy).await {
+                Ok(identity) => identity,
+                Err(e) => {
+                    // Objects can disappear while the tree is walked
+                    debug!("Skipping {}: {}", object.path.as_str(), e);
+                    continue;
+                }
+            };
+            if states.contains(State::Defunct) {
+                continue;
+            }
+            let description = match with_description {
+                true => Some(proxy.description().await.unwrap_or_default()),
+                false => None,
+            };
+
+            let index = tree.nodes.len();
+            tree.nodes.push(TreeNode {
+                role,
+                name,
+                description,
+                app: app_name.clone(),
+                states,
+                parent,
+            });
+            tree.objects.push(object.clone());
+
+            // Tables and lists that manage their descendants can have
+            // millions of children created on demand
+            if depth >= self.max_depth || states.contains(State::ManagesDescendants) {
+                continue;
+            }
+            match proxy.get_children().await {
+                Ok(children) => stack.extend(
+                    children
+                        .into_iter()
+                        .rev()
+                        .filter(|child| child.path.as_str() != NULL_PATH)
+                        .map(|child| (child, depth + 1, Some(index))),
+                ),
+                Err(e) => debug!("No children for {}: {}", object.path.as_str(), e),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn tree_element(&self, tree: &Tree, index: usize) -> Result<Element> {
+        let object = &tree.objects[index];
+        let handle = tree.handle(index);
+        self.element(
+            object.name.as_str(),
+            object.path.as_str(),
+            &tree.nodes[index],
+            &handle,
+        )
+        .await
+    }
+
+    /// Fill in the properties not needed for matching
+    async fn element(
+        &self,
+        bus_name: &str,
+        path: &str,
+        node: &TreeNode,
+        handle: &ElementHandle,
+    ) -> Result<Element> {
+        let proxy = AccessibleProxy::builder(self.connection.connection())
+            .destination(bus_name)?
+            .path(path)?
+            .build()
+            .await?;
+        let bounds = match proxy.get_interfaces().await {
+            Ok(interfaces) if interfaces.contains(Interface::Component) => {
+                self.bounds(bus_name, path).await.ok()
+            }
+            _ => None,
+        };
+        let description = match &node.description {
+            Some(description) => description.clone(),
+            None => proxy.description().await.unwrap_or_default(),
+        };
+
+        Ok(Element {
+            name: node.name.clone(),
+            role: format!("{:?}", node.role),
+            description,
+            states: node
+                .states
+                .iter()
+                .map(|state| format!("{:?}", state))
+                .collect(),
+            bounds,
+            bus_name: bus_name.to_string(),
+            app_name: node.app.to_string(),
+            path: path.to_string(),
+            handle: handle.to_string(),
+        })
+    }
+
+    pub(super) async fn bounds(&self, bus_name: &str, path: &str) -> Result<ElementBounds> {
+        let component = ComponentProxy::builder(self.connection.connection())
+            .destination(bus_name)?
+            .path(path)?
+            .build()
+            .await?;
+        let (x, y, width, height) = component.get_extents(CoordType::Screen).await?;
+        Ok(ElementBounds {
+            x,
+            y,
+            width,
+            height,
+        })
     }
 }
 
+/// Message and best candidates, as node indices with scores, for a lookup
+/// that did not match exactly one node
+fn rank_candidates(
+    nodes: &[TreeNode],
+    matches: &[usize],
+    selector: &Selector,
+    name: Option<&str>,
+    incomplete: &[String],
+) -> Result<(String, Vec<(usize, f64)>)> {
+    let score = |index: usize| name.map_or(1.0, |name| similarity(name, &nodes[index].name));
+    let (mut message, mut ranked): (String, Vec<(usize, f64)>) = match (matches.len(), name) {
+        // Only a lookup error can say the search was incomplete
+        (0, None) if incomplete.is_empty() => bail!("No element matches {}", selector),
+        (0, None) => (format!("No element matches {}", selector), Vec::new()),
+        (0, Some(name)) => {
+            let similar: Vec<_> = (0..nodes.len())
+                .filter(|&index| !nodes[index].name.trim().is_empty())
+                .map(|index| (index, score(index)))
+                .filter(|(_, score)| *score >= MIN_SIMILARITY)
+                .collect();
+            let wanted = fuzzy::normalize(name);
+            let message = if similar
+                .iter()
+                .any(|&(index, _)| fuzzy::normalize(&nodes[index].name) == wanted)
+            {
+                format!(
+                    "No element named \"{}\" (fuzzy matching would find one)",
+                    name
+                )
+            } else {
+                format!("No element named \"{}\"", name)
+            };
+            (message, similar)
+        }
+        (1, Some(name)) => (
+            format!("One element is named \"{}\"", name),
+            matches.iter().map(|&index| (index, score(index))).collect(),
+        ),
+        (1, None) => (
+            format!("One element matches {}", selector),
+            matches.iter().map(|&index| (index, 1.0)).collect(),
+        ),
+        (count, Some(name)) => (
+            format!("{} elements are named \"{}\"", count, name),
+            matches.iter().map(|&index| (index, score(index))).collect(),
+        ),
+        (count, None) => (
+            format!("{} elements match {}", count, selector),
+            matches.iter().map(|&index| (index, 1.0)).collect(),
+        ),
+    };
+    if !incomplete.is_empty() {
+        message.push_str(&format!(
+            "; {} could only be searched in part",
+            incomplete.join(", ")
+        ));
+    }
+    // Stable, so equal scores stay in tree order
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(MAX_CANDIDATES);
+    Ok((message, ranked))
+}
+
+/// Role, name and states: what a selector matches on
+async fn identity(proxy: &AccessibleProxy<'_>) -> Result<(Role, String, StateSet)> {
+    let role = proxy.get_role().await?;
+    let name = proxy.name().await?;
+    let states = proxy.get_state().await?;
+    Ok((role, name, states))
+}
+
+/// Name of the application a bus name belongs to: that of its root object
+pub async fn application_name(
+    connection: &AccessibilityConnection,
+    bus_name: &str,
+) -> Result<String> {
+    let proxy = AccessibleProxy::builder(connection.connection())
+        .destination(bus_name)?
+        .path(ROOT_PATH)?
+        .build()
+        .await?;
+    Ok(proxy.name().await?)
+}
+
+/// The AT-SPI interfaces an object implements
+pub(super) async fn interfaces(
+    connection: &AccessibilityConnection,
+    bus_name: &str,
+    path: &str,
+) -> Result<InterfaceSet> {
+    let proxy = AccessibleProxy::builder(connection.connection())
+        .destination(bus_name)?
+        .path(path)?
+        .build()
+        .await?;
+    Ok(proxy.get_interfaces().await?)
+}
+
+/// Check that an element still exists on the bus with the same role and name.
+/// Object paths are reused by toolkits, so a path that resolves is not enough.
+pub async fn is_live(connection: &AccessibilityConnection, element: &Element) -> bool {
+    match fetch_identity(connection, element).await {
+        Ok((role, name)) => role == element.role && name == element.name,
+        Err(e) => {
+            debug!("Element {} is no longer reachable: {}", element.path, e);
+            false
+        }
+    }
+}
+
+async fn fetch_identity(
+    connection: &AccessibilityConnection,
+    element: &Element,
+) -> Result<(String, String)> {
+    let proxy = AccessibleProxy::builder(connection.connection())
+        .destination(element.bus_name.as_str())?
+        .path(element.path.as_str())?
+        .build()
+        .await?;
+
+    let role = proxy.get_role().await?;
+    let name = proxy.name().await?;
+    Ok((format!("{:?}", role), name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -103,4 +816,80 @@ mod tests {
         let selector = ElementSelector::by_role(Role::PushButton);
         assert!(selector.role.is_some());
     }
+
+    #[test]
+    fn test_rank_candidates() {
+        let app: Arc<str> = Arc::from("gedit");
+        let nodes: Vec<TreeNode> = ["gedit", "OK", "_Save", "Save As…", "Cancel", "OK"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| TreeNode {
+                role: Role::PushButton,
+                name: name.to_string(),
+                description: None,
+                app: app.clone(),
+                states: StateSet::empty(),
+                parent: index.checked_sub(1).map(|_| 0),
+            })
+            .collect();
+        let selector: Selector = ElementSelector::by_name("save").into();
+
+        let (message, ranked) = rank_candidates(&nodes, &[], &selector, Some("save"), &[]).unwrap();
+        assert_eq!(
+            message,
+            "No element named \"save\" (fuzzy matching would find one)"
+        );
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), vec![2]);
+
+        let (message, ranked) =
+            rank_candidates(&nodes, &[], &selector, Some("Save As"), &[]).unwrap();
+        assert_eq!(
+            message,
+            "No element named \"Save As\" (fuzzy matching would find one)"
+        );
+        assert_eq!(ranked[0].0, 3);
+
+        let (message, ranked) =
+            rank_candidates(&nodes, &[1, 5], &selector, Some("OK"), &[]).unwrap();
+        assert_eq!(message, "2 elements are named \"OK\"");
+        assert_eq!(ranked, vec![(1, 1.0), (5, 1.0)]);
+
+        let selector: Selector = "push-button".parse().unwrap();
+        let (message, _) = rank_candidates(&nodes, &[1, 2, 4], &selector, None, &[]).unwrap();
+        assert_eq!(message, "3 elements match push-button");
+        let error = rank_candidates(&nodes, &[], &selector, None, &[]).unwrap_err();
+        assert_eq!(error.to_string(), "No element matches push-button");
+
+        // A partial walk is reported rather than passed off as no match
+        let incomplete = ["gimp".to_string()];
+        let (message, ranked) = rank_candidates(&nodes, &[], &selector, None, &incomplete).unwrap();
+        assert_eq!(
+            message,
+            "No element matches push-button; gimp could only be searched in part"
+        );
+        assert!(ranked.is_empty());
+
+        // Nor is one match a unique one when another could be in the rest
+        let (message, ranked) =
+            rank_candidates(&nodes, &[1], &selector, Some("OK"), &incomplete).unwrap();
+        assert_eq!(
+            message,
+            "One element is named \"OK\"; gimp could only be searched in part"
+        );
+        assert_eq!(ranked, vec![(1, 1.0)]);
+    }
+
+    #[test]
+    fn test_parse_role() {
+        assert_eq!(parse_role("PushButton"), Some(Role::PushButton));
+        assert_eq!(parse_role("push button"), Some(Role::PushButton));
+        assert_eq!(parse_role("push_button"), Some(Role::PushButton));
+        assert_eq!(parse_role("Frame"), Some(Role::Frame));
+        assert_eq!(parse_role("widget"), None);
+
+        assert_eq!(parse_state("focused"), Some(State::Focused));
+        assert_eq!(parse_state("has-tooltip"), Some(State::HasTooltip));
+        assert_eq!(parse_state("HasTooltip"), Some(State::HasTooltip));
+        assert_eq!(parse_state("sleepy"), None);
+    }
 }
@@ -0,0 +1,184 @@
+// AT-SPI event subscription
+//
+// Applications report changes to their trees as events on the accessibility
+// bus. The element cache listens so it never serves an element whose bounds,
+// states or subtree changed since it was looked up, and the focus manager
+// follows focus moving between objects.
+use super::cache::{ElementCache, Invalidation, InvalidationScope};
+use super::focus::FocusManager;
+use anyhow::{Context, Result};
+use atspi::connection::AccessibilityConnection;
+use atspi::events::focus::FocusEvent;
+use atspi::events::object::{
+    BoundsChangedEvent, ChildrenChangedEvent, ObjectEvents, Property, PropertyChangeEvent,
+    StateChangedEvent,
+};
+use atspi::events::window::{DestroyEvent, WindowEvents};
+use atspi::{Event, ObjectRef, State};
+use futures_lite::StreamExt;
+use std::sync::Arc;
+use tracing::{debug, info, trace};
+
+/// Ask applications for the events that invalidate cached elements or
+/// move focus
+pub async fn subscribe(connection: &AccessibilityConnection) -> Result<()> {
+    connection
+        .register_event::<ChildrenChangedEvent>()
+        .await
+        .context("Failed to subscribe to children-changed events")?;
+    connection
+        .register_event::<StateChangedEvent>()
+        .await
+        .context("Failed to subscribe to state-changed events")?;
+    connection
+        .register_event::<PropertyChangeEvent>()
+        .await
+        .context("Failed to subscribe to property-change events")?;
+    connection
+        .register_event::<BoundsChangedEvent>()
+        .await
+        .context("Failed to subscribe to bounds-changed events")?;
+    connection
+        .register_event::<DestroyEvent>()
+        .await
+        .context("Failed to subscribe to window destroy events")?;
+    connection
+        .register_event::<FocusEvent>()
+        .await
+        .context("Failed to subscribe to focus events")?;
+    Ok(())
+}
+
+/// Invalidate cached elements and track focus as events arrive, until the
+/// bus connection closes. Call `subscribe` first.
+pub async fn watch(
+    connection: Arc<AccessibilityConnection>,
+    cache: Arc<ElementCache>,
+    focus: Arc<FocusManager>,
+) {
+    focus.set_tracking(true);
+    let mut events = std::pin::pin!(connection.event_stream());
+    while let Some(event) = events.next().await {
+        let event = match event {
+            Ok(event) => event,
+            // Every other signal on the bus arrives here too
+            Err(e) => {
+                trace!("Ignoring signal: {}", e);
+                continue;
+            }
+        };
+        focus.observe(&event);
+        if let Some(invalidation) = invalidation(&event) {
+            cache.apply(&invalidation).await;
+        }
+    }
+    info!("AT-SPI event stream ended; cached elements now only expire");
+    focus.set_tracking(false);
+    cache.clear_all().await;
+}
+
+/// What an event made stale, if anything cached can depend on it
+pub fn invalidation(event: &Event) -> Option<Invalidation> {
+    let (item, scope) = match event {
+        // Cached lookups were unique when made; a new object anywhere, in
+        // any application, can be a second match, and removed children
+        // shift the positions of their siblings
+        Event::Object(ObjectEvents::ChildrenChanged(e)) => (&e.item, InvalidationScope::Lookups),
+        Event::Object(ObjectEvents::StateChanged(e)) => match e.state {
+            // Hiding or destroying an object does the same to its descendants
+            State::Defunct | State::Showing | State::Visible => {
+                (&e.item, InvalidationScope::Subtree)
+            }
+            _ => (&e.item, InvalidationScope::Object),
+        },
+        Event::Object(ObjectEvents::PropertyChange(e)) => match e.value {
+            // A renamed object can start matching another lookup
+            Property::Name(_) => (&e.item, InvalidationScope::Lookups),
+            Property::Description(_) | Property::Role(_) => (&e.item, InvalidationScope::Object),
+            // Reparenting changes the ancestry of the whole subtree
+            Property::Parent(_) => (&e.item, InvalidationScope::Subtree),
+            _ => return None,
+        },
+        // Children move with their parent
+        Event::Object(ObjectEvents::BoundsChanged(e)) => (&e.item, InvalidationScope::Subtree),
+        Event::Window(WindowEvents::Destroy(e)) => (&e.item, InvalidationScope::Subtree),
+        _ => return None,
+    };
+    debug!(
+        "{:?} change at {}{}",
+        scope,
+        item.name.as_str(),
+        item.path.as_str()
+    );
+    Some(at(item, scope))
+}
+
+fn at(item: &ObjectRef, scope: InvalidationScope) -> Invalidation {
+    Invalidation {
+        bus_name: item.name.to_string(),
+        path: item.path.to_string(),
+        scope,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(event: impl Into<Event>) -> Option<InvalidationScope> {
+        invalidation(&event.into()).map(|invalidation| invalidation.scope)
+    }
+
+    #[test]
+    fn test_event_invalidation() {
+        let item = ObjectRef::default();
+        let state = |state| StateChangedEvent {
+            item: item.clone(),
+            state,
+            enabled: 0,
+        };
+        let property = |value| PropertyChangeEvent {
+            item: item.clone(),
+            property: String::new(),
+            value,
+        };
+
+        assert_eq!(
+            scope(ChildrenChangedEvent::default()),
+            Some(InvalidationScope::Lookups)
+        );
+        assert_eq!(
+            scope(state(State::Focused)),
+            Some(InvalidationScope::Object)
+        );
+        assert_eq!(
+            scope(state(State::Showing)),
+            Some(InvalidationScope::Subtree)
+        );
+        assert_eq!(
+            scope(property(Property::Name("Save".to_string()))),
+            Some(InvalidationScope::Lookups)
+        );
+        assert_eq!(
+            scope(property(Property::Description(String::new()))),
+            Some(InvalidationScope::Object)
+        );
+        assert_eq!(
+            scope(property(Property::Parent(item.clone()))),
+            Some(InvalidationScope::Subtree)
+        );
+        assert_eq!(scope(property(Property::TableSummary(String::new()))), None);
+        assert_eq!(
+            scope(BoundsChangedEvent::default()),
+            Some(InvalidationScope::Subtree)
+        );
+        assert_eq!(
+            scope(DestroyEvent::default()),
+            Some(InvalidationScope::Subtree)
+        );
+
+        let invalidation = invalidation(&BoundsChangedEvent { item }.into()).unwrap();
+        assert_eq!(invalidation.bus_name, ":0.0");
+        assert_eq!(invalidation.path, "/org/a11y/atspi/accessible/null");
+    }
+}
@@ -1,62 +1,293 @@
 // Focus management via AT-SPI
-use anyhow::Result;
+//
+// Applications announce focus moves as focused-state changes. The manager
+// follows them from the event stream the element cache also listens on, so
+// the focused object is known without searching for it and waiting for focus
+// ends on the application's own event.
+use super::element::{Element, ElementFinder};
+use anyhow::{bail, Result};
 use atspi::connection::AccessibilityConnection;
+use atspi::events::focus::FocusEvents;
+use atspi::events::object::ObjectEvents;
+use atspi::proxy::accessible::AccessibleProxy;
+use atspi::proxy::component::ComponentProxy;
+use atspi::{Event, ObjectRef, State};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::{sleep, timeout};
-use tracing::info;
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout_at, Instant};
+use tracing::{debug, info};
 
-/// Focus manager for AT-SPI elements
-#[allow(dead_code)]
+/// How often focus is checked when no focus events arrive
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An object on the accessibility bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusedObject {
+    pub bus_name: String,
+    pub path: String,
+}
+
+impl FocusedObject {
+    fn of(element: &Element) -> Self {
+        Self {
+            bus_name: element.bus_name.clone(),
+            path: element.path.clone(),
+        }
+    }
+}
+
+impl From<&ObjectRef> for FocusedObject {
+    fn from(item: &ObjectRef) -> Self {
+        Self {
+            bus_name: item.name.to_string(),
+            path: item.path.to_string(),
+        }
+    }
+}
+
+/// What an event says about focus
+#[derive(Debug, PartialEq)]
+enum FocusChange {
+    Gained(FocusedObject),
+    Lost(FocusedObject),
+}
+
+/// Tracks the focused object and moves focus between elements
 pub struct FocusManager {
-    _connection: AccessibilityConnection,
-    focus_timeout_ms: u64,
+    connection: Arc<AccessibilityConnection>,
+    focused: watch::Sender<Option<FocusedObject>>,
+    /// Whether focus events are arriving; without them focus is polled
+    tracking: AtomicBool,
 }
 
-#[allow(dead_code)]
 impl FocusManager {
-    pub fn new(connection: AccessibilityConnection, focus_timeout_ms: u64) -> Self {
+    pub fn new(connection: Arc<AccessibilityConnection>) -> Self {
         Self {
-            _connection: connection,
-            focus_timeout_ms,
+            connection,
+            focused: watch::Sender::new(None),
+            tracking: AtomicBool::new(false),
         }
     }
 
-    /// Focus an element
-    /// This is a stub implementation for Phase 2
-    pub async fn focus_element(&self, element_path: &str) -> Result<()> {
-        info!("Focusing element: {}", element_path);
+    /// Mark whether events feed `observe`. When they stop, what was last
+    /// tracked is forgotten since it can no longer be kept current.
+    pub(super) fn set_tracking(&self, tracking: bool) {
+        self.tracking.store(tracking, Ordering::Relaxed);
+        if !tracking {
+            self.focused.send_replace(None);
+        }
+    }
 
-        // TODO: Implement actual focus via AT-SPI
-        Ok(())
+    fn is_tracking(&self) -> bool {
+        self.tracking.load(Ordering::Relaxed)
     }
 
-    /// Get the currently focused element
-    /// This is a stub implementation for Phase 2
-    pub async fn get_focused_element(&self) -> Result<Option<String>> {
-        // TODO: Implement actual focus detection via AT-SPI
-        Ok(None)
+    /// Follow a focus change reported by an accessibility event
+    pub fn observe(&self, event: &Event) {
+        let Some(change) = focus_change(event) else {
+            return;
+        };
+        self.focused
+            .send_if_modified(|focused| apply(focused, change));
     }
 
-    /// Wait for an element to receive focus
-    /// This is a stub implementation for Phase 2
-    pub async fn wait_for_focus(&self, element_path: &str) -> Result<()> {
-        let timeout_duration = Duration::from_millis(self.focus_timeout_ms);
+    /// The focused object, as last reported by its application
+    pub fn focused(&self) -> Option<FocusedObject> {
+        self.focused.borrow().clone()
+    }
+
+    /// The focused element. Until an application reports a focus change,
+    /// e.g. right after the daemon starts, the tree is searched for it.
+    pub async fn get_focused_element(&self) -> Result<Option<Element>> {
+        let finder = ElementFinder::new(self.connection.clone());
+        if let Some(focused) = self.focused() {
+            return Ok(Some(
+                finder.element_at(&focused.bus_name, &focused.path).await?,
+            ));
+        }
 
-        timeout(timeout_duration, async {
-            info!("Waiting for focus on: {}", element_path);
-            // TODO: Implement actual focus waiting via AT-SPI
-            sleep(Duration::from_millis(100)).await;
-            Ok(())
-        })
-        .await?
+        let element = finder.find_element(&"*:focused".parse()?).await?;
+        if let Some(element) = &element {
+            let found = FocusedObject::of(element);
+            // An event that arrived during the search is more recent
+            self.focused.send_if_modified(|focused| match focused {
+                Some(_) => false,
+                None => {
+                    *focused = Some(found);
+                    true
+                }
+            });
+        }
+        Ok(element)
+    }
+
+    /// Give an element keyboard focus through its Component interface and
+    /// wait until it has it
+    pub async fn focus_element(&self, element: &Element, timeout: Duration) -> Result<()> {
+        info!("Focusing element: {} ({})", element.name, element.path);
+
+        // Subscribed before grabbing, so the focus event cannot be missed
+        let focused = self.focused.subscribe();
+        let component = ComponentProxy::builder(self.connection.connection())
+            .destination(element.bus_name.as_str())?
+            .path(element.path.as_str())?
+            .build()
+            .await?;
+        if !component.grab_focus().await? {
+            bail!("{} refused focus", element.name);
+        }
+        self.wait(focused, element, timeout).await
+    }
+
+    /// Wait for an element to receive focus, failing after `timeout`
+    pub async fn wait_for_focus(&self, element: &Element, timeout: Duration) -> Result<()> {
+        info!("Waiting for focus on: {} ({})", element.name, element.path);
+        self.wait(self.focused.subscribe(), element, timeout).await
+    }
+
+    /// Complete when the element gains focus. An element focused before the
+    /// first event arrived is caught by asking it for its states.
+    async fn wait(
+        &self,
+        mut focused: watch::Receiver<Option<FocusedObject>>,
+        element: &Element,
+        timeout: Duration,
+    ) -> Result<()> {
+        let target = FocusedObject::of(element);
+        let deadline = Instant::now() + timeout;
+        if self.has_focus(&target).await {
+            return Ok(());
+        }
+        loop {
+            let woke = match self.is_tracking() {
+                true => timeout_at(deadline, focused.changed()).await.map(|_| ()),
+                false => timeout_at(deadline, sleep(POLL_INTERVAL)).await,
+            };
+            let timed_out = woke.is_err();
+            let gained = focused.borrow_and_update().as_ref() == Some(&target);
+            // A missed event still shows in the element's states
+            let polled = timed_out || !self.is_tracking();
+            if gained || (polled && self.has_focus(&target).await) {
+                debug!("{} gained focus", target.path);
+                return Ok(());
+            }
+            if timed_out {
+                bail!(
+                    "{} did not receive focus within {} ms",
+                    element.name,
+                    timeout.as_millis()
+                );
+            }
+        }
+    }
+
+    async fn has_focus(&self, target: &FocusedObject) -> bool {
+        if self.focused().as_ref() == Some(target) {
+            return true;
+        }
+        let states = async {
+            let proxy = AccessibleProxy::builder(self.connection.connection())
+                .destination(target.bus_name.as_str())?
+                .path(target.path.as_str())?
+                .build()
+                .await?;
+            anyhow::Ok(proxy.get_state().await?)
+        };
+        match states.await {
+            Ok(states) => states.contains(State::Focused),
+            Err(e) => {
+                debug!("Could not read states of {}: {:#}", target.path, e);
+                false
+            }
+        }
+    }
+}
+
+fn focus_change(event: &Event) -> Option<FocusChange> {
+    match event {
+        Event::Object(ObjectEvents::StateChanged(e)) => match e.state {
+            State::Focused if e.enabled == 1 => Some(FocusChange::Gained((&e.item).into())),
+            State::Focused | State::Defunct => Some(FocusChange::Lost((&e.item).into())),
+            _ => None,
+        },
+        // Older toolkits send focus events alongside the state change
+        Event::Focus(FocusEvents::Focus(e)) => Some(FocusChange::Gained((&e.item).into())),
+        _ => None,
+    }
+}
+
+/// Update the tracked focus; false when nothing changed
+fn apply(focused: &mut Option<FocusedObject>, change: FocusChange) -> bool {
+    match change {
+        FocusChange::Gained(object) if focused.as_ref() != Some(&object) => {
+            *focused = Some(object);
+            true
+        }
+        // Focus leaving an object is followed by it arriving elsewhere, but
+        // not always, e.g. when focus moves to an inaccessible window
+        FocusChange::Lost(object) if focused.as_ref() == Some(&object) => {
+            *focused = None;
+            true
+        }
+        _ => false,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    #[tokio::test]
-    #[ignore] // Requires AT-SPI bus to be running
-    async fn test_focus_manager_creation() {
-        // Test would require a real AT-SPI connection
+    use super::*;
+    use atspi::events::focus::FocusEvent;
+    use atspi::events::object::StateChangedEvent;
+
+    fn object(path: &str) -> ObjectRef {
+        ObjectRef {
+            path: path.try_into().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn state(path: &str, state: State, enabled: i32) -> Event {
+        StateChangedEvent {
+            item: object(path),
+            state,
+            enabled,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_focus_tracking() {
+        let entry = FocusedObject::from(&object("/app/entry"));
+        let button = FocusedObject::from(&object("/app/button"));
+        let mut focused = None;
+        let mut observe = |event: Event| match focus_change(&event) {
+            Some(change) => apply(&mut focused, change).then(|| focused.clone()),
+            None => None,
+        };
+
+        assert_eq!(
+            observe(state("/app/entry", State::Focused, 1)),
+            Some(Some(entry.clone()))
+        );
+        assert_eq!(observe(state("/app/entry", State::Focused, 1)), None);
+        assert_eq!(observe(state("/app/entry", State::Showing, 0)), None);
+        // Focus moving reports the loss after the gain as often as before it
+        assert_eq!(
+            observe(state("/app/button", State::Focused, 1)),
+            Some(Some(button.clone()))
+        );
+        assert_eq!(observe(state("/app/entry", State::Focused, 0)), None);
+        assert_eq!(observe(state("/app/button", State::Defunct, 1)), Some(None));
+        assert_eq!(
+            observe(
+                FocusEvent {
+                    item: object("/app/entry")
+                }
+                .into()
+            ),
+            Some(Some(entry))
+        );
     }
 }
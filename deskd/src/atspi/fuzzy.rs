@@ -0,0 +1,99 @@
+// Loose comparison of element names
+//
+// Labels differ from what a script expects in small ways: case, spacing,
+// mnemonic markers (`_Save`, `&Save`) and ellipses ("Save As…" or
+// "Save As..."). Fuzzy matching compares names with those removed.
+
+/// Name similarity below which an element is not worth suggesting
+pub const MIN_SIMILARITY: f64 = 0.6;
+
+/// Lowercase, single-spaced, without mnemonic markers or ellipses
+pub fn normalize(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // `_` and `&` mark the mnemonic letter that follows
+            '_' | '&' if chars.peek().is_some_and(|next| next.is_alphanumeric()) => {}
+            '…' => {}
+            // A run of dots is an ellipsis, a single one is kept
+            '.' if chars.peek() == Some(&'.') => {
+                while chars.peek() == Some(&'.') {
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => {
+                if !normalized.is_empty() && !normalized.ends_with(' ') {
+                    normalized.push(' ');
+                }
+            }
+            c => normalized.extend(c.to_lowercase()),
+        }
+    }
+    normalized.trim_end().to_string()
+}
+
+/// How close an element's name is to the wanted one, from 0 to 1. Names
+/// equal after normalizing score at least 0.9; only identical ones score 1.
+pub fn similarity(wanted: &str, name: &str) -> f64 {
+    let loose = ratio(&normalize(wanted), &normalize(name));
+    let exact = ratio(wanted.trim(), name.trim());
+    0.9 * loose + 0.1 * exact
+}
+
+/// 1 minus the edit distance relative to the longer string
+fn ratio(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    // Lengths alone can rule out a useful score without the O(n*m) distance
+    let bound = 1.0 - a.len().abs_diff(b.len()) as f64 / longest as f64;
+    if bound < MIN_SIMILARITY {
+        return bound;
+    }
+    1.0 - edit_distance(&a, &b) as f64 / longest as f64
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  Save   As… "), "save as");
+        assert_eq!(normalize("Save As..."), "save as");
+        assert_eq!(normalize("_Save"), "save");
+        assert_eq!(normalize("&Open File"), "open file");
+        assert_eq!(normalize("snake_case"), "snakecase");
+        assert_eq!(normalize("Version 1.2"), "version 1.2");
+        assert_eq!(normalize("a_ _b"), "a_ b");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("Save", "Save"), 1.0);
+        assert!(similarity("Save As...", "Save _As…") >= 0.9);
+        assert!(similarity("Save As...", "Save _As…") < 1.0);
+        assert!(similarity("Submit", "Submt") > MIN_SIMILARITY);
+        assert!(similarity("OK", "Cancel") < MIN_SIMILARITY);
+        assert!(similarity("OK", &"x".repeat(10_000)) < MIN_SIMILARITY);
+    }
+}
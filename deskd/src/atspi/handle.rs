@@ -0,0 +1,119 @@
+// Element handles
+//
+// A handle names one element across requests: the owning application's bus
+// name, the object's path, and a fingerprint of what the element is. Toolkits
+// reuse object paths, so the fingerprint is what tells a handle still points
+// at the element it was made for.
+use anyhow::{bail, Result};
+use atspi::Role;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::str::FromStr;
+
+/// Hex digits in a fingerprint
+const FINGERPRINT_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementHandle {
+    pub bus_name: String,
+    pub path: String,
+    pub fingerprint: String,
+}
+
+/// Fingerprint of an element: its application, role and name, and the roles
+/// of its ancestors from the application root down. Ancestor names are left
+/// out, window titles change as documents are edited.
+pub fn fingerprint(app: &str, role: Role, name: &str, ancestors: &[Role]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(app.as_bytes());
+    hasher.update([0]);
+    for ancestor in ancestors {
+        hasher.update((*ancestor as u32).to_le_bytes());
+    }
+    hasher.update([0]);
+    hasher.update((role as u32).to_le_bytes());
+    hasher.update(name.as_bytes());
+    hex::encode(&hasher.finalize()[..FINGERPRINT_LEN / 2])
+}
+
+/// `<bus name><object path>#<fingerprint>`; bus names never contain `/`
+impl fmt::Display for ElementHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}#{}", self.bus_name, self.path, self.fingerprint)
+    }
+}
+
+impl FromStr for ElementHandle {
+    type Err = anyhow::Error;
+
+    fn from_str(handle: &str) -> Result<Self> {
+        let parsed = handle.split_once('#').and_then(|(location, fingerprint)| {
+            let (bus_name, path) = location.split_at(location.find('/')?);
+            let valid = !bus_name.is_empty()
+                && fingerprint.len() == FINGERPRINT_LEN
+                && fingerprint.bytes().all(|b| b.is_ascii_hexdigit());
+            valid.then(|| ElementHandle {
+                bus_name: bus_name.to_string(),
+                path: path.to_string(),
+                fingerprint: fingerprint.to_ascii_lowercase(),
+            })
+        });
+        match parsed {
+            Some(handle) => Ok(handle),
+            None => bail!("Invalid element handle: {}", handle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_round_trip() {
+        let handle = ElementHandle {
+            bus_name: ":1.42".to_string(),
+            path: "/org/a11y/atspi/accessible/17".to_string(),
+            fingerprint: fingerprint("gedit", Role::PushButton, "OK", &[Role::Application]),
+        };
+        let text = handle.to_string();
+        assert!(text.starts_with(":1.42/org/a11y/atspi/accessible/17#"));
+        assert_eq!(text.parse::<ElementHandle>().unwrap(), handle);
+
+        for invalid in [
+            "",
+            ":1.42",
+            ":1.42/path",
+            "/path#0123456789abcdef",
+            ":1.42/path#0123",
+            ":1.42/path#0123456789abcdeg",
+        ] {
+            assert!(invalid.parse::<ElementHandle>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let ancestors = [Role::Application, Role::Frame, Role::Dialog];
+        let ok = fingerprint("gedit", Role::PushButton, "OK", &ancestors);
+        assert_eq!(ok.len(), FINGERPRINT_LEN);
+        assert_eq!(ok, fingerprint("gedit", Role::PushButton, "OK", &ancestors));
+
+        assert_ne!(
+            ok,
+            fingerprint("gedit", Role::PushButton, "Cancel", &ancestors)
+        );
+        assert_ne!(
+            ok,
+            fingerprint("gedit", Role::ToggleButton, "OK", &ancestors)
+        );
+        assert_ne!(
+            ok,
+            fingerprint("firefox", Role::PushButton, "OK", &ancestors)
+        );
+        assert_ne!(
+            ok,
+            fingerprint("gedit", Role::PushButton, "OK", &ancestors[..2])
+        );
+    }
+}
@@ -1,8 +1,14 @@
 // Input operations (typing, clicking) via AT-SPI
-use anyhow::Result;
+use super::action;
+use super::element::Element;
+use super::text;
+use crate::config::profiles::TypingStrategy;
+use anyhow::{bail, Result};
+use atspi::connection::AccessibilityConnection;
+use std::str::FromStr;
 use std::time::Duration;
 use tokio::time::sleep;
-use tracing::info;
+use tracing::{debug, info};
 
 /// Input handler for AT-SPI-based operations
 #[allow(dead_code)]
@@ -50,9 +56,81 @@ impl InputHandler {
         result
     }
 
-    /// Click an element using AT-SPI action interface
+    /// Type text into an element with the first strategy that works.
+    /// EditableText replaces what the element held and reads it back;
+    /// keystrokes and pastes go through the input backends, which do not
+    /// send input yet.
+    pub async fn type_into(
+        &self,
+        connection: &AccessibilityConnection,
+        element: &Element,
+        text: &str,
+        secure: bool,
+        strategies: &[TypingStrategy],
+    ) -> Result<Typed> {
+        let mut failures = Vec::new();
+        for &strategy in strategies {
+            match strategy {
+                TypingStrategy::EditableText => {
+                    match text::replace(connection, element, None, None, text).await {
+                        // The text is in place, so another strategy would
+                        // only type it twice
+                        Ok((_, readback)) => {
+                            return match readback.checked(element)? {
+                                true => Ok(Typed::Verified(strategy)),
+                                false => Ok(Typed::Unverified(strategy)),
+                            }
+                        }
+                        Err(e) => {
+                            debug!("Typing with {} failed: {:#}", strategy, e);
+                            failures.push(format!("{}: {:#}", strategy, e));
+                        }
+                    }
+                }
+                TypingStrategy::Keystrokes | TypingStrategy::ClipboardPaste => {
+                    self.type_text(&element.path, text, secure).await?;
+                    return Ok(Typed::Simulated(strategy));
+                }
+            }
+        }
+        match failures.is_empty() {
+            true => bail!("No typing strategy to try"),
+            false => bail!(
+                "Could not type into {}: {}",
+                element.name,
+                failures.join("; ")
+            ),
+        }
+    }
+
+    /// Click an element. A left click invokes the element's click action
+    /// when it has one, which also reaches covered and offscreen widgets;
+    /// otherwise pointer input is synthesized at the element.
+    pub async fn click(
+        &self,
+        connection: &AccessibilityConnection,
+        element: &Element,
+        button: ClickButton,
+    ) -> Result<ClickMethod> {
+        if button == ClickButton::Left {
+            match action::click_action(connection, element).await {
+                Ok(Some((index, name))) => {
+                    // A failed call may still have run the action, so no
+                    // pointer click follows that could click a second time
+                    action::invoke(connection, element, index, &name).await?;
+                    return Ok(ClickMethod::Action(name));
+                }
+                Ok(None) => debug!("{} has no click action", element.name),
+                Err(e) => debug!("Could not list actions of {}: {:#}", element.name, e),
+            }
+        }
+        self.pointer_click(&element.path, button).await?;
+        Ok(ClickMethod::Pointer)
+    }
+
+    /// Click at an element with synthesized pointer input
     /// This is a stub implementation for Phase 2
-    pub async fn click(&self, _element_path: &str, button: ClickButton) -> Result<()> {
+    async fn pointer_click(&self, _element_path: &str, button: ClickButton) -> Result<()> {
         info!("Clicking element with button: {:?}", button);
 
         if self.click_delay_ms > 0 {
@@ -63,19 +141,39 @@ impl InputHandler {
         Ok(())
     }
 
-    /// Double-click an element
+    /// Double-click an element. Always pointer input: invoking an action
+    /// twice is not a double click.
     pub async fn double_click(&self, element_path: &str) -> Result<()> {
         info!("Double-clicking element");
 
         // Perform two clicks with short delay
-        self.click(element_path, ClickButton::Left).await?;
+        self.pointer_click(element_path, ClickButton::Left).await?;
         sleep(Duration::from_millis(100)).await;
-        self.click(element_path, ClickButton::Left).await?;
+        self.pointer_click(element_path, ClickButton::Left).await?;
 
         Ok(())
     }
 }
 
+/// How typed text reached its element
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Typed {
+    /// Read back from the element as typed
+    Verified(TypingStrategy),
+    /// Entered, but the element hides its text (password fields)
+    Unverified(TypingStrategy),
+    /// Not sent: the strategy's input backend is not implemented yet
+    Simulated(TypingStrategy),
+}
+
+/// How a click reached its element
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClickMethod {
+    /// The element's click action, by name
+    Action(String),
+    Pointer,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ClickButton {
@@ -84,6 +182,22 @@ pub enum ClickButton {
     Middle,
 }
 
+impl FromStr for ClickButton {
+    type Err = anyhow::Error;
+
+    fn from_str(button: &str) -> Result<Self> {
+        match button.to_ascii_lowercase().as_str() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "middle" => Ok(Self::Middle),
+            _ => bail!(
+                "Unknown mouse button: {} (expected left, right or middle)",
+                button
+            ),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,5 +213,7 @@ mod tests {
     fn test_click_button_enum() {
         assert_eq!(ClickButton::Left, ClickButton::Left);
         assert_ne!(ClickButton::Left, ClickButton::Right);
+        assert_eq!("Right".parse::<ClickButton>().unwrap(), ClickButton::Right);
+        assert!("back".parse::<ClickButton>().is_err());
     }
 }
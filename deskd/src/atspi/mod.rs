@@ -1,17 +1,30 @@
 // AT-SPI accessibility integration
 use anyhow::{Context, Result};
 use atspi::connection::AccessibilityConnection;
-use tracing::info;
+use cache::ElementCache;
+use element::ElementFinder;
+use focus::FocusManager;
+use std::sync::Arc;
+use tracing::{info, warn};
 
+pub mod action;
 pub mod cache;
 pub mod element;
+pub mod events;
 pub mod focus;
+pub mod fuzzy;
+pub mod handle;
 pub mod input;
+pub mod selector;
+pub mod text;
+pub mod tree;
 
 /// AT-SPI client for desktop automation
 #[allow(dead_code)]
 pub struct AtSpiClient {
-    connection: AccessibilityConnection,
+    connection: Arc<AccessibilityConnection>,
+    cache: Option<Arc<ElementCache>>,
+    focus: Arc<FocusManager>,
 }
 
 #[allow(dead_code)]
@@ -26,7 +39,40 @@ impl AtSpiClient {
 
         info!("Successfully connected to AT-SPI");
 
-        Ok(Self { connection })
+        let connection = Arc::new(connection);
+        Ok(Self {
+            focus: Arc::new(FocusManager::new(connection.clone())),
+            connection,
+            cache: None,
+        })
+    }
+
+    /// Follow accessibility events: they invalidate cached element lookups
+    /// and track focus. Without the events entries could go stale, so
+    /// nothing is cached when subscribing to them fails, and focus is polled.
+    pub async fn with_events(mut self, cache: ElementCache) -> Self {
+        if let Err(e) = events::subscribe(&self.connection).await {
+            warn!("Element cache and focus tracking disabled: {:#}", e);
+            return self;
+        }
+        let cache = Arc::new(cache);
+        tokio::spawn(events::watch(
+            self.connection.clone(),
+            cache.clone(),
+            self.focus.clone(),
+        ));
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The element cache, when enabled
+    pub fn cache(&self) -> Option<&ElementCache> {
+        self.cache.as_deref()
+    }
+
+    /// Focus tracking and focus changes
+    pub fn focus(&self) -> &FocusManager {
+        &self.focus
     }
 
     /// Get the accessibility connection
@@ -34,6 +80,18 @@ impl AtSpiClient {
         &self.connection
     }
 
+    /// Identifies the desktop session: the accessibility bus's GUID. Bus
+    /// names and object paths only mean something on the bus instance that
+    /// handed them out, so cached elements are kept per bus.
+    pub fn desktop_id(&self) -> String {
+        self.connection.connection().server_guid().to_string()
+    }
+
+    /// Element search over this connection
+    pub fn finder(&self) -> ElementFinder {
+        ElementFinder::new(self.connection.clone())
+    }
+
     /// Check if AT-SPI is available and responsive
     pub async fn is_available(&self) -> bool {
         // For now, if we have a connection, we consider it available
@@ -0,0 +1,756 @@
+// Selector language for locating elements
+//
+//   app[name="Firefox"] window > push-button[name^="Sub"]:enabled:nth(2)
+//
+// A selector is a chain of compounds separated by a descendant (space) or
+// child (`>`) combinator. A compound is a role (`push-button`, `app`, or `*`
+// for any) followed by attribute filters, state pseudo-classes and `:nth()`.
+// Attribute filters take an `i` flag to ignore case and an `f` flag to
+// compare fuzzily (see `fuzzy::normalize`).
+use super::element::{parse_role, parse_state, ElementSelector};
+use super::fuzzy;
+use anyhow::{bail, Result};
+use atspi::{Role, State, StateSet};
+use regex_automata::meta::Regex;
+use regex_automata::util::syntax;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An object of the accessibility tree as selectors see it. Trees are flat
+/// lists in depth-first order, so a parent always comes before its children.
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub role: Role,
+    pub name: String,
+    /// Only fetched when the selector filters on it
+    pub description: Option<String>,
+    /// Name of the owning application
+    pub app: Arc<str>,
+    pub states: StateSet,
+    /// Index of the parent node; `None` for application roots
+    pub parent: Option<usize>,
+}
+
+/// A parsed selector
+#[derive(Debug, Clone)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    /// How this step relates to the previous one; unused on the first step
+    combinator: Combinator,
+    compound: Compound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Compound {
+    role: Option<Role>,
+    attributes: Vec<AttributeFilter>,
+    states: Vec<State>,
+    excluded_states: Vec<State>,
+    /// 1-based position among the step's matches; negative counts from the end
+    nth: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct AttributeFilter {
+    attribute: Attribute,
+    operator: Operator,
+    value: String,
+    ignore_case: bool,
+    /// Compare fuzzy-normalized values
+    fuzzy: bool,
+    /// Compiled pattern for `~=`
+    regex: Option<Regex>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Attribute {
+    Name,
+    Description,
+    App,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equals,
+    Prefix,
+    Suffix,
+    Contains,
+    Matches,
+}
+
+impl Selector {
+    /// Matches every element
+    pub fn any() -> Self {
+        Self {
+            steps: vec![Step {
+                combinator: Combinator::Descendant,
+                compound: Compound::default(),
+            }],
+        }
+    }
+
+    /// Indices of the matching nodes, in tree order
+    pub fn select(&self, nodes: &[TreeNode]) -> Vec<usize> {
+        let mut matched = vec![true; nodes.len()];
+        for (i, step) in self.steps.iter().enumerate() {
+            // Whether some ancestor matched the previous step
+            let mut below = vec![false; nodes.len()];
+            let mut current = vec![false; nodes.len()];
+            for (n, node) in nodes.iter().enumerate() {
+                if let Some(parent) = node.parent {
+                    below[n] = matched[parent] || below[parent];
+                }
+                let related = i == 0
+                    || match step.combinator {
+                        Combinator::Descendant => below[n],
+                        Combinator::Child => node.parent.is_some_and(|parent| matched[parent]),
+                    };
+                current[n] = related && step.compound.matches(node);
+            }
+            if let Some(nth) = step.compound.nth {
+                let hits: Vec<usize> = (0..nodes.len()).filter(|&n| current[n]).collect();
+                let keep = if nth > 0 {
+                    hits.get(nth as usize - 1)
+                } else {
+                    hits.len()
+                        .checked_sub(nth.unsigned_abs() as usize)
+                        .and_then(|index| hits.get(index))
+                };
+                current = vec![false; nodes.len()];
+                if let Some(&keep) = keep {
+                    current[keep] = true;
+                }
+            }
+            matched = current;
+        }
+        (0..nodes.len()).filter(|&n| matched[n]).collect()
+    }
+
+    /// Whether anything in the named application can match, so whole
+    /// applications can be skipped without walking them
+    pub fn may_match_app(&self, app: &str) -> bool {
+        let first = &self.steps[0].compound;
+        first
+            .attributes
+            .iter()
+            .all(|filter| match filter.attribute {
+                Attribute::App => filter.matches_value(app),
+                // Application roots are named after the application
+                Attribute::Name if first.role == Some(Role::Application) => {
+                    filter.matches_value(app)
+                }
+                _ => true,
+            })
+    }
+
+    /// Whether matching needs element descriptions, which cost an extra
+    /// D-Bus call per object
+    pub fn uses_description(&self) -> bool {
+        self.steps.iter().any(|step| {
+            step.compound
+                .attributes
+                .iter()
+                .any(|filter| filter.attribute == Attribute::Description)
+        })
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: &TreeNode) -> bool {
+        self.role.is_none_or(|role| role == node.role)
+            && self.states.iter().all(|state| node.states.contains(*state))
+            && !self
+                .excluded_states
+                .iter()
+                .any(|state| node.states.contains(*state))
+            && self.attributes.iter().all(|filter| {
+                let value = match filter.attribute {
+                    Attribute::Name => &node.name,
+                    Attribute::Description => node.description.as_deref().unwrap_or_default(),
+                    Attribute::App => &node.app,
+                };
+                filter.matches_value(value)
+            })
+    }
+}
+
+impl AttributeFilter {
+    /// Filter from the plain name/app fields: whole value, any case
+    fn equals(attribute: Attribute, value: &str, fuzzy: bool) -> Self {
+        Self {
+            attribute,
+            operator: Operator::Equals,
+            value: value.trim().to_string(),
+            ignore_case: true,
+            fuzzy,
+            regex: None,
+        }
+    }
+
+    /// Surrounding whitespace is ignored, toolkits often pad labels
+    fn matches_value(&self, value: &str) -> bool {
+        let value = value.trim();
+        if let Some(regex) = &self.regex {
+            return match self.fuzzy {
+                true => regex.is_match(&fuzzy::normalize(value)),
+                false => regex.is_match(value),
+            };
+        }
+        let (value, wanted) = if self.fuzzy {
+            (
+                Cow::Owned(fuzzy::normalize(value)),
+                Cow::Owned(fuzzy::normalize(&self.value)),
+            )
+        } else if self.ignore_case {
+            (
+                Cow::Owned(value.to_lowercase()),
+                Cow::Owned(self.value.to_lowercase()),
+            )
+        } else {
+            (Cow::Borrowed(value), Cow::Borrowed(self.value.as_str()))
+        };
+        match self.operator {
+            Operator::Equals => value == wanted,
+            Operator::Prefix => value.starts_with(wanted.as_ref()),
+            Operator::Suffix => value.ends_with(wanted.as_ref()),
+            Operator::Contains => value.contains(wanted.as_ref()),
+            Operator::Matches => false,
+        }
+    }
+}
+
+impl From<ElementSelector> for Selector {
+    fn from(selector: ElementSelector) -> Self {
+        let mut attributes = Vec::new();
+        if let Some(name) = &selector.name {
+            attributes.push(AttributeFilter::equals(
+                Attribute::Name,
+                name,
+                selector.fuzzy,
+            ));
+        }
+        if let Some(app) = &selector.app {
+            attributes.push(AttributeFilter::equals(Attribute::App, app, false));
+        }
+        Self {
+            steps: vec![Step {
+                combinator: Combinator::Descendant,
+                compound: Compound {
+                    role: selector.role,
+                    attributes,
+                    states: selector.states.unwrap_or_default(),
+                    ..Compound::default()
+                },
+            }],
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+        }
+        .selector()
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn selector(&mut self) -> Result<Selector> {
+        self.skip_whitespace();
+        if self.at_end() {
+            return self.error("empty selector");
+        }
+
+        let mut steps = Vec::new();
+        let mut combinator = Combinator::Descendant;
+        loop {
+            let compound = self.compound()?;
+            steps.push(Step {
+                combinator,
+                compound,
+            });
+
+            let spaced = self.skip_whitespace();
+            if self.at_end() {
+                break;
+            }
+            combinator = if self.eat('>') {
+                self.skip_whitespace();
+                Combinator::Child
+            } else if spaced {
+                Combinator::Descendant
+            } else {
+                return self.error(&format!("unexpected '{}'", self.chars[self.pos]));
+            };
+        }
+        Ok(Selector { steps })
+    }
+
+    fn compound(&mut self) -> Result<Compound> {
+        let mut compound = Compound::default();
+        let start = self.pos;
+
+        if self.eat('*') {
+        } else if self.peek().is_some_and(is_ident_char) {
+            let role = self.ident();
+            compound.role = match role.as_str() {
+                "app" => Some(Role::Application),
+                _ => match parse_role(&role) {
+                    Some(role) => Some(role),
+                    None => return self.error_at(start, &format!("unknown role '{}'", role)),
+                },
+            };
+        }
+
+        loop {
+            if self.eat('[') {
+                compound.attributes.push(self.attribute()?);
+            } else if self.eat(':') {
+                self.pseudo_class(&mut compound)?;
+            } else {
+                break;
+            }
+        }
+
+        if self.pos == start {
+            return self.error("expected a role, '*', '[' or ':'");
+        }
+        Ok(compound)
+    }
+
+    fn attribute(&mut self) -> Result<AttributeFilter> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let attribute = match self.ident().as_str() {
+            "name" => Attribute::Name,
+            "description" => Attribute::Description,
+            "app" => Attribute::App,
+            "" => return self.error("expected an attribute name"),
+            other => {
+                return self.error_at(
+                    start,
+                    &format!(
+                        "unknown attribute '{}' (expected name, description or app)",
+                        other
+                    ),
+                )
+            }
+        };
+
+        self.skip_whitespace();
+        let operator = match self.peek() {
+            Some('=') => Operator::Equals,
+            Some('^') => Operator::Prefix,
+            Some('$') => Operator::Suffix,
+            Some('*') => Operator::Contains,
+            Some('~') => Operator::Matches,
+            _ => return self.error("expected =, ^=, $=, *= or ~="),
+        };
+        self.pos += 1;
+        if operator != Operator::Equals && !self.eat('=') {
+            return self.error("expected =, ^=, $=, *= or ~=");
+        }
+
+        self.skip_whitespace();
+        let value_start = self.pos;
+        let value = self.value()?;
+        self.skip_whitespace();
+        let (mut ignore_case, mut fuzzy) = (false, false);
+        loop {
+            if self.eat('i') || self.eat('I') {
+                ignore_case = true;
+            } else if self.eat('f') || self.eat('F') {
+                fuzzy = true;
+            } else {
+                break;
+            }
+            self.skip_whitespace();
+        }
+        if !self.eat(']') {
+            return self.error("expected ']'");
+        }
+
+        let regex = if operator == Operator::Matches {
+            let config = syntax::Config::new().case_insensitive(ignore_case);
+            match Regex::builder().syntax(config).build(&value) {
+                Ok(regex) => Some(regex),
+                Err(e) => return self.error_at(value_start, &format!("invalid regex: {}", e)),
+            }
+        } else {
+            None
+        };
+
+        Ok(AttributeFilter {
+            attribute,
+            operator,
+            value: if regex.is_some() {
+                value
+            } else {
+                value.trim().to_string()
+            },
+            ignore_case,
+            fuzzy,
+            regex,
+        })
+    }
+
+    /// A quoted string with backslash escapes, or a bare word
+    fn value(&mut self) -> Result<String> {
+        let Some(quote) = self.peek().filter(|c| *c == '"' || *c == '\'') else {
+            let word = self.ident();
+            if word.is_empty() {
+                return self.error("expected a value");
+            }
+            return Ok(word);
+        };
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => return self.error_at(start, "unterminated string"),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) => value.push(c),
+                        None => return self.error_at(start, "unterminated string"),
+                    }
+                }
+                Some(c) => value.push(c),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn pseudo_class(&mut self, compound: &mut Compound) -> Result<()> {
+        let start = self.pos;
+        let name = self.ident();
+        match name.as_str() {
+            "nth" => {
+                let argument = self.argument()?;
+                match argument.parse::<i64>() {
+                    Ok(0) => {
+                        return self.error_at(
+                            start,
+                            ":nth() counts from 1; use :nth(-1) for the last match",
+                        )
+                    }
+                    Ok(n) => compound.nth = Some(n),
+                    Err(_) => {
+                        return self.error_at(start, &format!("invalid index '{}'", argument))
+                    }
+                }
+            }
+            "not" => {
+                let argument = self.argument()?;
+                match parse_state(&argument) {
+                    Some(state) => compound.excluded_states.push(state),
+                    None => return self.error_at(start, &format!("unknown state '{}'", argument)),
+                }
+            }
+            "" => return self.error("expected a state or nth()"),
+            _ => match parse_state(&name) {
+                Some(state) => compound.states.push(state),
+                None => return self.error_at(start, &format!("unknown state '{}'", name)),
+            },
+        }
+        Ok(())
+    }
+
+    /// The `(...)` after a functional pseudo-class
+    fn argument(&mut self) -> Result<String> {
+        if !self.eat('(') {
+            return self.error("expected '('");
+        }
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.pos += 1;
+        }
+        let argument: String = self.chars[start..self.pos].iter().collect();
+        if !self.eat(')') {
+            return self.error("expected ')'");
+        }
+        Ok(argument.trim().to_string())
+    }
+
+    fn ident(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_char) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T> {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at<T>(&self, pos: usize, message: &str) -> Result<T> {
+        bail!("Invalid selector at column {}: {}", pos + 1, message)
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// `HasTooltip` -> `has-tooltip`
+fn kebab(name: &str) -> String {
+    let mut kebab = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() && !kebab.is_empty() {
+            kebab.push('-');
+        }
+        kebab.push(c.to_ascii_lowercase());
+    }
+    kebab
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str(match step.combinator {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => " > ",
+                })?;
+            }
+            write!(f, "{}", step.compound)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.role {
+            Some(Role::Application) => f.write_str("app")?,
+            Some(role) => f.write_str(&role.name().replace(' ', "-"))?,
+            None => f.write_str("*")?,
+        }
+        for filter in &self.attributes {
+            write!(f, "{}", filter)?;
+        }
+        for state in &self.states {
+            write!(f, ":{}", kebab(&format!("{:?}", state)))?;
+        }
+        for state in &self.excluded_states {
+            write!(f, ":not({})", kebab(&format!("{:?}", state)))?;
+        }
+        if let Some(nth) = self.nth {
+            write!(f, ":nth({})", nth)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AttributeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attribute = match self.attribute {
+            Attribute::Name => "name",
+            Attribute::Description => "description",
+            Attribute::App => "app",
+        };
+        let operator = match self.operator {
+            Operator::Equals => "=",
+            Operator::Prefix => "^=",
+            Operator::Suffix => "$=",
+            Operator::Contains => "*=",
+            Operator::Matches => "~=",
+        };
+        let value = self.value.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "[{}{}\"{}\"", attribute, operator, value)?;
+        if self.ignore_case {
+            f.write_str(" i")?;
+        }
+        if self.fuzzy {
+            f.write_str(" f")?;
+        }
+        f.write_str("]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(
+        role: Role,
+        name: &str,
+        app: &str,
+        states: &[State],
+        parent: Option<usize>,
+    ) -> TreeNode {
+        TreeNode {
+            role,
+            name: name.to_string(),
+            description: None,
+            app: Arc::from(app),
+            states: states.iter().fold(StateSet::empty(), |mut set, state| {
+                set.insert(*state);
+                set
+            }),
+            parent,
+        }
+    }
+
+    /// Two applications, each with a dialog of buttons
+    fn tree() -> Vec<TreeNode> {
+        use State::{Enabled, Focused};
+        vec![
+            node(Role::Application, "Firefox", "Firefox", &[], None), // 0
+            node(Role::Frame, "Mozilla Firefox", "Firefox", &[], Some(0)),
+            node(Role::Dialog, "Save As", "Firefox", &[], Some(1)),
+            node(Role::PushButton, "OK", "Firefox", &[Enabled], Some(2)),
+            node(Role::Panel, "", "Firefox", &[], Some(2)),
+            node(
+                Role::PushButton,
+                "OK ",
+                "Firefox",
+                &[Enabled, Focused],
+                Some(4),
+            ), // 5
+            node(Role::PushButton, "Submit", "Firefox", &[], Some(1)),
+            node(Role::Application, "gedit", "gedit", &[], None), // 7
+            node(Role::Frame, "Untitled", "gedit", &[], Some(7)),
+            node(Role::PushButton, "OK", "gedit", &[Enabled], Some(8)),
+        ]
+    }
+
+    fn select(selector: &str) -> Vec<usize> {
+        selector.parse::<Selector>().unwrap().select(&tree())
+    }
+
+    #[test]
+    fn test_combinators() {
+        assert_eq!(select("push-button[name=OK]"), vec![3, 5, 9]);
+        assert_eq!(
+            select(r#"app[name="Firefox"] push-button[name="OK"]"#),
+            vec![3, 5]
+        );
+        assert_eq!(select("dialog > push-button"), vec![3]);
+        assert_eq!(select("frame > push-button"), vec![6, 9]);
+        assert_eq!(select("app frame dialog panel push-button"), vec![5]);
+        assert_eq!(select("dialog>panel>*"), vec![5]);
+        assert_eq!(select("dialog app"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_attribute_operators() {
+        assert_eq!(select("[name^=Sub]"), vec![6]);
+        assert_eq!(select("[name$=\"Firefox\"]"), vec![0, 1]);
+        assert_eq!(select("[name*=\"As\"]"), vec![2]);
+        assert_eq!(select("[name=ok]"), Vec::<usize>::new());
+        assert_eq!(select("[name=ok i]"), vec![3, 5, 9]);
+        assert_eq!(select("[name~=\"^(OK|Submit)$\"]"), vec![3, 5, 6, 9]);
+        assert_eq!(select("[name~=\"^unt\" i]"), vec![8]);
+        assert_eq!(select("push-button[app=gedit]"), vec![9]);
+        assert_eq!(select("[name=\"_ok\" f]"), vec![3, 5, 9]);
+        assert_eq!(select("[name=\"save as…\" f]"), vec![2]);
+        assert_eq!(select("[name^=\"MOZILLA  \" f i]"), vec![1]);
+    }
+
+    #[test]
+    fn test_states_and_indexing() {
+        assert_eq!(select("push-button:enabled"), vec![3, 5, 9]);
+        assert_eq!(select("push-button:enabled:focused"), vec![5]);
+        assert_eq!(select("push-button:not(focused)"), vec![3, 6, 9]);
+        assert_eq!(select("push-button[name=OK]:nth(2)"), vec![5]);
+        assert_eq!(select("push-button:nth(-1)"), vec![9]);
+        assert_eq!(select("push-button:nth(9)"), Vec::<usize>::new());
+        assert_eq!(select("frame:nth(2) push-button"), vec![9]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |selector: &str| selector.parse::<Selector>().unwrap_err().to_string();
+        assert_eq!(error(""), "Invalid selector at column 1: empty selector");
+        assert!(error("widget").contains("column 1: unknown role 'widget'"));
+        assert!(error("[title=x]").contains("column 2: unknown attribute 'title'"));
+        assert!(error("[name=\"OK]").contains("column 7: unterminated string"));
+        assert!(error("push-button:sleepy").contains("column 13: unknown state 'sleepy'"));
+        assert!(error("*:nth(0)").contains(":nth() counts from 1"));
+        assert!(error("[name~=\"(\"]").contains("invalid regex"));
+        assert!(error("frame >").contains("expected a role"));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let text = r#"app[name="Firefox"] dialog > push-button[name^="Sub" i]:enabled:not(focused):nth(2)"#;
+        let selector: Selector = text.parse().unwrap();
+        assert_eq!(selector.to_string(), text);
+        assert_eq!(
+            "[name='say \"hi\"']"
+                .parse::<Selector>()
+                .unwrap()
+                .to_string(),
+            r#"*[name="say \"hi\""]"#
+        );
+    }
+
+    #[test]
+    fn test_app_pruning() {
+        let selector: Selector = "app[name=gedit] push-button".parse().unwrap();
+        assert!(selector.may_match_app("gedit"));
+        assert!(!selector.may_match_app("Firefox"));
+        let selector: Selector = "frame[name=gedit]".parse().unwrap();
+        assert!(selector.may_match_app("Firefox"));
+
+        let selector: Selector = ElementSelector::by_name("ok").in_app("FIREFOX").into();
+        assert!(selector.may_match_app("firefox"));
+        assert!(!selector.may_match_app("gedit"));
+        assert_eq!(selector.select(&tree()), vec![3, 5]);
+    }
+}
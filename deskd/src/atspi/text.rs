@@ -0,0 +1,311 @@
+// Text content via the AT-SPI Text and EditableText interfaces
+//
+// Offsets count characters, not bytes, and ranges end before their end
+// offset. Editing through EditableText goes straight to the widget: no
+// keyboard layout is involved, and a large input is one call rather than a
+// key event per character. Every edit is read back to check it took effect.
+use super::element::{self, Element};
+use anyhow::{bail, Result};
+use atspi::connection::AccessibilityConnection;
+use atspi::proxy::editable_text::EditableTextProxy;
+use atspi::proxy::text::TextProxy;
+use atspi::Interface;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// Characters `start..end` of an element's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextRange {
+    pub start: i32,
+    pub end: i32,
+}
+
+/// Caret and selection of a text element
+#[derive(Debug, Clone, Serialize)]
+pub struct TextCursor {
+    pub caret: i32,
+    /// The first selected range; none when nothing is selected
+    pub selection: Option<TextRange>,
+    pub character_count: i32,
+}
+
+/// Whether an edit read back as intended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readback {
+    Matched,
+    /// The element hides its text, e.g. a password field
+    Unreadable,
+    Differs,
+}
+
+impl Readback {
+    /// Whether the edit could be checked; an error when it did not take
+    /// effect as intended
+    pub fn checked(self, element: &Element) -> Result<bool> {
+        match self {
+            Self::Matched => Ok(true),
+            Self::Unreadable => Ok(false),
+            // The text itself stays out of errors, it may be sensitive
+            Self::Differs => bail!("{} does not read back as edited", element.name),
+        }
+    }
+}
+
+/// Text of the element, all of it or the characters in `start..end`
+pub async fn get_text(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    start: Option<i32>,
+    end: Option<i32>,
+) -> Result<(String, TextRange)> {
+    let proxy = text_proxy(connection, element).await?;
+    let range = text_range(start, end, proxy.character_count().await?)?;
+    Ok((proxy.get_text(range.start, range.end).await?, range))
+}
+
+pub async fn cursor(connection: &AccessibilityConnection, element: &Element) -> Result<TextCursor> {
+    let proxy = text_proxy(connection, element).await?;
+    let selection = match proxy.get_nselections().await? {
+        0 => None,
+        _ => {
+            let (start, end) = proxy.get_selection(0).await?;
+            Some(TextRange { start, end })
+        }
+    };
+    Ok(TextCursor {
+        caret: proxy.caret_offset().await?,
+        selection,
+        character_count: proxy.character_count().await?,
+    })
+}
+
+/// Move the caret; offsets past the end of the text are rejected
+pub async fn set_caret(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    offset: i32,
+) -> Result<()> {
+    let proxy = text_proxy(connection, element).await?;
+    text_range(Some(offset), Some(offset), proxy.character_count().await?)?;
+    if !proxy.set_caret_offset(offset).await? {
+        bail!("{} refused to move its caret", element.name);
+    }
+    Ok(())
+}
+
+/// Select `range`, replacing any selection; an empty range clears it
+pub async fn set_selection(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    range: TextRange,
+) -> Result<()> {
+    let proxy = text_proxy(connection, element).await?;
+    text_range(
+        Some(range.start),
+        Some(range.end),
+        proxy.character_count().await?,
+    )?;
+    let selections = proxy.get_nselections().await?;
+    let done = match (range.start == range.end, selections) {
+        (true, 0) => true,
+        (true, _) => proxy.remove_selection(0).await?,
+        (false, 0) => proxy.add_selection(range.start, range.end).await?,
+        (false, _) => proxy.set_selection(0, range.start, range.end).await?,
+    };
+    if !done {
+        bail!("{} refused to change its selection", element.name);
+    }
+    Ok(())
+}
+
+/// Replace the characters in `start..end`, by default all of them, with
+/// `text`. Inserting is replacing an empty range and deleting is replacing
+/// with nothing. Returns the range the new text occupies.
+pub async fn replace(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    start: Option<i32>,
+    end: Option<i32>,
+    text: &str,
+) -> Result<(TextRange, Readback)> {
+    let proxy = text_proxy(connection, element).await?;
+    let editable = editable_proxy(connection, element).await?;
+    let before = proxy.get_text(0, proxy.character_count().await?).await?;
+    let range = text_range(start, end, char_count(&before))?;
+    let length = char_count(text);
+    info!(
+        "Replacing characters {}..{} of {} with {} characters",
+        range.start, range.end, element.name, length
+    );
+
+    let done = if range.start == 0 && range.end == char_count(&before) {
+        editable.set_text_contents(text).await?
+    } else {
+        let deleted =
+            range.start == range.end || editable.delete_text(range.start, range.end).await?;
+        deleted
+            && (text.is_empty()
+                || editable
+                    .insert_text(range.start, text, insert_length(text))
+                    .await?)
+    };
+    if !done {
+        bail!("{} refused the edit", element.name);
+    }
+
+    let readback = verify(&proxy, element, &spliced(&before, range, text)).await?;
+    let inserted = TextRange {
+        start: range.start,
+        end: range.start + length,
+    };
+    Ok((inserted, readback))
+}
+
+/// Insert `text` at `position`, by default at the caret
+pub async fn insert(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    position: Option<i32>,
+    text: &str,
+) -> Result<(TextRange, Readback)> {
+    let position = match position {
+        Some(position) => position,
+        None => {
+            text_proxy(connection, element)
+                .await?
+                .caret_offset()
+                .await?
+        }
+    };
+    replace(connection, element, Some(position), Some(position), text).await
+}
+
+/// Compare the element's text with what an edit should have left. Password
+/// fields show placeholder characters instead, so they cannot be checked.
+async fn verify(proxy: &TextProxy<'_>, element: &Element, expected: &str) -> Result<Readback> {
+    if element.role == "PasswordText" {
+        return Ok(Readback::Unreadable);
+    }
+    let after = proxy.get_text(0, proxy.character_count().await?).await?;
+    if after != expected {
+        debug!(
+            "{} has {} characters after the edit, expected {}",
+            element.name,
+            char_count(&after),
+            char_count(expected)
+        );
+        return Ok(Readback::Differs);
+    }
+    Ok(Readback::Matched)
+}
+
+async fn text_proxy<'a>(
+    connection: &'a AccessibilityConnection,
+    element: &'a Element,
+) -> Result<TextProxy<'a>> {
+    require(connection, element, Interface::Text).await?;
+    Ok(TextProxy::builder(connection.connection())
+        .destination(element.bus_name.as_str())?
+        .path(element.path.as_str())?
+        .build()
+        .await?)
+}
+
+async fn editable_proxy<'a>(
+    connection: &'a AccessibilityConnection,
+    element: &'a Element,
+) -> Result<EditableTextProxy<'a>> {
+    require(connection, element, Interface::EditableText).await?;
+    Ok(EditableTextProxy::builder(connection.connection())
+        .destination(element.bus_name.as_str())?
+        .path(element.path.as_str())?
+        .build()
+        .await?)
+}
+
+async fn require(
+    connection: &AccessibilityConnection,
+    element: &Element,
+    interface: Interface,
+) -> Result<()> {
+    let interfaces = element::interfaces(connection, &element.bus_name, &element.path).await?;
+    if !interfaces.contains(interface) {
+        bail!("{} has no {:?} interface", element.name, interface);
+    }
+    Ok(())
+}
+
+/// Resolve an optional range against a text of `count` characters
+fn text_range(start: Option<i32>, end: Option<i32>, count: i32) -> Result<TextRange> {
+    let range = TextRange {
+        start: start.unwrap_or(0),
+        end: end.unwrap_or(count),
+    };
+    if range.start < 0 || range.start > range.end {
+        bail!("Invalid text range {}..{}", range.start, range.end);
+    }
+    if range.end > count {
+        bail!(
+            "Offset {} is past the end of the text ({} characters)",
+            range.end,
+            count
+        );
+    }
+    Ok(range)
+}
+
+fn char_count(text: &str) -> i32 {
+    text.chars().count() as i32
+}
+
+/// The length `InsertText` takes. Unlike offsets it counts bytes: GTK and
+/// ATK cut the text at that many bytes, so a character count would drop the
+/// end of non-ASCII text.
+fn insert_length(text: &str) -> i32 {
+    text.len() as i32
+}
+
+/// `before` with the characters in `range` replaced by `text`
+fn spliced(before: &str, range: TextRange, text: &str) -> String {
+    let chars: Vec<char> = before.chars().collect();
+    let (start, end) = (range.start as usize, range.end as usize);
+    chars[..start]
+        .iter()
+        .copied()
+        .chain(text.chars())
+        .chain(chars[end..].iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_range() {
+        let range = |start, end| text_range(start, end, 5).map(|r| (r.start, r.end)).ok();
+        assert_eq!(range(None, None), Some((0, 5)));
+        assert_eq!(range(Some(2), None), Some((2, 5)));
+        assert_eq!(range(None, Some(3)), Some((0, 3)));
+        assert_eq!(range(Some(5), Some(5)), Some((5, 5)));
+        assert_eq!(range(Some(3), Some(2)), None);
+        assert_eq!(range(Some(-1), None), None);
+        assert_eq!(range(None, Some(6)), None);
+    }
+
+    #[test]
+    fn test_insert_length_counts_bytes() {
+        assert_eq!(insert_length("Save"), 4);
+        assert_eq!(insert_length("Grüße"), 7);
+        assert_eq!(insert_length("日本"), 6);
+    }
+
+    #[test]
+    fn test_spliced_counts_characters() {
+        let range = |start, end| TextRange { start, end };
+        assert_eq!(spliced("Grüße", range(2, 4), "ss"), "Grsse");
+        assert_eq!(spliced("Grüße", range(5, 5), "!"), "Grüße!");
+        assert_eq!(spliced("Grüße", range(0, 5), ""), "");
+        assert_eq!(spliced("", range(0, 0), "日本"), "日本");
+    }
+}
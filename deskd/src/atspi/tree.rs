@@ -0,0 +1,349 @@
+// Accessibility tree dumps
+//
+// Everything needed to write selectors for an application: the tree below a
+// desktop, application or window, with each object's role, name, states,
+// bounds, actions and interfaces.
+use super::action;
+use super::element::{
+    self, ElementBounds, ElementFinder, ElementSelector, Tree, APPLICATION_TIMEOUT,
+};
+use super::selector::{Selector, TreeNode};
+use anyhow::Result;
+use atspi::Interface;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, info, warn};
+
+/// One object of a dumped tree
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TreeDumpNode {
+    pub role: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub states: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<ElementBounds>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+    pub interfaces: Vec<String>,
+    pub handle: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeDumpNode>,
+}
+
+/// Part of the tree to dump
+#[derive(Debug, Clone, Default)]
+pub struct TreeScope {
+    /// Application name, as matched by `ElementSelector::in_app`
+    pub app: Option<String>,
+    /// Case-insensitive substring of a top-level window's name
+    pub window: Option<String>,
+    /// Keep only objects matching this selector, with their ancestors
+    pub filter: Option<Selector>,
+}
+
+impl TreeScope {
+    /// Everything in the scope's applications
+    fn walk_selector(&self) -> Selector {
+        match &self.app {
+            Some(app) => ElementSelector {
+                name: None,
+                role: None,
+                states: None,
+                app: Some(app.clone()),
+                fuzzy: false,
+            }
+            .into(),
+            None => Selector::any(),
+        }
+    }
+
+    /// Short description of the scope, stored with snapshots
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(app) = &self.app {
+            parts.push(format!("app={}", app));
+        }
+        if let Some(window) = &self.window {
+            parts.push(format!("window={}", window));
+        }
+        if let Some(filter) = &self.filter {
+            parts.push(format!("filter={}", filter));
+        }
+        match parts.is_empty() {
+            true => "desktop".to_string(),
+            false => parts.join(" "),
+        }
+    }
+}
+
+/// Dumped roots: applications, or the matching windows
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeDump {
+    pub roots: Vec<TreeDumpNode>,
+    pub nodes: usize,
+    /// Applications that did not answer in time and are only partly dumped,
+    /// or dumped without bounds, actions and interfaces
+    pub incomplete: Vec<String>,
+}
+
+impl ElementFinder {
+    /// Dump the objects in scope. Depth counts from the roots of the dump:
+    /// applications, or windows when one is named.
+    pub async fn dump(&self, scope: &TreeScope) -> Result<TreeDump> {
+        info!("Dumping accessibility tree: {}", scope.describe());
+
+        let walk_scope = scope.walk_selector();
+        let finder = ElementFinder {
+            connection: self.connection.clone(),
+            max_depth: self.max_depth + usize::from(scope.window.is_some()),
+        };
+        let tree = finder.walk_with(&walk_scope, true).await?;
+
+        // Each application gets APPLICATION_TIMEOUT for the queries below
+        // as well; one that ran out, here or during the walk, keeps only
+        // what the walk found
+        let kept = kept_nodes(&tree.nodes, &walk_scope, scope);
+        let mut incomplete = tree.incomplete.clone();
+        let mut deadlines: HashMap<Arc<str>, Instant> = HashMap::new();
+        let mut dumped = Vec::new();
+        for index in (0..tree.nodes.len()).filter(|&index| kept[index]) {
+            let mut node = walked_node(&tree, index);
+            let app = &tree.nodes[index].app;
+            if !incomplete.iter().any(|name| **name == **app) {
+                let deadline = *deadlines
+                    .entry(app.clone())
+                    .or_insert_with(|| Instant::now() + APPLICATION_TIMEOUT);
+                let described = self.describe_node(&tree, index, &mut node);
+                if timeout_at(deadline, described).await.is_err() {
+                    warn!(
+                        "Application {} did not answer within {:?}; dumping what the walk found",
+                        app, APPLICATION_TIMEOUT
+                    );
+                    incomplete.push(app.to_string());
+                }
+            }
+            dumped.push(node);
+        }
+
+        let nodes = dumped.len();
+        debug!("Dumped {} objects", nodes);
+        Ok(TreeDump {
+            roots: nest(&tree.nodes, &kept, dumped),
+            nodes,
+            incomplete,
+        })
+    }
+
+    /// Add the properties the walk did not fetch. An object that disappeared
+    /// since the walk keeps what the walk found.
+    async fn describe_node(&self, tree: &Tree, index: usize, dumped: &mut TreeDumpNode) {
+        let object = &tree.objects[index];
+        let (bus_name, path) = (object.name.as_str(), object.path.as_str());
+        let interfaces = match self.interfaces(bus_name, path).await {
+            Ok(interfaces) => interfaces,
+            Err(e) => {
+                debug!("No interfaces for {}: {:#}", path, e);
+                return;
+            }
+        };
+        if interfaces.contains(Interface::Component) {
+            dumped.bounds = self.bounds(bus_name, path).await.ok();
+        }
+        if interfaces.contains(Interface::Action) {
+            dumped.actions = self.actions(bus_name, path).await.unwrap_or_default();
+        }
+        dumped.interfaces = interfaces
+            .iter()
+            .map(|interface| format!("{:?}", interface))
+            .collect();
+    }
+
+    async fn interfaces(&self, bus_name: &str, path: &str) -> Result<atspi::InterfaceSet> {
+        element::interfaces(&self.connection, bus_name, path).await
+    }
+
+    /// Names of the object's actions; the first is its default action
+    async fn actions(&self, bus_name: &str, path: &str) -> Result<Vec<String>> {
+        let actions = action::listed(&self.connection, bus_name, path).await?;
+        Ok(actions.into_iter().map(|action| action.name).collect())
+    }
+}
+
+/// What the walk found about one object
+fn walked_node(tree: &Tree, index: usize) -> TreeDumpNode {
+    let node = &tree.nodes[index];
+    TreeDumpNode {
+        role: format!("{:?}", node.role),
+        name: node.name.clone(),
+        description: node.description.clone().unwrap_or_default(),
+        states: node
+            .states
+            .iter()
+            .map(|state| format!("{:?}", state))
+            .collect(),
+        handle: tree.handle(index).to_string(),
+        ..Default::default()
+    }
+}
+
+/// Which walked nodes go in the dump. Parents come before their children in
+/// a walk, so one pass in order sees every parent decided.
+fn kept_nodes(nodes: &[TreeNode], walk_scope: &Selector, scope: &TreeScope) -> Vec<bool> {
+    let mut in_scope = vec![false; nodes.len()];
+    for index in walk_scope.select(nodes) {
+        in_scope[index] = true;
+    }
+    if let Some(window) = &scope.window {
+        let window = window.to_lowercase();
+        for index in 0..nodes.len() {
+            let node = &nodes[index];
+            in_scope[index] = in_scope[index]
+                && match node.parent {
+                    Some(parent) if nodes[parent].parent.is_none() => {
+                        node.name.to_lowercase().contains(&window)
+                    }
+                    Some(parent) => in_scope[parent],
+                    None => false,
+                };
+        }
+    }
+
+    let Some(filter) = &scope.filter else {
+        return in_scope;
+    };
+    let mut kept = vec![false; nodes.len()];
+    for index in filter.select(nodes) {
+        let mut current = Some(index);
+        while let Some(index) = current.filter(|&index| in_scope[index] && !kept[index]) {
+            kept[index] = true;
+            current = nodes[index].parent;
+        }
+    }
+    kept
+}
+
+/// Nest the dumped nodes, given in walk order, under their kept parents
+fn nest(nodes: &[TreeNode], kept: &[bool], dumped: Vec<TreeDumpNode>) -> Vec<TreeDumpNode> {
+    let indices: Vec<usize> = (0..nodes.len()).filter(|&index| kept[index]).collect();
+    let mut children: Vec<Vec<TreeDumpNode>> = vec![Vec::new(); nodes.len()];
+    let mut roots = Vec::new();
+
+    // Children come after their parent, so in reverse each node's children
+    // are complete (if reversed) by the time the node itself is reached
+    for (index, mut node) in indices.into_iter().zip(dumped).rev() {
+        node.children = std::mem::take(&mut children[index]);
+        node.children.reverse();
+        match nodes[index].parent.filter(|&parent| kept[parent]) {
+            Some(parent) => children[parent].push(node),
+            None => roots.push(node),
+        }
+    }
+    roots.reverse();
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atspi::{Role, StateSet};
+    use std::sync::Arc;
+
+    fn node(role: Role, name: &str, app: &str, parent: Option<usize>) -> TreeNode {
+        TreeNode {
+            role,
+            name: name.to_string(),
+            description: None,
+            app: Arc::from(app),
+            states: StateSet::empty(),
+            parent,
+        }
+    }
+
+    fn desktop() -> Vec<TreeNode> {
+        vec![
+            node(Role::Application, "gedit", "gedit", None),
+            node(Role::Frame, "notes.txt - gedit", "gedit", Some(0)),
+            node(Role::PushButton, "Save", "gedit", Some(1)),
+            node(Role::Frame, "todo.txt - gedit", "gedit", Some(0)),
+            node(Role::PushButton, "Save", "gedit", Some(3)),
+            node(Role::Application, "firefox", "firefox", None),
+            node(Role::Frame, "Mozilla Firefox", "firefox", Some(5)),
+        ]
+    }
+
+    fn dump(nodes: &[TreeNode], scope: &TreeScope) -> Vec<TreeDumpNode> {
+        let kept = kept_nodes(nodes, &scope.walk_selector(), scope);
+        let dumped = (0..nodes.len())
+            .filter(|&index| kept[index])
+            .map(|index| TreeDumpNode {
+                name: nodes[index].name.clone(),
+                ..Default::default()
+            })
+            .collect();
+        nest(nodes, &kept, dumped)
+    }
+
+    fn outline(roots: &[TreeDumpNode]) -> String {
+        roots
+            .iter()
+            .map(|node| match node.children.is_empty() {
+                true => node.name.clone(),
+                false => format!("{}({})", node.name, outline(&node.children)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    #[test]
+    fn test_dump_scopes() {
+        let nodes = desktop();
+
+        let everything = dump(&nodes, &TreeScope::default());
+        assert_eq!(
+            outline(&everything),
+            "gedit(notes.txt - gedit(Save), todo.txt - gedit(Save)), firefox(Mozilla Firefox)"
+        );
+
+        let app = TreeScope {
+            app: Some("Firefox".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(outline(&dump(&nodes, &app)), "firefox(Mozilla Firefox)");
+
+        let window = TreeScope {
+            window: Some("TODO".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(outline(&dump(&nodes, &window)), "todo.txt - gedit(Save)");
+    }
+
+    #[test]
+    fn test_dump_filter_keeps_ancestors() {
+        let nodes = desktop();
+
+        let filtered = TreeScope {
+            filter: Some("push-button".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            outline(&dump(&nodes, &filtered)),
+            "gedit(notes.txt - gedit(Save), todo.txt - gedit(Save))"
+        );
+
+        let in_window = TreeScope {
+            window: Some("notes".to_string()),
+            filter: Some("push-button".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            outline(&dump(&nodes, &in_window)),
+            "notes.txt - gedit(Save)"
+        );
+        assert_eq!(in_window.describe(), "window=notes filter=push-button");
+        assert_eq!(TreeScope::default().describe(), "desktop");
+    }
+}
@@ -0,0 +1,289 @@
+// Source positions and unknown-key detection for config files
+use super::profiles::{PROFILE_FIELDS, PROFILE_KEY, PROFILE_SECTIONS};
+use super::Config;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use toml_edit::{ArrayOfTables, ImDocument, Item, Table};
+
+/// Keys with no default value, so absent from a serialized default config
+const OPTIONAL_KEYS: &[&str] = &[
+    "wayland.compositor",
+    "wayland.preferred_method",
+    "logging.file",
+];
+
+/// Keys that used to be valid and where they live now
+const MOVED_KEYS: &[(&str, &str)] = &[(
+    "wayland.clipboard_history_size",
+    "clipboard.max_history_entries",
+)];
+
+/// A problem that does not stop the file from loading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+/// A config file parsed with spans, for reporting problems by line
+pub struct ConfigDocument<'a> {
+    contents: &'a str,
+    document: ImDocument<&'a str>,
+}
+
+impl<'a> ConfigDocument<'a> {
+    pub fn parse(contents: &'a str) -> Result<Self> {
+        Ok(Self {
+            contents,
+            document: ImDocument::parse(contents)?,
+        })
+    }
+
+    /// Line of a dotted key such as `wayland.retry_attempts`, if present.
+    /// For `profile.<name>` it is the line of that profile's name.
+    pub fn line_of(&self, key: &str) -> Option<usize> {
+        if let Some(name) = key
+            .strip_prefix(PROFILE_KEY)
+            .and_then(|k| k.strip_prefix('.'))
+        {
+            return self.profile_line(name);
+        }
+        let mut table = self.document.as_table();
+        let mut parts = key.split('.').peekable();
+        while let Some(part) = parts.next() {
+            let (key, item) = table.get_key_value(part)?;
+            if parts.peek().is_none() {
+                return self.line_at(key.span().or_else(|| item.span())?.start);
+            }
+            table = item.as_table()?;
+        }
+        None
+    }
+
+    fn profile_line(&self, name: &str) -> Option<usize> {
+        let profile = self
+            .document
+            .get(PROFILE_KEY)?
+            .as_array_of_tables()?
+            .iter()
+            .find(|profile| profile.get("name").and_then(Item::as_str) == Some(name))?;
+        let (key, _) = profile.get_key_value("name")?;
+        self.line_at(key.span()?.start)
+    }
+
+    /// Keys and sections `Config` does not define
+    pub fn unknown_keys(&self) -> Vec<ConfigWarning> {
+        let known = known_keys();
+        let mut warnings = Vec::new();
+
+        for (name, item) in self.document.iter() {
+            let line = self.line_of(name).unwrap_or(1);
+            match (known.get(name), item) {
+                (Some(keys), Item::Table(table)) => {
+                    self.check_section(name, table, keys, &mut warnings)
+                }
+                (Some(_), _) => warnings.push(ConfigWarning {
+                    line,
+                    message: format!("`{}` must be a section", name),
+                }),
+                (None, Item::Table(_)) => warnings.push(ConfigWarning {
+                    line,
+                    message: format!(
+                        "unknown section [{}]{}",
+                        name,
+                        suggestion(name, known.keys().filter(|k| !k.is_empty()))
+                    ),
+                }),
+                (None, Item::ArrayOfTables(profiles)) if name == PROFILE_KEY => {
+                    self.check_profiles(profiles, &mut warnings)
+                }
+                (None, _) if known[""].contains(name) => {}
+                (None, _) => warnings.push(ConfigWarning {
+                    line,
+                    message: format!("unknown key `{}`{}", name, suggestion(name, &known[""])),
+                }),
+            }
+        }
+
+        warnings.sort_by_key(|w| w.line);
+        warnings
+    }
+
+    fn check_section(
+        &self,
+        section: &str,
+        table: &Table,
+        keys: &BTreeSet<String>,
+        warnings: &mut Vec<ConfigWarning>,
+    ) {
+        for (name, _) in table.iter() {
+            if keys.contains(name) {
+                continue;
+            }
+            let key = format!("{}.{}", section, name);
+            let hint = match MOVED_KEYS.iter().find(|(old, _)| *old == key) {
+                Some((_, new)) => format!(" (moved to `{}`)", new),
+                None => suggestion(name, keys),
+            };
+            warnings.push(ConfigWarning {
+                line: self.line_of(&key).unwrap_or(1),
+                message: format!("unknown key `{}`{}", key, hint),
+            });
+        }
+    }
+
+    /// Top-level keys of `[[profile]]` tables; keys inside their override
+    /// sections are checked when the profile is validated
+    fn check_profiles(&self, profiles: &ArrayOfTables, warnings: &mut Vec<ConfigWarning>) {
+        let fields: Vec<String> = PROFILE_FIELDS
+            .iter()
+            .chain(PROFILE_SECTIONS)
+            .map(|k| k.to_string())
+            .collect();
+        for profile in profiles.iter() {
+            for (name, _) in profile.iter() {
+                if fields.iter().any(|field| field == name) {
+                    continue;
+                }
+                let line = profile
+                    .get_key_value(name)
+                    .and_then(|(key, _)| key.span())
+                    .and_then(|span| self.line_at(span.start))
+                    .unwrap_or(1);
+                warnings.push(ConfigWarning {
+                    line,
+                    message: format!(
+                        "unknown key `{}.{}`{}",
+                        PROFILE_KEY,
+                        name,
+                        suggestion(name, &fields)
+                    ),
+                });
+            }
+        }
+    }
+
+    fn line_at(&self, offset: usize) -> Option<usize> {
+        let before = self.contents.get(..offset)?;
+        Some(before.matches('\n').count() + 1)
+    }
+}
+
+/// Every key `Config` accepts, by section ("" for top-level keys)
+pub(super) fn known_keys() -> BTreeMap<String, BTreeSet<String>> {
+    let mut known: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    known.insert(String::new(), BTreeSet::new());
+
+    let defaults = toml::Value::try_from(Config::default()).expect("default config serializes");
+    if let Some(table) = defaults.as_table() {
+        for (name, value) in table {
+            match value.as_table() {
+                Some(section) => {
+                    known
+                        .entry(name.clone())
+                        .or_default()
+                        .extend(section.keys().cloned());
+                }
+                None => {
+                    known.entry(String::new()).or_default().insert(name.clone());
+                }
+            }
+        }
+    }
+
+    for key in OPTIONAL_KEYS {
+        if let Some((section, name)) = key.split_once('.') {
+            known
+                .entry(section.to_string())
+                .or_default()
+                .insert(name.to_string());
+        }
+    }
+    known
+}
+
+/// " (did you mean `x`?)" for the closest candidate within a few edits
+fn suggestion<'k>(name: &str, candidates: impl IntoIterator<Item = &'k String>) -> String {
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 3))
+        .min()
+        .map(|(_, candidate)| format!(" (did you mean `{}`?)", candidate))
+        .unwrap_or_default()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != *cb);
+            current.push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_keys_reported_with_lines() {
+        let contents = "\
+log_level = \"info\"
+log_levle = \"debug\"
+
+[wayland]
+input_methods = [\"portal\"]
+retry_atempts = 3
+clipboard_history_size = 50
+
+[clipbaord]
+enable_history = true
+
+[[profile]]
+name = \"electron\"
+app_id = [\"code\"]
+typing_stratgy = [\"clipboard_paste\"]
+";
+        let document = ConfigDocument::parse(contents).unwrap();
+        let warnings = document.unknown_keys();
+        let messages: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "2: unknown key `log_levle` (did you mean `log_level`?)",
+                "6: unknown key `wayland.retry_atempts` (did you mean `retry_attempts`?)",
+                "7: unknown key `wayland.clipboard_history_size` \
+                 (moved to `clipboard.max_history_entries`)",
+                "9: unknown section [clipbaord] (did you mean `clipboard`?)",
+                "15: unknown key `profile.typing_stratgy` (did you mean `typing_strategy`?)",
+            ]
+        );
+        assert_eq!(document.line_of("wayland.input_methods"), Some(5));
+        assert_eq!(document.line_of("wayland.compositor"), None);
+        assert_eq!(document.line_of("profile.electron"), Some(13));
+        assert_eq!(document.line_of("profile.slack"), None);
+    }
+
+    #[test]
+    fn test_example_config_has_no_unknown_keys() {
+        let contents = include_str!("../../../deskd.conf.example");
+        let document = ConfigDocument::parse(contents).unwrap();
+        assert_eq!(document.unknown_keys(), []);
+
+        let config: Config = toml::from_str(contents).unwrap();
+        assert!(config.problems().is_empty(), "{:?}", config.problems());
+    }
+}
@@ -0,0 +1,465 @@
+// Layered configuration: defaults, system files, user file, environment, flags
+use super::diagnostics::{self, ConfigDocument};
+use super::profiles::PROFILE_KEY;
+use super::{expand_home, Config};
+use crate::wayland::input::InputMethod;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Environment variables setting a key are named `DESKD_<SECTION>_<KEY>`
+pub const ENV_PREFIX: &str = "DESKD_";
+
+/// Where an effective value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    File { path: PathBuf, line: Option<usize> },
+    Env { var: String },
+    Flag { flag: String },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File {
+                path,
+                line: Some(line),
+            } => write!(f, "{}:{}", path.display(), line),
+            Origin::File { path, line: None } => write!(f, "{}", path.display()),
+            Origin::Env { var } => write!(f, "env {}", var),
+            Origin::Flag { flag } => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+impl Serialize for Origin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Builds the configuration from every layer, later layers winning: built-in
+/// defaults, `/etc/deskd/deskd.conf`, `/etc/deskd/conf.d/*.conf` in name
+/// order, the user's `~/.config/deskd/deskd.conf`, `DESKD_*` environment
+/// variables and command-line flags. Files only need the keys they change.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    system_dir: PathBuf,
+    user_file: Option<PathBuf>,
+    /// Set by `--config`: the only file read
+    only_file: Option<PathBuf>,
+    env: BTreeMap<String, String>,
+    /// `(flag, key, value)`
+    flags: Vec<(String, String, Value)>,
+}
+
+/// A loaded configuration and where its values came from
+#[derive(Debug, Clone)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// Origin of every key set by a file, variable or flag; other keys have
+    /// their default value
+    pub origins: BTreeMap<String, Origin>,
+    /// Files that were read, in the order they were applied
+    pub files: Vec<PathBuf>,
+    /// Unknown keys in those files
+    pub warnings: Vec<String>,
+}
+
+impl Default for ConfigLoader {
+    /// Standard locations and the daemon's environment
+    fn default() -> Self {
+        Self {
+            system_dir: PathBuf::from("/etc/deskd"),
+            user_file: Some(PathBuf::from(expand_home("~/.config/deskd/deskd.conf"))),
+            only_file: None,
+            env: std::env::vars()
+                .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+                .collect(),
+            flags: Vec::new(),
+        }
+    }
+}
+
+impl ConfigLoader {
+    /// Read only `path` instead of the system and user files; it must exist
+    pub fn only_file(mut self, path: PathBuf) -> Self {
+        self.only_file = Some(path);
+        self
+    }
+
+    /// Set `key` from a command-line flag, above every other layer
+    pub fn flag(mut self, flag: &str, key: &str, value: impl Into<Value>) -> Self {
+        self.flags
+            .push((flag.to_string(), key.to_string(), value.into()));
+        self
+    }
+
+    /// Config files that exist, in the order they are applied
+    pub fn files(&self) -> Vec<PathBuf> {
+        if let Some(path) = &self.only_file {
+            return vec![path.clone()];
+        }
+        let mut files = Vec::new();
+
+        let system = self.system_dir.join("deskd.conf");
+        if system.is_file() {
+            files.push(system);
+        }
+        if let Ok(entries) = std::fs::read_dir(self.system_dir.join("conf.d")) {
+            let mut drop_ins: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+                .filter(|path| path.is_file())
+                .collect();
+            drop_ins.sort();
+            files.extend(drop_ins);
+        }
+        if let Some(user) = self.user_file.as_ref().filter(|path| path.is_file()) {
+            files.push(user.clone());
+        }
+
+        files
+    }
+
+    /// Read every layer and validate the result. Problems are reported with
+    /// the file and line, variable or flag that set the bad value.
+    pub fn load(&self) -> Result<LoadedConfig> {
+        let Value::Table(mut merged) = Value::try_from(Config::default())? else {
+            anyhow::bail!("Default configuration is not a table");
+        };
+        let mut origins = BTreeMap::new();
+        let mut warnings = Vec::new();
+
+        let files = self.files();
+        for path in &files {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+            // Type errors are reported against the file that has them
+            toml::from_str::<Config>(&contents)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+            let document = ConfigDocument::parse(&contents)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+            warnings.extend(
+                document
+                    .unknown_keys()
+                    .iter()
+                    .map(|warning| format!("{}:{}", path.display(), warning)),
+            );
+
+            let layer: Table = toml::from_str(&contents)?;
+            for key in merge(&mut merged, layer) {
+                let line = document.line_of(&key);
+                let origin = Origin::File {
+                    path: path.clone(),
+                    line,
+                };
+                origins.insert(key, origin);
+            }
+        }
+
+        for (section, names) in diagnostics::known_keys() {
+            for name in names {
+                let key = match section.as_str() {
+                    // Profiles are only defined in files
+                    "" if name == PROFILE_KEY => continue,
+                    "" => name.clone(),
+                    section => format!("{}.{}", section, name),
+                };
+                let var = env_var(&key);
+                let Some(raw) = self.env.get(&var) else {
+                    continue;
+                };
+                let value = parse_env(raw, lookup(&merged, &key))
+                    .with_context(|| format!("Invalid value in {}", var))?;
+                set(&mut merged, &key, value);
+                origins.insert(key, Origin::Env { var });
+            }
+        }
+
+        for (flag, key, value) in &self.flags {
+            set(&mut merged, key, value.clone());
+            origins.insert(key.clone(), Origin::Flag { flag: flag.clone() });
+        }
+
+        let mut config: Config = Value::Table(merged)
+            .try_into()
+            .context("Failed to parse configuration")?;
+
+        // Expand home directory in paths
+        config.database_path = expand_home(&config.database_path);
+        config.socket_path = expand_home(&config.socket_path);
+        config.backup.directory = expand_home(&config.backup.directory);
+        config.encryption.keyfile = expand_home(&config.encryption.keyfile);
+        config.logging.file = config.logging.file.as_deref().map(expand_home);
+
+        // Accept the descriptive method names used by older example files
+        if let Some(preferred) = &config.wayland.preferred_method {
+            if let Ok(method) = preferred.parse::<InputMethod>() {
+                config.wayland.preferred_method = Some(method.name().to_string());
+            }
+        }
+
+        let problems = config.problems();
+        if !problems.is_empty() {
+            let report: Vec<String> = problems
+                .iter()
+                .map(|(key, problem)| match origins.get(key) {
+                    Some(origin) => format!("{}: {}", origin, problem),
+                    None => problem.clone(),
+                })
+                .collect();
+            anyhow::bail!("Invalid configuration:\n{}", report.join("\n"));
+        }
+
+        Ok(LoadedConfig {
+            config,
+            origins,
+            files,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+impl ConfigLoader {
+    /// A loader reading only the given locations and variables
+    pub fn isolated(
+        system_dir: &std::path::Path,
+        user_file: &std::path::Path,
+        env: &[(&str, &str)],
+    ) -> Self {
+        Self {
+            system_dir: system_dir.to_path_buf(),
+            user_file: Some(user_file.to_path_buf()),
+            only_file: None,
+            env: env
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            flags: Vec::new(),
+        }
+    }
+}
+
+/// `DESKD_INPUT_TIMING_TYPING_DELAY_MS` for `input_timing.typing_delay_ms`
+pub fn env_var(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+/// Overlay one file onto the merged table, returning the keys it set
+fn merge(merged: &mut Table, layer: Table) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (name, value) in layer {
+        match (merged.get_mut(&name), value) {
+            // A profile replaces an earlier one with the same name; new
+            // profiles are added after those from earlier layers
+            (Some(Value::Array(profiles)), Value::Array(layer)) if name == PROFILE_KEY => {
+                for profile in layer {
+                    keys.push(profile_key(&profile));
+                    let same_name = |p: &Value| p.get("name") == profile.get("name");
+                    match profiles.iter_mut().find(|p| same_name(p)) {
+                        Some(existing) => *existing = profile,
+                        None => profiles.push(profile),
+                    }
+                }
+            }
+            (Some(Value::Table(section)), Value::Table(values)) => {
+                for (key, value) in values {
+                    keys.push(format!("{}.{}", name, key));
+                    section.insert(key, value);
+                }
+            }
+            (_, value) => {
+                match &value {
+                    Value::Table(values) => {
+                        keys.extend(values.keys().map(|key| format!("{}.{}", name, key)))
+                    }
+                    _ => keys.push(name.clone()),
+                }
+                merged.insert(name, value);
+            }
+        }
+    }
+    keys
+}
+
+/// `profile.<name>`, the key a profile's origin is recorded under
+fn profile_key(profile: &Value) -> String {
+    let name = profile
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    format!("{}.{}", PROFILE_KEY, name)
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((section, name)) => table.get(section)?.get(name),
+        None => table.get(key),
+    }
+}
+
+fn set(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, name)) => {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(section) = section {
+                section.insert(name.to_string(), value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Parse a variable as the type of the value it replaces; lists are
+/// comma-separated
+fn parse_env(raw: &str, current: Option<&Value>) -> Result<Value> {
+    let raw = raw.trim();
+    Ok(match current {
+        Some(Value::Integer(_)) => Value::Integer(
+            raw.parse()
+                .with_context(|| format!("expected an integer, got {:?}", raw))?,
+        ),
+        Some(Value::Boolean(_)) => match raw.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Value::Boolean(true),
+            "false" | "0" | "no" | "off" => Value::Boolean(false),
+            _ => anyhow::bail!("expected true or false, got {:?}", raw),
+        },
+        Some(Value::Array(_)) => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        _ => Value::String(raw.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_and_origins() {
+        let dir = std::env::temp_dir().join(format!("deskd-layers-test-{}", uuid::Uuid::new_v4()));
+        let system = dir.join("etc");
+        std::fs::create_dir_all(system.join("conf.d")).unwrap();
+        std::fs::write(
+            system.join("deskd.conf"),
+            "[input_timing]\ntyping_delay_ms = 20\nclick_delay_ms = 60\n",
+        )
+        .unwrap();
+        std::fs::write(
+            system.join("conf.d/50-fleet.conf"),
+            "[input_timing]\nclick_delay_ms = 70\n\n[tasks]\nmax_concurrent = 4\n\n\
+             [[profile]]\nname = \"electron\"\napp_id = [\"code\"]\n\n\
+             [[profile]]\nname = \"gtk\"\napp_id = [\"org.gnome.*\"]\n",
+        )
+        .unwrap();
+        std::fs::write(system.join("conf.d/README"), "not a config file").unwrap();
+        let user = dir.join("user.conf");
+        std::fs::write(
+            &user,
+            "log_level = \"debug\"\n[tasks]\nmax_concurrent = 6\n\n\
+             [[profile]]\nname = \"electron\"\napp_id = [\"code\", \"Slack\"]\n",
+        )
+        .unwrap();
+
+        let loader = ConfigLoader::isolated(
+            &system,
+            &user,
+            &[
+                ("DESKD_WAYLAND_INPUT_METHODS", "ydotool, portal"),
+                ("DESKD_ATSPI_ENABLE_CACHE", "off"),
+            ],
+        )
+        .flag("--socket", "socket_path", "/tmp/deskd-test.sock");
+        let loaded = loader.load().unwrap();
+
+        assert_eq!(loaded.files.len(), 3);
+        let config = &loaded.config;
+        assert_eq!(config.input_timing.typing_delay_ms, 20);
+        assert_eq!(config.input_timing.click_delay_ms, 70);
+        assert_eq!(config.input_timing.focus_timeout_ms, 1000);
+        assert_eq!(config.tasks.max_concurrent, 6);
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.wayland.input_methods, ["ydotool", "portal"]);
+        assert!(!config.atspi.enable_cache);
+        assert_eq!(config.socket_path, "/tmp/deskd-test.sock");
+        // The user's electron profile replaces the system one in place
+        let profiles: Vec<(&str, usize)> = config
+            .profiles
+            .iter()
+            .map(|p| (p.name.as_str(), p.app_id.len()))
+            .collect();
+        assert_eq!(profiles, [("electron", 2), ("gtk", 1)]);
+
+        let origin = |key: &str| loaded.origins.get(key).map(ToString::to_string);
+        assert_eq!(
+            origin("input_timing.typing_delay_ms"),
+            Some(format!("{}:2", system.join("deskd.conf").display()))
+        );
+        assert_eq!(
+            origin("input_timing.click_delay_ms"),
+            Some(format!(
+                "{}:2",
+                system.join("conf.d/50-fleet.conf").display()
+            ))
+        );
+        assert_eq!(
+            origin("tasks.max_concurrent"),
+            Some(format!("{}:3", user.display()))
+        );
+        assert_eq!(
+            origin("wayland.input_methods").as_deref(),
+            Some("env DESKD_WAYLAND_INPUT_METHODS")
+        );
+        assert_eq!(origin("socket_path").as_deref(), Some("flag --socket"));
+        assert_eq!(origin("input_timing.focus_timeout_ms"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_problems_name_their_layer() {
+        let dir = std::env::temp_dir().join(format!("deskd-layers-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.conf");
+        std::fs::write(&user, "[tasks]\n\nmax_concurrent = 0\n").unwrap();
+
+        let err = ConfigLoader::isolated(&dir, &user, &[("DESKD_LOG_LEVEL", "loud")])
+            .load()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("env DESKD_LOG_LEVEL: Invalid log level: loud"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains(&format!("{}:3: tasks.max_concurrent", user.display())),
+            "{}",
+            err
+        );
+
+        let err =
+            ConfigLoader::isolated(&dir, &user, &[("DESKD_TASKS_CLEANUP_AFTER_DAYS", "soon")])
+                .load()
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value in DESKD_TASKS_CLEANUP_AFTER_DAYS"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,45 @@
 // Configuration management
-use anyhow::{Context, Result};
+use crate::logging::LogFormat;
+use crate::wayland::input::InputMethod;
+use anyhow::Result;
+use profiles::Profile;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+
+pub mod diagnostics;
+pub mod layers;
+pub mod preferences;
+pub mod profiles;
+pub mod runtime;
+pub mod watch;
+
+/// Input backends deskd knows how to use, in default priority order
+pub const INPUT_METHODS: &[&str] = &input_method_names();
+
+const fn input_method_names() -> [&'static str; InputMethod::ALL.len()] {
+    let mut names = [""; InputMethod::ALL.len()];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = InputMethod::ALL[i].name();
+        i += 1;
+    }
+    names
+}
+
+/// Compositor names accepted by `wayland.compositor`
+pub const COMPOSITORS: &[&str] = &["gnome", "kde", "sway", "hyprland", "wlroots", "x11"];
+
+/// Settings (or whole sections) only read at startup; changing them in the
+/// file takes effect after a restart
+pub const RESTART_REQUIRED: &[&str] = &[
+    "database_path",
+    "socket_path",
+    "session_discovery",
+    "backup",
+    "encryption",
+    "logging",
+];
+
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -18,11 +55,43 @@ pub struct Config {
     #[serde(default)]
     pub session_discovery: SessionDiscovery,
 
+    /// Reload when the config file changes on disk, not only on SIGHUP
+    #[serde(default)]
+    pub watch_config: bool,
+
     #[serde(default)]
     pub input_timing: InputTiming,
 
     #[serde(default)]
     pub wayland: WaylandConfig,
+
+    #[serde(default)]
+    pub atspi: AtspiConfig,
+
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+
+    #[serde(default)]
+    pub tasks: TasksConfig,
+
+    #[serde(default)]
+    pub desktop: DesktopConfig,
+
+    #[serde(default)]
+    pub security: SecurityConfig,
+
+    #[serde(default)]
+    pub backup: BackupConfig,
+
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// Per-application overrides, first match wins
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<Profile>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -60,9 +129,17 @@ pub struct WaylandConfig {
     #[serde(default)]
     pub compositor: Option<String>,
 
+    /// Method tried before the rest of `input_methods`
+    #[serde(default)]
+    pub preferred_method: Option<String>,
+
     #[serde(default = "default_input_methods")]
     pub input_methods: Vec<String>,
 
+    /// Try the other input methods when the first one fails
+    #[serde(default = "default_enable_fallback")]
+    pub enable_fallback: bool,
+
     #[serde(default = "default_retry_attempts")]
     pub retry_attempts: usize,
 
@@ -71,24 +148,218 @@ pub struct WaylandConfig {
 
     #[serde(default = "default_portal_timeout_ms")]
     pub portal_permission_timeout_ms: u64,
-
-    #[serde(default = "default_clipboard_history_size")]
-    pub clipboard_history_size: usize,
 }
 
 impl Default for WaylandConfig {
     fn default() -> Self {
         Self {
             compositor: None, // Auto-detect
+            preferred_method: None,
             input_methods: default_input_methods(),
+            enable_fallback: default_enable_fallback(),
             retry_attempts: default_retry_attempts(),
             retry_delay_ms: default_retry_delay_ms(),
             portal_permission_timeout_ms: default_portal_timeout_ms(),
-            clipboard_history_size: default_clipboard_history_size(),
         }
     }
 }
 
+impl WaylandConfig {
+    /// Input methods in the order they are tried: `preferred_method` first,
+    /// then `input_methods`; only the first one without fallback
+    pub fn method_order(&self) -> Vec<String> {
+        let mut order = self.input_methods.clone();
+        if let Some(preferred) = &self.preferred_method {
+            order.retain(|method| method != preferred);
+            order.insert(0, preferred.clone());
+        }
+        if !self.enable_fallback {
+            order.truncate(1);
+        }
+        order
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AtspiConfig {
+    #[serde(default = "default_enable_cache")]
+    pub enable_cache: bool,
+
+    #[serde(default = "default_cache_expiration")]
+    pub cache_expiration_seconds: u64,
+}
+
+impl Default for AtspiConfig {
+    fn default() -> Self {
+        Self {
+            enable_cache: default_enable_cache(),
+            cache_expiration_seconds: default_cache_expiration(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardConfig {
+    #[serde(default = "default_enable_history")]
+    pub enable_history: bool,
+
+    #[serde(default = "default_max_history_entries")]
+    pub max_history_entries: usize,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            enable_history: default_enable_history(),
+            max_history_entries: default_max_history_entries(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TasksConfig {
+    /// Remove finished tasks older than this many days (0 keeps them)
+    #[serde(default = "default_cleanup_after_days")]
+    pub cleanup_after_days: u64,
+
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+impl Default for TasksConfig {
+    fn default() -> Self {
+        Self {
+            cleanup_after_days: default_cleanup_after_days(),
+            max_concurrent: default_max_concurrent(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DesktopConfig {
+    /// "auto" or a desktop session id
+    #[serde(default = "default_primary_desktop")]
+    pub primary_desktop: String,
+
+    #[serde(default)]
+    pub enable_cross_desktop: bool,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        Self {
+            primary_desktop: default_primary_desktop(),
+            enable_cross_desktop: false,
+        }
+    }
+}
+
+/// Client authentication; only enforced by the system-wide daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    #[serde(default = "default_require_auth")]
+    pub require_auth: bool,
+
+    #[serde(default = "default_token_expiration_hours")]
+    pub token_expiration_hours: u64,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            require_auth: default_require_auth(),
+            token_expiration_hours: default_token_expiration_hours(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    #[serde(default = "default_backup_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_backup_directory")]
+    pub directory: String,
+
+    #[serde(default = "default_backup_interval")]
+    pub interval_seconds: u64,
+
+    #[serde(default = "default_backup_retention")]
+    pub retention_count: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_backup_enabled(),
+            directory: default_backup_directory(),
+            interval_seconds: default_backup_interval(),
+            retention_count: default_backup_retention(),
+        }
+    }
+}
+
+/// Log output; the level is the top-level `log_level`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Write logs to this file instead of stderr
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// Rotate the log file when it grows past this size
+    #[serde(default = "default_max_file_size_mb")]
+    pub max_file_size_mb: u64,
+
+    /// Rotated log files to keep (`deskd.log.1` is the newest)
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            file: None,
+            max_file_size_mb: default_max_file_size_mb(),
+            max_files: default_max_files(),
+        }
+    }
+}
+
+/// Column-level encryption of clipboard history and workflow steps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub key_source: KeySource,
+
+    #[serde(default = "default_keyfile")]
+    pub keyfile: String,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_source: KeySource::default(),
+            keyfile: default_keyfile(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    #[default]
+    Keyfile,
+    Keyring,
+}
+
 // Default value functions
 fn default_database_path() -> String {
     expand_home("~/.local/share/deskd/state.db")
@@ -98,8 +369,10 @@ fn default_socket_path() -> String {
     expand_home("~/.local/run/deskd.sock")
 }
 
+// `RUST_LOG` holds filter directives rather than a level; `logging::init`
+// reads it directly
 fn default_log_level() -> String {
-    std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string())
+    "info".to_string()
 }
 
 fn default_typing_delay() -> u64 {
@@ -115,13 +388,11 @@ fn default_focus_timeout() -> u64 {
 }
 
 fn default_input_methods() -> Vec<String> {
-    vec![
-        "portal".to_string(),
-        "compositor_ipc".to_string(),
-        "libei".to_string(),
-        "ydotool".to_string(),
-        "xtest".to_string(),
-    ]
+    INPUT_METHODS.iter().map(|m| m.to_string()).collect()
+}
+
+fn default_enable_fallback() -> bool {
+    true
 }
 
 fn default_retry_attempts() -> usize {
@@ -136,10 +407,70 @@ fn default_portal_timeout_ms() -> u64 {
     30000 // 30 seconds for user to respond to permission dialog
 }
 
-fn default_clipboard_history_size() -> usize {
+fn default_enable_cache() -> bool {
+    true
+}
+
+fn default_cache_expiration() -> u64 {
+    300
+}
+
+fn default_enable_history() -> bool {
+    true
+}
+
+fn default_max_history_entries() -> usize {
     100
 }
 
+fn default_cleanup_after_days() -> u64 {
+    30
+}
+
+fn default_max_concurrent() -> usize {
+    10
+}
+
+fn default_primary_desktop() -> String {
+    "auto".to_string()
+}
+
+fn default_require_auth() -> bool {
+    true
+}
+
+fn default_token_expiration_hours() -> u64 {
+    24
+}
+
+fn default_max_file_size_mb() -> u64 {
+    10
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+fn default_backup_enabled() -> bool {
+    true
+}
+
+fn default_backup_directory() -> String {
+    expand_home("~/.local/share/deskd/backups")
+}
+
+fn default_backup_interval() -> u64 {
+    86400 // Daily
+}
+
+fn default_backup_retention() -> usize {
+    7
+}
+
+fn default_keyfile() -> String {
+    expand_home("~/.config/deskd/db.key")
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -147,87 +478,176 @@ impl Default for Config {
             socket_path: default_socket_path(),
             log_level: default_log_level(),
             session_discovery: SessionDiscovery::default(),
+            watch_config: false,
             input_timing: InputTiming::default(),
             wayland: WaylandConfig::default(),
+            atspi: AtspiConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            tasks: TasksConfig::default(),
+            desktop: DesktopConfig::default(),
+            security: SecurityConfig::default(),
+            backup: BackupConfig::default(),
+            encryption: EncryptionConfig::default(),
+            logging: LoggingConfig::default(),
+            profiles: Vec::new(),
         }
     }
 }
 
 impl Config {
-    /// Load configuration from file with fallback to defaults
-    pub fn load() -> Result<Self> {
-        let config_path = Self::find_config_file();
-
-        if let Some(path) = config_path {
-            info!("Loading configuration from: {}", path.display());
-            Self::load_from_file(&path)
-        } else {
-            warn!("No configuration file found, using defaults");
-            Ok(Self::default())
+    /// Validate configuration
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if !problems.is_empty() {
+            let report: Vec<&str> = problems.iter().map(|(_, p)| p.as_str()).collect();
+            anyhow::bail!("Invalid configuration:\n{}", report.join("\n"));
         }
+        Ok(())
     }
 
-    /// Load configuration from a specific file
-    pub fn load_from_file(path: &Path) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-
-        let mut config: Config = toml::from_str(&contents)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
-
-        // Expand home directory in paths
-        config.database_path = expand_home(&config.database_path);
-        config.socket_path = expand_home(&config.socket_path);
-
-        // Override with environment variables
-        if let Ok(db_path) = std::env::var("DESKD_DATABASE_PATH") {
-            config.database_path = db_path;
-        }
-        if let Ok(socket_path) = std::env::var("DESKD_SOCKET_PATH") {
-            config.socket_path = socket_path;
-        }
-        if let Ok(log_level) = std::env::var("RUST_LOG") {
-            config.log_level = log_level;
+    /// Every invalid setting, as `(key, problem)` pairs
+    pub fn problems(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let mut check = |key: &str, ok: bool, problem: String| {
+            if !ok {
+                problems.push((key.to_string(), problem));
+            }
+        };
+
+        check(
+            "log_level",
+            LOG_LEVELS.contains(&self.log_level.as_str()),
+            format!(
+                "Invalid log level: {} (expected one of: {})",
+                self.log_level,
+                LOG_LEVELS.join(", ")
+            ),
+        );
+        check(
+            "database_path",
+            !self.database_path.is_empty(),
+            "database_path cannot be empty".to_string(),
+        );
+        check(
+            "socket_path",
+            !self.socket_path.is_empty(),
+            "socket_path cannot be empty".to_string(),
+        );
+
+        // Settings that can also be changed at runtime share their ranges
+        for preference in preferences::PREFERENCES {
+            if let Err(e) = preference.validate(&preference.get(self)) {
+                check(preference.key, false, format!("{:#}", e));
+            }
         }
 
-        config.validate()?;
-        Ok(config)
-    }
-
-    /// Find configuration file in standard locations
-    fn find_config_file() -> Option<PathBuf> {
-        let candidates = vec![
-            expand_home("~/.config/deskd/deskd.conf"),
-            "/etc/deskd/deskd.conf".to_string(),
-        ];
-
-        for path in candidates {
-            let path = PathBuf::from(path);
-            if path.exists() {
-                return Some(path);
+        if let Some(compositor) = &self.wayland.compositor {
+            check(
+                "wayland.compositor",
+                COMPOSITORS.contains(&compositor.as_str()),
+                format!(
+                    "Unknown compositor in wayland.compositor: {} (expected one of: {})",
+                    compositor,
+                    COMPOSITORS.join(", ")
+                ),
+            );
+        }
+        if let Some(preferred) = &self.wayland.preferred_method {
+            if let Err(e) = preferred.parse::<InputMethod>() {
+                check("wayland.preferred_method", false, format!("{:#}", e));
             }
         }
 
-        None
-    }
-
-    /// Validate configuration
-    pub fn validate(&self) -> Result<()> {
-        // Validate log level
-        let valid_levels = ["error", "warn", "info", "debug", "trace"];
-        if !valid_levels.contains(&self.log_level.as_str()) {
-            anyhow::bail!("Invalid log level: {}", self.log_level);
+        check(
+            "tasks.max_concurrent",
+            (1..=1000).contains(&self.tasks.max_concurrent),
+            format!(
+                "tasks.max_concurrent must be between 1 and 1000, got {}",
+                self.tasks.max_concurrent
+            ),
+        );
+        check(
+            "tasks.cleanup_after_days",
+            self.tasks.cleanup_after_days <= 3650,
+            format!(
+                "tasks.cleanup_after_days must be at most 3650, got {}",
+                self.tasks.cleanup_after_days
+            ),
+        );
+        check(
+            "desktop.primary_desktop",
+            !self.desktop.primary_desktop.is_empty(),
+            "desktop.primary_desktop cannot be empty (use \"auto\")".to_string(),
+        );
+        check(
+            "security.token_expiration_hours",
+            (1..=8760).contains(&self.security.token_expiration_hours),
+            format!(
+                "security.token_expiration_hours must be between 1 and 8760, got {}",
+                self.security.token_expiration_hours
+            ),
+        );
+
+        if let Some(file) = &self.logging.file {
+            check(
+                "logging.file",
+                !file.is_empty(),
+                "logging.file cannot be empty (remove it to log to stderr)".to_string(),
+            );
         }
-
-        // Validate paths are not empty
-        if self.database_path.is_empty() {
-            anyhow::bail!("database_path cannot be empty");
+        check(
+            "logging.max_file_size_mb",
+            (1..=1024).contains(&self.logging.max_file_size_mb),
+            format!(
+                "logging.max_file_size_mb must be between 1 and 1024, got {}",
+                self.logging.max_file_size_mb
+            ),
+        );
+        check(
+            "logging.max_files",
+            (1..=100).contains(&self.logging.max_files),
+            format!(
+                "logging.max_files must be between 1 and 100, got {}",
+                self.logging.max_files
+            ),
+        );
+
+        for (key, problem) in profiles::problems(self) {
+            check(&key, false, problem);
         }
-        if self.socket_path.is_empty() {
-            anyhow::bail!("socket_path cannot be empty");
+
+        // Validate backup schedule
+        if self.backup.enabled {
+            check(
+                "backup.directory",
+                !self.backup.directory.is_empty(),
+                "backup.directory cannot be empty".to_string(),
+            );
+            check(
+                "backup.interval_seconds",
+                self.backup.interval_seconds >= 60,
+                format!(
+                    "backup.interval_seconds must be at least 60, got {}",
+                    self.backup.interval_seconds
+                ),
+            );
+            check(
+                "backup.retention_count",
+                self.backup.retention_count > 0,
+                "backup.retention_count must be at least 1".to_string(),
+            );
         }
 
-        Ok(())
+        check(
+            "encryption.keyfile",
+            !(self.encryption.enabled
+                && self.encryption.key_source == KeySource::Keyfile
+                && self.encryption.keyfile.is_empty()),
+            "encryption.keyfile cannot be empty".to_string(),
+        );
+
+        problems
     }
 
     /// Get configuration paths for reference
@@ -255,7 +675,7 @@ pub struct ConfigPaths {
 }
 
 /// Expand ~ to home directory
-fn expand_home(path: &str) -> String {
+pub(crate) fn expand_home(path: &str) -> String {
     if path.starts_with("~/") {
         if let Ok(home) = std::env::var("HOME") {
             return path.replacen("~", &home, 1);
@@ -263,3 +683,60 @@ fn expand_home(path: &str) -> String {
     }
     path.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.wayland.input_methods = vec!["portal".to_string(), "xdotool".to_string()];
+        config.wayland.preferred_method = Some("telepathy".to_string());
+        config.wayland.compositor = Some("weston".to_string());
+        config.tasks.max_concurrent = 0;
+        config.security.token_expiration_hours = 0;
+
+        let keys: Vec<String> = config.problems().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(
+            keys,
+            [
+                "wayland.input_methods",
+                "wayland.compositor",
+                "wayland.preferred_method",
+                "tasks.max_concurrent",
+                "security.token_expiration_hours",
+            ]
+        );
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("xdotool"), "{}", err);
+        assert!(err.contains("weston"), "{}", err);
+    }
+
+    #[test]
+    fn test_default_log_level() {
+        let config = Config::default();
+        assert_eq!(config.log_level, "info");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_method_order() {
+        assert_eq!(
+            WaylandConfig::default().method_order(),
+            InputMethod::ALL.map(|method| method.name())
+        );
+
+        let mut wayland = WaylandConfig {
+            input_methods: vec!["portal".to_string(), "ydotool".to_string()],
+            preferred_method: Some("ydotool".to_string()),
+            ..WaylandConfig::default()
+        };
+        assert_eq!(wayland.method_order(), ["ydotool", "portal"]);
+
+        wayland.enable_fallback = false;
+        assert_eq!(wayland.method_order(), ["ydotool"]);
+    }
+}
@@ -0,0 +1,287 @@
+// Typed runtime preferences that override the configuration file
+use super::{Config, INPUT_METHODS};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Value type and constraints of a preference
+#[derive(Debug, Clone, Copy)]
+pub enum PreferenceType {
+    Integer {
+        min: u64,
+        max: u64,
+    },
+    Bool,
+    /// Non-empty list of distinct values from the given set
+    List(&'static [&'static str]),
+}
+
+impl PreferenceType {
+    /// Short description for listings, e.g. `integer (0-1000)`
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Integer { min, max } => format!("integer ({}-{})", min, max),
+            Self::Bool => "bool".to_string(),
+            Self::List(choices) => format!("list of {}", choices.join(", ")),
+        }
+    }
+}
+
+/// A setting that can be changed at runtime with `deskctl config set`
+pub struct Preference {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub kind: PreferenceType,
+    get: fn(&Config) -> Value,
+    set: fn(&mut Config, &Value),
+}
+
+impl Preference {
+    /// The value of this setting in `config`
+    pub fn get(&self, config: &Config) -> Value {
+        (self.get)(config)
+    }
+
+    /// Check a value against the preference type and normalize it.
+    /// Strings are accepted for every type so CLI input can be passed as-is.
+    pub fn validate(&self, value: &Value) -> Result<Value> {
+        match self.kind {
+            PreferenceType::Integer { min, max } => {
+                let n = match value {
+                    Value::Number(n) => n.as_u64(),
+                    Value::String(s) => s.trim().parse().ok(),
+                    _ => None,
+                }
+                .with_context(|| format!("{} must be a non-negative integer", self.key))?;
+                if !(min..=max).contains(&n) {
+                    anyhow::bail!(
+                        "{} must be between {} and {}, got {}",
+                        self.key,
+                        min,
+                        max,
+                        n
+                    );
+                }
+                Ok(Value::from(n))
+            }
+            PreferenceType::Bool => {
+                let b = match value {
+                    Value::Bool(b) => Some(*b),
+                    Value::String(s) => match s.trim() {
+                        "true" | "yes" | "on" | "1" => Some(true),
+                        "false" | "no" | "off" | "0" => Some(false),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+                .with_context(|| format!("{} must be true or false", self.key))?;
+                Ok(Value::Bool(b))
+            }
+            PreferenceType::List(choices) => {
+                let items: Vec<String> = match value {
+                    Value::Array(items) => items
+                        .iter()
+                        .map(|v| v.as_str().map(str::to_string))
+                        .collect::<Option<_>>()
+                        .with_context(|| format!("{} must be a list of strings", self.key))?,
+                    Value::String(s) => s
+                        .split(',')
+                        .map(|item| item.trim().to_string())
+                        .filter(|item| !item.is_empty())
+                        .collect(),
+                    _ => anyhow::bail!("{} must be a list of strings", self.key),
+                };
+
+                if items.is_empty() {
+                    anyhow::bail!("{} cannot be empty", self.key);
+                }
+                for (i, item) in items.iter().enumerate() {
+                    if !choices.contains(&item.as_str()) {
+                        anyhow::bail!(
+                            "Unknown value for {}: {} (expected one of: {})",
+                            self.key,
+                            item,
+                            choices.join(", ")
+                        );
+                    }
+                    if items[..i].contains(item) {
+                        anyhow::bail!("{} lists {} more than once", self.key, item);
+                    }
+                }
+                Ok(Value::from(items))
+            }
+        }
+    }
+
+    /// Apply a validated value to `config`
+    pub fn apply(&self, config: &mut Config, value: &Value) {
+        (self.set)(config, value)
+    }
+}
+
+fn as_u64(value: &Value) -> u64 {
+    value.as_u64().unwrap_or_default()
+}
+
+fn as_list(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Every runtime preference, in display order
+pub const PREFERENCES: &[Preference] = &[
+    Preference {
+        key: "input_timing.typing_delay_ms",
+        description: "Delay between keystrokes when typing",
+        kind: PreferenceType::Integer { min: 0, max: 1000 },
+        get: |c| Value::from(c.input_timing.typing_delay_ms),
+        set: |c, v| c.input_timing.typing_delay_ms = as_u64(v),
+    },
+    Preference {
+        key: "input_timing.click_delay_ms",
+        description: "Delay after clicking",
+        kind: PreferenceType::Integer { min: 0, max: 5000 },
+        get: |c| Value::from(c.input_timing.click_delay_ms),
+        set: |c, v| c.input_timing.click_delay_ms = as_u64(v),
+    },
+    Preference {
+        key: "input_timing.focus_timeout_ms",
+        description: "Timeout for focus operations",
+        kind: PreferenceType::Integer {
+            min: 100,
+            max: 60_000,
+        },
+        get: |c| Value::from(c.input_timing.focus_timeout_ms),
+        set: |c, v| c.input_timing.focus_timeout_ms = as_u64(v),
+    },
+    Preference {
+        key: "wayland.input_methods",
+        description: "Input backends to try, in order of preference",
+        kind: PreferenceType::List(INPUT_METHODS),
+        get: |c| Value::from(c.wayland.input_methods.clone()),
+        set: |c, v| c.wayland.input_methods = as_list(v),
+    },
+    Preference {
+        key: "wayland.retry_attempts",
+        description: "Attempts per input backend before falling back",
+        kind: PreferenceType::Integer { min: 0, max: 10 },
+        get: |c| Value::from(c.wayland.retry_attempts),
+        set: |c, v| c.wayland.retry_attempts = as_u64(v) as usize,
+    },
+    Preference {
+        key: "wayland.retry_delay_ms",
+        description: "Delay between retries",
+        kind: PreferenceType::Integer {
+            min: 0,
+            max: 10_000,
+        },
+        get: |c| Value::from(c.wayland.retry_delay_ms),
+        set: |c, v| c.wayland.retry_delay_ms = as_u64(v),
+    },
+    Preference {
+        key: "wayland.portal_permission_timeout_ms",
+        description: "Time allowed for answering a portal permission dialog",
+        kind: PreferenceType::Integer {
+            min: 1000,
+            max: 300_000,
+        },
+        get: |c| Value::from(c.wayland.portal_permission_timeout_ms),
+        set: |c, v| c.wayland.portal_permission_timeout_ms = as_u64(v),
+    },
+    Preference {
+        key: "wayland.enable_fallback",
+        description: "Try the other input backends when the first one fails",
+        kind: PreferenceType::Bool,
+        get: |c| Value::Bool(c.wayland.enable_fallback),
+        set: |c, v| c.wayland.enable_fallback = v.as_bool().unwrap_or(true),
+    },
+    Preference {
+        key: "clipboard.enable_history",
+        description: "Record clipboard contents in the history",
+        kind: PreferenceType::Bool,
+        get: |c| Value::Bool(c.clipboard.enable_history),
+        set: |c, v| c.clipboard.enable_history = v.as_bool().unwrap_or(true),
+    },
+    Preference {
+        key: "clipboard.max_history_entries",
+        description: "Clipboard history entries to keep",
+        kind: PreferenceType::Integer {
+            min: 0,
+            max: 10_000,
+        },
+        get: |c| Value::from(c.clipboard.max_history_entries),
+        set: |c, v| c.clipboard.max_history_entries = as_u64(v) as usize,
+    },
+    Preference {
+        key: "atspi.enable_cache",
+        description: "Cache AT-SPI element lookups",
+        kind: PreferenceType::Bool,
+        get: |c| Value::Bool(c.atspi.enable_cache),
+        set: |c, v| c.atspi.enable_cache = v.as_bool().unwrap_or(true),
+    },
+    Preference {
+        key: "atspi.cache_expiration_seconds",
+        description: "How long cached elements stay valid",
+        kind: PreferenceType::Integer {
+            min: 1,
+            max: 86_400,
+        },
+        get: |c| Value::from(c.atspi.cache_expiration_seconds),
+        set: |c, v| c.atspi.cache_expiration_seconds = as_u64(v),
+    },
+];
+
+/// Look up a preference by key
+pub fn find(key: &str) -> Result<&'static Preference> {
+    PREFERENCES
+        .iter()
+        .find(|p| p.key == key)
+        .with_context(|| format!("Unknown preference: {} (see `deskctl config get`)", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_and_coercion() {
+        let delay = find("input_timing.typing_delay_ms").unwrap();
+        assert_eq!(delay.validate(&Value::from("25")).unwrap(), Value::from(25));
+        assert!(delay.validate(&Value::from(5000)).is_err());
+        assert!(delay.validate(&Value::from("fast")).is_err());
+
+        let methods = find("wayland.input_methods").unwrap();
+        assert_eq!(
+            methods.validate(&Value::from("ydotool, portal")).unwrap(),
+            serde_json::json!(["ydotool", "portal"])
+        );
+        assert!(methods.validate(&Value::from("portal,portal")).is_err());
+        assert!(methods.validate(&Value::from("xdotool")).is_err());
+        assert!(methods.validate(&Value::from("")).is_err());
+
+        let cache = find("atspi.enable_cache").unwrap();
+        assert_eq!(
+            cache.validate(&Value::from("off")).unwrap(),
+            Value::Bool(false)
+        );
+
+        assert!(find("database_path").is_err());
+    }
+
+    #[test]
+    fn test_apply_round_trips() {
+        let mut config = Config::default();
+        for preference in PREFERENCES {
+            let value = preference.get(&config);
+            let value = preference.validate(&value).unwrap();
+            preference.apply(&mut config, &value);
+            assert_eq!(preference.get(&config), value, "{}", preference.key);
+        }
+    }
+}
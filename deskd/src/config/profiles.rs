@@ -0,0 +1,434 @@
+// Per-application profiles: settings that apply when a request targets a
+// matching application
+use super::preferences::{self, PREFERENCES};
+use super::{AtspiConfig, Config, InputTiming};
+use crate::wayland::compositor::FocusedWindow;
+use crate::wayland::input::InputMethod;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use toml::Table;
+
+/// Name of the `[[profile]]` array in config files
+pub const PROFILE_KEY: &str = "profile";
+
+/// Keys of a profile table other than its override sections
+pub const PROFILE_FIELDS: &[&str] = &[
+    "name",
+    "app_name",
+    "window_class",
+    "app_id",
+    "typing_strategy",
+];
+
+/// Sections a profile can override, with the same keys as at the top level
+pub const PROFILE_SECTIONS: &[&str] = &["input_timing", "wayland", "atspi"];
+
+/// Overridable keys that are not runtime preferences
+const EXTRA_KEYS: &[&str] = &["wayland.preferred_method"];
+
+/// How text is entered into an application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypingStrategy {
+    /// Key events through the input backends
+    Keystrokes,
+    /// AT-SPI EditableText on the target element
+    EditableText,
+    /// Put the text on the clipboard and paste it
+    ClipboardPaste,
+}
+
+impl fmt::Display for TypingStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Keystrokes => "keystrokes",
+            Self::EditableText => "editable_text",
+            Self::ClipboardPaste => "clipboard_paste",
+        })
+    }
+}
+
+/// A `[[profile]]` section. It matches when any of its patterns matches the
+/// target application; `*` matches any run of characters and case is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+
+    /// AT-SPI application names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_name: Vec<String>,
+
+    /// X11/XWayland window classes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub window_class: Vec<String>,
+
+    /// Wayland app_ids as reported by the compositor
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_id: Vec<String>,
+
+    /// Strategies to try when typing, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub typing_strategy: Vec<TypingStrategy>,
+
+    #[serde(default, skip_serializing_if = "Table::is_empty")]
+    pub input_timing: Table,
+
+    #[serde(default, skip_serializing_if = "Table::is_empty")]
+    pub wayland: Table,
+
+    #[serde(default, skip_serializing_if = "Table::is_empty")]
+    pub atspi: Table,
+}
+
+/// What is known about the application a request targets
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AppIdentity {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+}
+
+impl AppIdentity {
+    /// Identity of the focused window, plus the AT-SPI application name
+    /// when the request targets an element
+    pub fn new(window: Option<FocusedWindow>, app_name: Option<String>) -> Self {
+        let window = window.unwrap_or_default();
+        Self {
+            app_name,
+            window_class: window.window_class,
+            app_id: window.app_id,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.app_name.is_none() && self.window_class.is_none() && self.app_id.is_none()
+    }
+}
+
+/// Effective settings for one request
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSettings {
+    /// Name of the matching profile, if any
+    pub profile: Option<String>,
+    pub input_timing: InputTiming,
+    /// Input methods in the order they are tried
+    pub method_order: Vec<String>,
+    pub atspi: AtspiConfig,
+    pub typing_strategy: Vec<TypingStrategy>,
+}
+
+impl Profile {
+    pub fn matches(&self, app: &AppIdentity) -> bool {
+        let any = |patterns: &[String], value: &Option<String>| {
+            value
+                .as_deref()
+                .is_some_and(|value| patterns.iter().any(|p| glob_match(p, value)))
+        };
+        any(&self.app_name, &app.app_name)
+            || any(&self.window_class, &app.window_class)
+            || any(&self.app_id, &app.app_id)
+    }
+
+    /// `(section.key, value)` for every override in the profile
+    fn overrides(&self) -> impl Iterator<Item = (String, &toml::Value)> {
+        [
+            ("input_timing", &self.input_timing),
+            ("wayland", &self.wayland),
+            ("atspi", &self.atspi),
+        ]
+        .into_iter()
+        .flat_map(|(section, table)| {
+            table
+                .iter()
+                .map(move |(key, value)| (format!("{}.{}", section, key), value))
+        })
+    }
+
+    /// Apply the overrides to `config`, stopping at the first invalid one
+    fn apply(&self, config: &mut Config) -> Result<()> {
+        for (key, value) in self.overrides() {
+            let value = serde_json::to_value(value)?;
+            if key == "wayland.preferred_method" {
+                let method: InputMethod = value
+                    .as_str()
+                    .context("wayland.preferred_method must be a string")?
+                    .parse()?;
+                config.wayland.preferred_method = Some(method.name().to_string());
+                continue;
+            }
+            let preference = preferences::find(&key)
+                .ok()
+                .filter(|_| is_overridable(&key))
+                .with_context(|| format!("{} cannot be set in a profile", key))?;
+            let value = preference.validate(&value)?;
+            preference.apply(config, &value);
+        }
+        Ok(())
+    }
+}
+
+/// Whether a profile can override `key` (`section.key`)
+pub fn is_overridable(key: &str) -> bool {
+    overridable_keys().any(|k| k == key)
+}
+
+/// Every key a profile can override
+pub fn overridable_keys() -> impl Iterator<Item = &'static str> {
+    PREFERENCES
+        .iter()
+        .map(|p| p.key)
+        .chain(EXTRA_KEYS.iter().copied())
+        .filter(|key| {
+            key.split_once('.')
+                .is_some_and(|(section, _)| PROFILE_SECTIONS.contains(&section))
+        })
+}
+
+impl Config {
+    /// The first profile matching `app`
+    pub fn profile_for(&self, app: &AppIdentity) -> Option<&Profile> {
+        if app.is_empty() {
+            return None;
+        }
+        self.profiles.iter().find(|profile| profile.matches(app))
+    }
+
+    /// Settings for a request targeting `app`: the matching profile's
+    /// overrides on top of the global configuration
+    pub fn settings_for(&self, app: &AppIdentity) -> AppSettings {
+        let mut config = self.clone();
+        let profile = self.profile_for(app);
+        if let Some(profile) = profile {
+            // Profiles are validated on load, so this only guards against
+            // a preference changing the ranges underneath
+            if let Err(e) = profile.apply(&mut config) {
+                tracing::warn!("Ignoring profile {}: {:#}", profile.name, e);
+                config = self.clone();
+            }
+        }
+
+        let typing_strategy = match profile {
+            Some(profile) if !profile.typing_strategy.is_empty() => profile.typing_strategy.clone(),
+            _ => vec![TypingStrategy::Keystrokes],
+        };
+        AppSettings {
+            profile: profile.map(|p| p.name.clone()),
+            method_order: config.wayland.method_order(),
+            input_timing: config.input_timing,
+            atspi: config.atspi,
+            typing_strategy,
+        }
+    }
+}
+
+/// Problems in the `[[profile]]` sections, as `(profile.<name>, problem)`
+pub(super) fn problems(config: &Config) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    for (i, profile) in config.profiles.iter().enumerate() {
+        if profile.name.trim().is_empty() {
+            problems.push((
+                PROFILE_KEY.to_string(),
+                format!("profile #{} needs a name", i + 1),
+            ));
+            continue;
+        }
+        let key = format!("{}.{}", PROFILE_KEY, profile.name);
+        let mut check = |ok: bool, problem: String| {
+            if !ok {
+                problems.push((key.clone(), problem));
+            }
+        };
+
+        check(
+            !config.profiles[..i].iter().any(|p| p.name == profile.name),
+            format!("profile {} is defined more than once", profile.name),
+        );
+        check(
+            !(profile.app_name.is_empty()
+                && profile.window_class.is_empty()
+                && profile.app_id.is_empty()),
+            format!(
+                "profile {} must set app_name, window_class or app_id",
+                profile.name
+            ),
+        );
+        for (j, strategy) in profile.typing_strategy.iter().enumerate() {
+            check(
+                !profile.typing_strategy[..j].contains(strategy),
+                format!(
+                    "profile {} lists typing strategy {} more than once",
+                    profile.name, strategy
+                ),
+            );
+        }
+        if let Err(e) = profile.apply(&mut config.clone()) {
+            check(false, format!("profile {}: {:#}", profile.name, e));
+        }
+    }
+    problems
+}
+
+/// Case-insensitive match where `*` matches any run of characters
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let value = value.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the whole value must match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(contents: &str) -> Config {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("code", "Code"));
+        assert!(!glob_match("code", "code-oss"));
+        assert!(glob_match("libreoffice-*", "libreoffice-writer"));
+        assert!(glob_match("*electron*", "my-electron-app"));
+        assert!(glob_match("org.*.Slack", "org.chromium.Slack"));
+        assert!(!glob_match("org.*.Slack", "org.chromium.Slacker"));
+        assert!(glob_match("*", "anything"));
+    }
+
+    #[test]
+    fn test_settings_for_matching_profile() {
+        let config = load(
+            r#"
+[input_timing]
+typing_delay_ms = 10
+
+[[profile]]
+name = "electron"
+app_id = ["code", "Slack"]
+window_class = ["*-electron"]
+typing_strategy = ["clipboard_paste", "keystrokes"]
+
+[profile.input_timing]
+typing_delay_ms = 25
+
+[[profile]]
+name = "libreoffice"
+app_name = ["soffice"]
+app_id = ["libreoffice-*"]
+typing_strategy = ["editable_text"]
+
+[profile.wayland]
+preferred_method = "ydotool"
+
+[profile.atspi]
+enable_cache = false
+"#,
+        );
+        assert_eq!(problems(&config), []);
+
+        let slack = config.settings_for(&AppIdentity {
+            app_id: Some("slack".to_string()),
+            ..AppIdentity::default()
+        });
+        assert_eq!(slack.profile.as_deref(), Some("electron"));
+        assert_eq!(slack.input_timing.typing_delay_ms, 25);
+        assert_eq!(
+            slack.typing_strategy,
+            [TypingStrategy::ClipboardPaste, TypingStrategy::Keystrokes]
+        );
+
+        let writer = config.settings_for(&AppIdentity {
+            app_name: Some("soffice".to_string()),
+            window_class: Some("unrelated".to_string()),
+            ..AppIdentity::default()
+        });
+        assert_eq!(writer.profile.as_deref(), Some("libreoffice"));
+        assert_eq!(writer.input_timing.typing_delay_ms, 10);
+        assert_eq!(writer.method_order[0], "ydotool");
+        assert!(!writer.atspi.enable_cache);
+
+        let gedit = config.settings_for(&AppIdentity {
+            app_id: Some("org.gnome.gedit".to_string()),
+            ..AppIdentity::default()
+        });
+        assert_eq!(gedit.profile, None);
+        assert_eq!(gedit.typing_strategy, [TypingStrategy::Keystrokes]);
+        assert_eq!(gedit.method_order, config.wayland.method_order());
+    }
+
+    #[test]
+    fn test_app_name_only_profile() {
+        let config = load(
+            r#"
+[[profile]]
+name = "libreoffice"
+app_name = ["soffice"]
+typing_strategy = ["editable_text"]
+"#,
+        );
+        assert_eq!(problems(&config), []);
+
+        // GNOME and KDE report no focused window; the AT-SPI name suffices
+        let writer = config.settings_for(&AppIdentity::new(None, Some("soffice".to_string())));
+        assert_eq!(writer.profile.as_deref(), Some("libreoffice"));
+        assert_eq!(writer.typing_strategy, [TypingStrategy::EditableText]);
+
+        let window = FocusedWindow {
+            app_id: Some("libreoffice-writer".to_string()),
+            ..FocusedWindow::default()
+        };
+        assert_eq!(
+            config
+                .settings_for(&AppIdentity::new(Some(window), None))
+                .profile,
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_profiles_reported() {
+        let config = load(
+            r#"
+[[profile]]
+name = "slow"
+app_id = ["a"]
+[profile.input_timing]
+typing_delay_ms = 5000
+
+[[profile]]
+name = "slow"
+[profile.wayland]
+compositor = "sway"
+"#,
+        );
+        let problems = problems(&config);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().all(|(key, _)| key == "profile.slow"));
+        assert!(problems[0].1.contains("typing_delay_ms must be between"));
+        assert!(problems[1].1.contains("defined more than once"));
+        assert!(problems[2].1.contains("must set app_name"));
+        assert!(problems[3]
+            .1
+            .contains("wayland.compositor cannot be set in a profile"));
+    }
+}
@@ -0,0 +1,439 @@
+// Effective configuration: the config file with stored preferences applied
+use super::layers::{ConfigLoader, LoadedConfig, Origin};
+use super::preferences::{self, Preference};
+use super::profiles::PROFILE_KEY;
+use super::{Config, RESTART_REQUIRED};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Configuration shared by the daemon. Readers take a cheap snapshot with
+/// `current()`; preferences changed at runtime and config reloads replace
+/// the snapshot, and `subscribe()` reports each replacement.
+pub struct RuntimeConfig {
+    loader: ConfigLoader,
+    state: RwLock<State>,
+    changes: watch::Sender<Arc<Config>>,
+    /// Held by a reload or preference change from reading the state until
+    /// the new configuration is installed, so neither undoes the other
+    update: Mutex<()>,
+}
+
+struct State {
+    /// Configuration from files, environment and flags
+    file: Arc<Config>,
+    origins: BTreeMap<String, Origin>,
+    effective: Arc<Config>,
+    overrides: BTreeMap<String, Value>,
+}
+
+/// One effective setting and where it came from
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigValue {
+    pub key: String,
+    pub value: Value,
+    /// "default", "preference", `<file>:<line>`, `env <VAR>` or `flag <--flag>`
+    pub origin: String,
+}
+
+/// Outcome of reloading the config file
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadReport {
+    /// Files that were read, in the order they were applied
+    pub files: Vec<String>,
+    /// Changed settings now in effect
+    pub applied: Vec<String>,
+    /// Changed settings that take effect after a restart
+    pub restart_required: Vec<String>,
+    /// Changed settings still overridden by a runtime preference
+    pub overridden: Vec<String>,
+    /// Unknown keys in the file
+    pub warnings: Vec<String>,
+}
+
+/// A preference as reported to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct PreferenceInfo {
+    pub key: &'static str,
+    /// Effective value
+    pub value: Value,
+    /// Value from the configuration file (or built-in default)
+    pub config_value: Value,
+    /// "preference" when overridden at runtime, otherwise "config"
+    pub source: &'static str,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub description: &'static str,
+}
+
+impl RuntimeConfig {
+    /// `loaded` must come from `loader`, which is reused on reload
+    pub fn new(loader: ConfigLoader, loaded: LoadedConfig) -> Self {
+        let file = Arc::new(loaded.config);
+        Self {
+            loader,
+            state: RwLock::new(State {
+                effective: file.clone(),
+                overrides: BTreeMap::new(),
+                origins: loaded.origins,
+                file: file.clone(),
+            }),
+            changes: watch::Sender::new(file),
+            update: Mutex::new(()),
+        }
+    }
+
+    pub fn loader(&self) -> &ConfigLoader {
+        &self.loader
+    }
+
+    /// Snapshot of the effective configuration
+    pub fn current(&self) -> Arc<Config> {
+        self.read().effective.clone()
+    }
+
+    /// Receives the effective configuration each time it is replaced
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.changes.subscribe()
+    }
+
+    /// Re-read every configuration layer. On error the running
+    /// configuration is left untouched.
+    pub fn reload(&self) -> Result<ReloadReport> {
+        let _update = self.lock_update();
+        let loaded = self.loader.load()?;
+        let mut file = loaded.config;
+        let mut origins = loaded.origins;
+
+        let (previous, previous_origins, overrides) = {
+            let state = self.read();
+            (
+                state.file.clone(),
+                state.origins.clone(),
+                state.overrides.clone(),
+            )
+        };
+
+        let mut report = ReloadReport {
+            files: loaded
+                .files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            applied: Vec::new(),
+            restart_required: Vec::new(),
+            overridden: Vec::new(),
+            warnings: loaded.warnings,
+        };
+        for key in changed_keys(&previous, &file) {
+            if restart_required(&key) {
+                report.restart_required.push(key);
+            } else if overrides.contains_key(&key) {
+                report.overridden.push(key);
+            } else {
+                report.applied.push(key);
+            }
+        }
+
+        // Keep reporting what the daemon actually runs with until a restart
+        file.database_path = previous.database_path.clone();
+        file.socket_path = previous.socket_path.clone();
+        file.session_discovery = previous.session_discovery.clone();
+        file.backup = previous.backup.clone();
+        file.encryption = previous.encryption.clone();
+        file.logging = previous.logging.clone();
+        origins.retain(|key, _| !restart_required(key));
+        origins.extend(
+            previous_origins
+                .into_iter()
+                .filter(|(key, _)| restart_required(key)),
+        );
+
+        let mut effective = file.clone();
+        for (key, value) in &overrides {
+            if let Ok(preference) = preferences::find(key) {
+                preference.apply(&mut effective, value);
+            }
+        }
+        self.install(Arc::new(file), effective, overrides, Some(origins));
+
+        Ok(report)
+    }
+
+    /// Rebuild the effective configuration from stored `(key, JSON value)`
+    /// pairs. Unknown keys and invalid values are skipped with a warning so a
+    /// bad row never keeps the daemon from starting.
+    pub fn apply_preferences<'a>(&self, stored: impl IntoIterator<Item = (&'a str, &'a str)>) {
+        let _update = self.lock_update();
+        let file = self.read().file.clone();
+        let mut effective = Config::clone(&file);
+        let mut overrides = BTreeMap::new();
+
+        for (key, raw) in stored {
+            let result = preferences::find(key).and_then(|preference| {
+                let value = serde_json::from_str(raw).unwrap_or(Value::from(raw));
+                let value = preference.validate(&value)?;
+                preference.apply(&mut effective, &value);
+                Ok(value)
+            });
+            match result {
+                Ok(value) => {
+                    overrides.insert(key.to_string(), value);
+                }
+                Err(e) => warn!("Ignoring stored preference {}: {:#}", key, e),
+            }
+        }
+
+        self.install(file, effective, overrides, None);
+    }
+
+    /// Replace the state in one write, keeping the origins when `None`.
+    /// Callers hold `update`.
+    fn install(
+        &self,
+        file: Arc<Config>,
+        effective: Config,
+        overrides: BTreeMap<String, Value>,
+        origins: Option<BTreeMap<String, Origin>>,
+    ) {
+        let effective = Arc::new(effective);
+        {
+            let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+            state.file = file;
+            state.effective = effective.clone();
+            state.overrides = overrides;
+            if let Some(origins) = origins {
+                state.origins = origins;
+            }
+        }
+        self.changes.send_replace(effective);
+    }
+
+    pub fn describe(&self, preference: &'static Preference) -> PreferenceInfo {
+        let state = self.read();
+        PreferenceInfo {
+            key: preference.key,
+            value: preference.get(&state.effective),
+            config_value: preference.get(&state.file),
+            source: if state.overrides.contains_key(preference.key) {
+                "preference"
+            } else {
+                "config"
+            },
+            kind: preference.kind.describe(),
+            description: preference.description,
+        }
+    }
+
+    /// Every effective setting with its origin
+    pub fn describe_config(&self) -> Vec<ConfigValue> {
+        let state = self.read();
+        flatten(&state.effective)
+            .into_iter()
+            .map(|(key, value)| {
+                let origin = if state.overrides.contains_key(&key) {
+                    "preference".to_string()
+                } else {
+                    state
+                        .origins
+                        .get(&key)
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| "default".to_string())
+                };
+                ConfigValue {
+                    value: serde_json::to_value(value).unwrap_or(Value::Null),
+                    key,
+                    origin,
+                }
+            })
+            .collect()
+    }
+
+    pub fn describe_all(&self) -> Vec<PreferenceInfo> {
+        preferences::PREFERENCES
+            .iter()
+            .map(|preference| self.describe(preference))
+            .collect()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_update(&self) -> std::sync::MutexGuard<'_, ()> {
+        self.update.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn restart_required(key: &str) -> bool {
+    RESTART_REQUIRED.iter().any(|prefix| {
+        key.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Dotted keys whose values differ between two configurations
+fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let (old, new) = (flatten(old), flatten(new));
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+fn flatten(config: &Config) -> BTreeMap<String, toml::Value> {
+    let mut values = BTreeMap::new();
+    if let Ok(toml::Value::Table(table)) = toml::Value::try_from(config) {
+        for (name, value) in table {
+            match value {
+                toml::Value::Table(section) => {
+                    for (key, value) in section {
+                        values.insert(format!("{}.{}", name, key), value);
+                    }
+                }
+                // One entry per profile, keyed by its name
+                toml::Value::Array(profiles) if name == PROFILE_KEY => {
+                    for profile in profiles {
+                        let profile_name = profile.get("name").and_then(|n| n.as_str());
+                        let key = format!("{}.{}", name, profile_name.unwrap_or_default());
+                        values.insert(key, profile);
+                    }
+                }
+                value => {
+                    values.insert(name, value);
+                }
+            }
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Runtime config whose only layer is `dir/deskd.conf` as the user file
+    fn isolated(dir: &Path) -> RuntimeConfig {
+        let loader = ConfigLoader::isolated(&dir.join("etc"), &dir.join("deskd.conf"), &[]);
+        let loaded = loader.load().unwrap();
+        RuntimeConfig::new(loader, loaded)
+    }
+
+    #[test]
+    fn test_preferences_override_file_values() {
+        let runtime = isolated(Path::new("/nonexistent"));
+        runtime.apply_preferences([
+            ("input_timing.typing_delay_ms", "25"),
+            ("wayland.input_methods", r#"["ydotool"]"#),
+            ("input_timing.click_delay_ms", "999999"),
+            ("no.such.key", "1"),
+        ]);
+
+        let config = runtime.current();
+        assert_eq!(config.input_timing.typing_delay_ms, 25);
+        assert_eq!(config.wayland.input_methods, ["ydotool"]);
+        // Invalid stored values fall back to the file
+        assert_eq!(config.input_timing.click_delay_ms, 50);
+
+        let info = runtime.describe(preferences::find("input_timing.typing_delay_ms").unwrap());
+        assert_eq!(info.source, "preference");
+        assert_eq!(info.config_value, Value::from(10));
+
+        runtime.apply_preferences([]);
+        assert_eq!(runtime.current().input_timing.typing_delay_ms, 10);
+    }
+
+    #[test]
+    fn test_reload_applies_live_settings() {
+        let dir = std::env::temp_dir().join(format!("deskd-reload-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("deskd.conf");
+
+        let runtime = isolated(&dir);
+        runtime.apply_preferences([("input_timing.click_delay_ms", "80")]);
+        let mut changes = runtime.subscribe();
+        changes.borrow_and_update();
+
+        std::fs::write(
+            &path,
+            "log_level = \"debug\"
+socket_path = \"/tmp/elsewhere.sock\"
+[input_timing]
+typing_delay_ms = 30
+click_delay_ms = 5
+[logging]
+format = \"json\"
+",
+        )
+        .unwrap();
+        let report = runtime.reload().unwrap();
+        assert_eq!(report.files, [path.display().to_string()]);
+        assert_eq!(
+            report.applied,
+            ["input_timing.typing_delay_ms", "log_level"]
+        );
+        assert_eq!(report.restart_required, ["logging.format", "socket_path"]);
+        assert_eq!(report.overridden, ["input_timing.click_delay_ms"]);
+
+        let config = runtime.current();
+        assert_eq!(config.input_timing.typing_delay_ms, 30);
+        assert_eq!(config.input_timing.click_delay_ms, 80);
+        assert_eq!(config.socket_path, Config::default().socket_path);
+        assert_eq!(config.logging.format, Config::default().logging.format);
+        assert!(changes.has_changed().unwrap());
+
+        let origins: BTreeMap<String, String> = runtime
+            .describe_config()
+            .into_iter()
+            .map(|setting| (setting.key, setting.origin))
+            .collect();
+        assert_eq!(
+            origins["input_timing.typing_delay_ms"],
+            format!("{}:4", path.display())
+        );
+        assert_eq!(origins["input_timing.click_delay_ms"], "preference");
+        // Not applied, so still the value the daemon started with
+        assert_eq!(origins["socket_path"], "default");
+        assert_eq!(origins["logging.format"], "default");
+        assert_eq!(origins["atspi.enable_cache"], "default");
+
+        // An invalid file keeps the running configuration
+        std::fs::write(&path, "[input_timing]\ntyping_delay_ms = 5000\n").unwrap();
+        assert!(runtime.reload().is_err());
+        assert_eq!(runtime.current().input_timing.typing_delay_ms, 30);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_keeps_concurrent_preferences() {
+        let dir = std::env::temp_dir().join(format!("deskd-reload-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("deskd.conf"), "log_level = \"debug\"\n").unwrap();
+
+        // Each reload must see the preferences installed before it, or it
+        // puts back older ones
+        let runtime = isolated(&dir);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..50 {
+                    runtime.reload().unwrap();
+                }
+            });
+            for delay in 1..=200 {
+                let delay = delay.to_string();
+                runtime.apply_preferences([("input_timing.typing_delay_ms", delay.as_str())]);
+            }
+        });
+        assert_eq!(runtime.current().input_timing.typing_delay_ms, 200);
+        assert_eq!(runtime.current().log_level, "debug");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
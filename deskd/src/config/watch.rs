@@ -0,0 +1,87 @@
+// Reload the configuration on SIGHUP or when the file changes
+use super::runtime::RuntimeConfig;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+/// How often the config files are checked when `watch_config` is enabled
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Run until the daemon exits. Files are polled by modification time, which
+/// also notices config files created or removed after startup.
+pub async fn run(config: Arc<RuntimeConfig>) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    let mut modified = files_modified(&config);
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                info!("Received SIGHUP, reloading configuration");
+            }
+            _ = poll.tick() => {
+                if !config.current().watch_config || files_modified(&config) == modified {
+                    continue;
+                }
+                info!("Configuration file changed, reloading");
+            }
+        }
+
+        modified = files_modified(&config);
+        reload(&config);
+    }
+}
+
+/// Reload and log the outcome
+fn reload(config: &RuntimeConfig) {
+    match config.reload() {
+        Ok(report) => {
+            for warning in &report.warnings {
+                warn!("{}", warning);
+            }
+            if report.applied.is_empty()
+                && report.overridden.is_empty()
+                && report.restart_required.is_empty()
+            {
+                info!("Configuration reloaded, nothing changed");
+            }
+            if !report.applied.is_empty() {
+                info!(
+                    "Applied configuration changes: {}",
+                    report.applied.join(", ")
+                );
+            }
+            if !report.overridden.is_empty() {
+                info!(
+                    "Changed but overridden by preferences: {}",
+                    report.overridden.join(", ")
+                );
+            }
+            if !report.restart_required.is_empty() {
+                warn!(
+                    "Restart deskd to apply: {}",
+                    report.restart_required.join(", ")
+                );
+            }
+        }
+        Err(e) => error!(
+            "Configuration reload failed, keeping current settings: {:#}",
+            e
+        ),
+    }
+}
+
+fn files_modified(config: &RuntimeConfig) -> Vec<(PathBuf, Option<SystemTime>)> {
+    config
+        .loader()
+        .files()
+        .into_iter()
+        .map(|path| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
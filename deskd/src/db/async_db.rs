@@ -0,0 +1,333 @@
+// Non-blocking access to the database from async code
+use super::audit::{AuditStats, AuditWriter};
+use super::clipboard::ClipboardEntry;
+use super::element_cache::StoredElement;
+use super::preferences::StoredPreference;
+use super::query::{QueryLimits, QueryResult};
+use super::search::{SearchHit, SearchQuery};
+use super::snapshots::{SnapshotInfo, TreeSnapshot};
+use super::stats::{StatsQuery, StatsReport};
+use super::tasks::{AuditEntry, TaskHistoryEntry};
+use super::transfer::{DataFormat, DataTable, ExportFilter, ExportResult, ImportResult};
+use super::workflows::Workflow;
+use super::{Database, POOL_SIZE};
+use crate::atspi::cache::Invalidation;
+use crate::atspi::element::Element;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Async front end to `Database`. Blocking SQLite work runs on tokio's
+/// blocking pool, at most one job per pooled connection so waiting for a
+/// connection never parks more threads than the pool can serve. Audit rows
+/// go through a background writer and never wait for SQLite.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    database: Arc<Database>,
+    permits: Arc<Semaphore>,
+    audit: AuditWriter,
+}
+
+impl AsyncDatabase {
+    /// Wrap a migrated database; must be called inside the tokio runtime
+    pub fn new(database: Arc<Database>) -> Self {
+        Self {
+            audit: AuditWriter::spawn(database.clone()),
+            permits: Arc::new(Semaphore::new(POOL_SIZE as usize)),
+            database,
+        }
+    }
+
+    /// The underlying database, for code that already runs on a blocking thread
+    pub fn blocking(&self) -> &Arc<Database> {
+        &self.database
+    }
+
+    /// Run `f` against the database on the blocking pool
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Database) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .context("Database is shutting down")?;
+        let database = self.database.clone();
+        tokio::task::spawn_blocking(move || f(&database))
+            .await
+            .context("Database task failed")?
+    }
+
+    pub async fn get_schema_version(&self) -> Result<i32> {
+        self.run(|db| db.get_schema_version()).await
+    }
+
+    pub async fn execute_query(&self, sql: String, limits: QueryLimits) -> Result<QueryResult> {
+        self.run(move |db| db.execute_query(&sql, &limits)).await
+    }
+
+    // Audit trail
+
+    /// Queue an audit entry; returns once it is queued, not written
+    pub async fn audit(&self, entry: AuditEntry) {
+        self.audit.write(entry).await
+    }
+
+    /// Wait until every queued audit entry has been written
+    pub async fn flush_audit(&self) {
+        self.audit.flush().await
+    }
+
+    pub fn audit_stats(&self) -> AuditStats {
+        self.audit.stats()
+    }
+
+    pub async fn list_task_history(&self, limit: usize) -> Result<Vec<TaskHistoryEntry>> {
+        self.run(move |db| db.list_task_history(limit)).await
+    }
+
+    pub async fn operation_stats(&self, query: StatsQuery) -> Result<StatsReport> {
+        self.run(move |db| db.operation_stats(&query)).await
+    }
+
+    // Search, export and import
+
+    pub async fn search(&self, query: SearchQuery) -> Result<Vec<SearchHit>> {
+        self.run(move |db| db.search(&query)).await
+    }
+
+    pub async fn export_data(
+        &self,
+        tables: Vec<DataTable>,
+        format: DataFormat,
+        filter: ExportFilter,
+    ) -> Result<ExportResult> {
+        self.run(move |db| db.export_data(&tables, format, &filter))
+            .await
+    }
+
+    pub async fn import_data(
+        &self,
+        content: String,
+        format: DataFormat,
+        table: Option<DataTable>,
+    ) -> Result<ImportResult> {
+        self.run(move |db| db.import_data(&content, format, table))
+            .await
+    }
+
+    // Preferences
+
+    pub async fn list_preferences(&self) -> Result<Vec<StoredPreference>> {
+        self.run(|db| db.list_preferences()).await
+    }
+
+    pub async fn set_preference(
+        &self,
+        key: &'static str,
+        value: String,
+        description: &'static str,
+    ) -> Result<()> {
+        self.run(move |db| db.set_preference(key, &value, description))
+            .await
+    }
+
+    pub async fn delete_preference(&self, key: &'static str) -> Result<bool> {
+        self.run(move |db| db.delete_preference(key)).await
+    }
+
+    // Clipboard history
+
+    #[allow(dead_code)]
+    pub async fn add_clipboard_entry(
+        &self,
+        content: String,
+        content_type: Option<String>,
+        session_id: Option<String>,
+        max_entries: usize,
+    ) -> Result<i64> {
+        self.run(move |db| {
+            db.add_clipboard_entry(
+                &content,
+                content_type.as_deref(),
+                session_id.as_deref(),
+                max_entries,
+            )
+        })
+        .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn list_clipboard_history(&self, limit: usize) -> Result<Vec<ClipboardEntry>> {
+        self.run(move |db| db.list_clipboard_history(limit)).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn clear_clipboard_history(&self) -> Result<usize> {
+        self.run(|db| db.clear_clipboard_history()).await
+    }
+
+    // Workflows
+
+    #[allow(dead_code)]
+    pub async fn save_workflow(
+        &self,
+        id: String,
+        name: String,
+        description: Option<String>,
+        steps: serde_json::Value,
+    ) -> Result<()> {
+        self.run(move |db| db.save_workflow(&id, &name, description.as_deref(), &steps))
+            .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_workflow(&self, id: String) -> Result<Option<Workflow>> {
+        self.run(move |db| db.get_workflow(&id)).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn list_workflows(&self) -> Result<Vec<Workflow>> {
+        self.run(|db| db.list_workflows()).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_workflow(&self, id: String) -> Result<bool> {
+        self.run(move |db| db.delete_workflow(&id)).await
+    }
+
+    // Tree snapshots
+
+    pub async fn save_tree_snapshot(
+        &self,
+        name: String,
+        scope: String,
+        node_count: usize,
+        tree: serde_json::Value,
+    ) -> Result<i64> {
+        self.run(move |db| db.save_tree_snapshot(&name, &scope, node_count, &tree))
+            .await
+    }
+
+    pub async fn list_tree_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        self.run(|db| db.list_tree_snapshots()).await
+    }
+
+    pub async fn get_tree_snapshot(&self, id: i64) -> Result<Option<TreeSnapshot>> {
+        self.run(move |db| db.get_tree_snapshot(id)).await
+    }
+
+    pub async fn delete_tree_snapshot(&self, id: i64) -> Result<bool> {
+        self.run(move |db| db.delete_tree_snapshot(id)).await
+    }
+
+    // Element cache
+
+    pub async fn store_cached_element(
+        &self,
+        desktop_id: String,
+        key: String,
+        element: Element,
+        ancestors: Option<Vec<String>>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.run(move |db| {
+            db.store_cached_element(
+                &desktop_id,
+                &key,
+                &element,
+                ancestors.as_deref(),
+                expires_at,
+            )
+        })
+        .await
+    }
+
+    pub async fn load_cached_element(
+        &self,
+        desktop_id: String,
+        key: String,
+    ) -> Result<Option<StoredElement>> {
+        self.run(move |db| db.load_cached_element(&desktop_id, &key))
+            .await
+    }
+
+    pub async fn record_element_cache_hit(&self, desktop_id: String, key: String) -> Result<()> {
+        self.run(move |db| db.record_element_cache_hit(&desktop_id, &key))
+            .await
+    }
+
+    pub async fn record_element_cache_miss(&self, desktop_id: String, key: String) -> Result<()> {
+        self.run(move |db| db.record_element_cache_miss(&desktop_id, &key))
+            .await
+    }
+
+    pub async fn delete_cached_element(&self, desktop_id: String, key: String) -> Result<()> {
+        self.run(move |db| db.delete_cached_element(&desktop_id, &key))
+            .await
+    }
+
+    pub async fn invalidate_cached_elements(
+        &self,
+        desktop_id: String,
+        invalidation: Invalidation,
+    ) -> Result<usize> {
+        self.run(move |db| db.invalidate_cached_elements(&desktop_id, &invalidation))
+            .await
+    }
+
+    pub async fn purge_expired_elements(&self, desktop_id: String) -> Result<usize> {
+        self.run(move |db| db.purge_expired_elements(&desktop_id))
+            .await
+    }
+
+    pub async fn purge_other_desktops(&self, desktop_id: String) -> Result<usize> {
+        self.run(move |db| db.purge_other_desktops(&desktop_id))
+            .await
+    }
+
+    pub async fn clear_cached_elements(&self, desktop_id: String) -> Result<usize> {
+        self.run(move |db| db.clear_cached_elements(&desktop_id))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_calls_beyond_pool_size() {
+        let database = TestDatabase::new().await;
+        let db = AsyncDatabase::new(database.shared());
+
+        let calls = (0..POOL_SIZE * 4).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.set_preference("input_timing.typing_delay_ms", i.to_string(), "test")
+                    .await?;
+                db.get_schema_version().await
+            })
+        });
+        for call in calls {
+            assert_eq!(
+                call.await.unwrap().unwrap(),
+                crate::db::schema::CURRENT_SCHEMA_VERSION
+            );
+        }
+
+        let stored = db.list_preferences().await.unwrap();
+        assert_eq!(stored.len(), 1);
+
+        // Errors from the closure are returned as-is
+        let err = db
+            .run(|_| -> Result<()> { anyhow::bail!("boom") })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "boom");
+    }
+}
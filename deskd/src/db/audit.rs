@@ -0,0 +1,259 @@
+// Background writer that batches audit rows into few transactions
+use super::tasks::AuditEntry;
+use super::Database;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, warn};
+
+/// Entries that can be queued before senders wait for the writer
+pub const QUEUE_CAPACITY: usize = 8192;
+/// Most entries written in a single transaction
+pub const MAX_BATCH: usize = 512;
+/// Attempts at committing a batch before its entries are counted as failed
+const WRITE_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+enum Command {
+    Write(AuditEntry),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Default)]
+struct Counters {
+    written: AtomicU64,
+    rejected: AtomicU64,
+    failed: AtomicU64,
+    batches: AtomicU64,
+    retries: AtomicU64,
+}
+
+/// Audit writer counters
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditStats {
+    /// Entries committed
+    pub written: u64,
+    /// Entries skipped because they violated a constraint
+    pub rejected: u64,
+    /// Entries lost because their batch could not be committed
+    pub failed: u64,
+    pub batches: u64,
+    /// Commits that failed and were attempted again
+    pub retries: u64,
+}
+
+/// Handle to the audit writer task. Entries are written in the order they
+/// are queued; whatever accumulates while one batch commits becomes the next
+/// batch, so writes are grouped under load without delaying them when idle.
+#[derive(Clone)]
+pub struct AuditWriter {
+    sender: mpsc::Sender<Command>,
+    counters: Arc<Counters>,
+}
+
+impl AuditWriter {
+    /// Start the writer on the current tokio runtime
+    pub fn spawn(database: Arc<Database>) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let counters = Arc::new(Counters::default());
+        tokio::spawn(run(database, receiver, counters.clone()));
+        Self { sender, counters }
+    }
+
+    /// Queue an entry, waiting only if the queue is full
+    pub async fn write(&self, entry: AuditEntry) {
+        if self.sender.send(Command::Write(entry)).await.is_err() {
+            error!("Audit writer has stopped; dropping entry");
+        }
+    }
+
+    /// Wait until everything queued so far has been written
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(Command::Flush(done)).await.is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    pub fn stats(&self) -> AuditStats {
+        AuditStats {
+            written: self.counters.written.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            batches: self.counters.batches.load(Ordering::Relaxed),
+            retries: self.counters.retries.load(Ordering::Relaxed),
+        }
+    }
+}
+
+async fn run(
+    database: Arc<Database>,
+    mut receiver: mpsc::Receiver<Command>,
+    counters: Arc<Counters>,
+) {
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+    let mut waiters = Vec::new();
+
+    while let Some(command) = receiver.recv().await {
+        let mut next = Some(command);
+        while let Some(command) = next.take() {
+            match command {
+                Command::Write(entry) => batch.push(entry),
+                Command::Flush(done) => waiters.push(done),
+            }
+            if batch.len() < MAX_BATCH {
+                next = receiver.try_recv().ok();
+            }
+        }
+
+        if !batch.is_empty() {
+            let entries = std::mem::replace(&mut batch, Vec::with_capacity(MAX_BATCH));
+            let count = entries.len() as u64;
+            let result = write_batch(&database, entries, &counters).await;
+
+            counters.batches.fetch_add(1, Ordering::Relaxed);
+            match result {
+                Ok(written) => {
+                    debug!("Wrote {} audit entries", written);
+                    counters
+                        .written
+                        .fetch_add(written as u64, Ordering::Relaxed);
+                    counters
+                        .rejected
+                        .fetch_add(count - written as u64, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    error!("Failed to write {} audit entries: {:#}", count, e);
+                    counters.failed.fetch_add(count, Ordering::Relaxed);
+                }
+            }
+        }
+
+        for done in waiters.drain(..) {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Commit a batch, retrying with backoff so a transient failure (a busy
+/// or briefly unavailable database) does not lose the entries
+async fn write_batch(
+    database: &Arc<Database>,
+    entries: Vec<AuditEntry>,
+    counters: &Counters,
+) -> Result<usize> {
+    let entries = Arc::new(entries);
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        let (database, batch) = (database.clone(), entries.clone());
+        let result = tokio::task::spawn_blocking(move || database.write_audit_entries(&batch))
+            .await
+            .context("Audit write task failed")
+            .and_then(|result| result);
+
+        match result {
+            Ok(written) => return Ok(written),
+            Err(e) if attempt >= WRITE_ATTEMPTS => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Failed to write {} audit entries (attempt {} of {}), retrying in {:?}: {:#}",
+                    entries.len(),
+                    attempt,
+                    WRITE_ATTEMPTS,
+                    delay,
+                    e
+                );
+                counters.retries.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tasks::TaskEvent;
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_entries_are_batched_in_order() {
+        let db = TestDatabase::new().await;
+
+        let writer = AuditWriter::spawn(db.shared());
+        writer
+            .write(AuditEntry::TaskCreated {
+                id: "t1".to_string(),
+                task_type: "Type".to_string(),
+                status: "running".to_string(),
+                metadata: None,
+            })
+            .await;
+        for i in 0..2000 {
+            writer
+                .write(AuditEntry::Event(TaskEvent {
+                    task_id: "t1".to_string(),
+                    event_type: format!("step-{}", i),
+                    details: None,
+                    user_id: None,
+                    session_id: None,
+                }))
+                .await;
+        }
+        writer.flush().await;
+
+        let stats = writer.stats();
+        assert_eq!(stats.written, 2001);
+        assert_eq!(stats.failed, 0);
+        assert!(stats.batches < 2001);
+
+        let latest = db.list_task_history(1).unwrap();
+        assert_eq!(latest[0].event_type, "step-1999");
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_is_retried() {
+        let db = TestDatabase::new().await;
+
+        // Make the first attempts fail, then bring the table back
+        db.get_conn()
+            .unwrap()
+            .execute_batch("ALTER TABLE tasks RENAME TO tasks_away")
+            .unwrap();
+        let restore = {
+            let db = db.shared();
+            tokio::spawn(async move {
+                tokio::time::sleep(RETRY_DELAY * 2).await;
+                db.get_conn()
+                    .unwrap()
+                    .execute_batch("ALTER TABLE tasks_away RENAME TO tasks")
+                    .unwrap();
+            })
+        };
+
+        let writer = AuditWriter::spawn(db.shared());
+        writer
+            .write(AuditEntry::TaskCreated {
+                id: "t1".to_string(),
+                task_type: "Type".to_string(),
+                status: "running".to_string(),
+                metadata: None,
+            })
+            .await;
+        writer.flush().await;
+        restore.await.unwrap();
+
+        let stats = writer.stats();
+        assert_eq!(stats.written, 1);
+        assert_eq!(stats.failed, 0);
+        assert!(stats.retries > 0);
+    }
+}
@@ -0,0 +1,115 @@
+// Clipboard history persistence
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+
+/// A clipboard history entry; `content` is encrypted at rest when encryption
+/// is enabled
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipboardEntry {
+    pub id: i64,
+    pub content: String,
+    pub content_type: Option<String>,
+    pub timestamp: Option<String>,
+    pub session_id: Option<String>,
+}
+
+#[allow(dead_code)]
+impl Database {
+    /// Record a clipboard entry, keeping at most `max_entries` rows
+    pub fn add_clipboard_entry(
+        &self,
+        content: &str,
+        content_type: Option<&str>,
+        session_id: Option<&str>,
+        max_entries: usize,
+    ) -> Result<i64> {
+        let conn = self.get_conn()?;
+        let content = self.seal("clipboard_history", "content", content);
+
+        conn.execute(
+            "INSERT INTO clipboard_history (content, content_type, session_id)
+             VALUES (?1, ?2, ?3)",
+            params![content, content_type, session_id],
+        )
+        .context("Failed to record clipboard entry")?;
+        let id = conn.last_insert_rowid();
+
+        conn.execute(
+            "DELETE FROM clipboard_history WHERE id NOT IN
+                (SELECT id FROM clipboard_history ORDER BY id DESC LIMIT ?1)",
+            [max_entries as i64],
+        )?;
+
+        Ok(id)
+    }
+
+    /// Most recent entries first
+    pub fn list_clipboard_history(&self, limit: usize) -> Result<Vec<ClipboardEntry>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, content, content_type, timestamp, session_id
+             FROM clipboard_history ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt
+            .query_map([limit as i64], |row| {
+                Ok(ClipboardEntry {
+                    id: row.get(0)?,
+                    content: row.get(1)?,
+                    content_type: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    session_id: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to read clipboard history")?;
+
+        rows.into_iter()
+            .map(|mut entry| {
+                entry.content = self.unseal("clipboard_history", "content", entry.content)?;
+                Ok(entry)
+            })
+            .collect()
+    }
+
+    pub fn clear_clipboard_history(&self) -> Result<usize> {
+        let conn = self.get_conn()?;
+        Ok(conn.execute("DELETE FROM clipboard_history", [])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::crypto::{ColumnCipher, DataKey};
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_history_trim_and_encryption() {
+        let db = TestDatabase::new().await;
+
+        db.add_clipboard_entry("plain before encryption", None, None, 2)
+            .unwrap();
+        db.set_cipher(Some(ColumnCipher::new(DataKey::generate(), vec![])));
+        db.add_clipboard_entry("token-1", Some("text/plain"), None, 2)
+            .unwrap();
+        db.add_clipboard_entry("token-2", Some("text/plain"), None, 2)
+            .unwrap();
+
+        let entries = db.list_clipboard_history(10).unwrap();
+        let contents: Vec<&str> = entries.iter().map(|e| e.content.as_str()).collect();
+        assert_eq!(contents, ["token-2", "token-1"]);
+
+        let raw: Vec<String> = db
+            .get_conn()
+            .unwrap()
+            .prepare("SELECT content FROM clipboard_history")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(raw.iter().all(|c| !c.contains("token")));
+    }
+}
@@ -0,0 +1,261 @@
+// Column-level encryption for sensitive values (ChaCha20-Poly1305, RFC 8439)
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// Prefix marking a stored value as encrypted: `enc:v1:<key id>:<base64>`
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// A 256-bit data key, wiped from memory when dropped
+#[derive(Clone)]
+pub struct DataKey {
+    id: String,
+    bytes: Zeroizing<[u8; KEY_LEN]>,
+}
+
+impl DataKey {
+    /// Generate a fresh random key
+    pub fn generate() -> Self {
+        let mut bytes = Zeroizing::new([0u8; KEY_LEN]);
+        rand::rngs::OsRng.fill_bytes(bytes.as_mut());
+        Self::from_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: Zeroizing<[u8; KEY_LEN]>) -> Self {
+        // Short fingerprint stored with each value so rotated keys can be told apart
+        let id = hex::encode(&Sha1::digest(bytes.as_ref())[..4]);
+        Self { id, bytes }
+    }
+
+    /// Parse a hex-encoded key as stored in a keyfile or keyring
+    pub fn from_hex(text: &str) -> Result<Self> {
+        let mut bytes = Zeroizing::new([0u8; KEY_LEN]);
+        hex::decode_to_slice(text.trim(), bytes.as_mut()).map_err(|e| match e {
+            hex::FromHexError::InvalidStringLength => {
+                anyhow::anyhow!("Database key must be {} bytes", KEY_LEN)
+            }
+            _ => anyhow::anyhow!("Database key is not valid hex"),
+        })?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    pub fn to_hex(&self) -> Zeroizing<String> {
+        Zeroizing::new(hex::encode(self.bytes.as_ref()))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataKey").field("id", &self.id).finish()
+    }
+}
+
+/// Encrypts values with the current key and decrypts with any known key.
+/// The column name is authenticated with each value, so ciphertext cannot be
+/// moved between columns unnoticed.
+#[derive(Debug, Clone)]
+pub struct ColumnCipher {
+    current: DataKey,
+    previous: Vec<DataKey>,
+}
+
+impl ColumnCipher {
+    pub fn new(current: DataKey, previous: Vec<DataKey>) -> Self {
+        Self { current, previous }
+    }
+
+    pub fn current_key(&self) -> &DataKey {
+        &self.current
+    }
+
+    pub fn previous_keys(&self) -> &[DataKey] {
+        &self.previous
+    }
+
+    /// Encrypt a value for storage in `column`
+    pub fn encrypt(&self, column: &str, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let mut payload = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+        payload.extend_from_slice(&nonce);
+        payload.extend_from_slice(&seal(
+            &self.current.bytes,
+            &nonce,
+            column.as_bytes(),
+            plaintext.as_bytes(),
+        ));
+
+        format!(
+            "{}{}:{}",
+            ENCRYPTED_PREFIX,
+            self.current.id,
+            BASE64.encode(payload)
+        )
+    }
+
+    /// Decrypt a value read from `column`. Values without the encryption
+    /// prefix are refused; telling legacy plaintext apart is up to the caller.
+    pub fn decrypt(&self, column: &str, stored: &str) -> Result<String> {
+        let rest = stored
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .with_context(|| format!("{} holds a value that is not encrypted", column))?;
+        let (key_id, encoded) = rest
+            .split_once(':')
+            .context("Encrypted value is malformed")?;
+
+        let key = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == key_id)
+            .with_context(|| format!("Value was encrypted with unknown key {}", key_id))?;
+
+        let payload = BASE64
+            .decode(encoded)
+            .context("Encrypted value is malformed")?;
+        if payload.len() < NONCE_LEN + TAG_LEN {
+            anyhow::bail!("Encrypted value is truncated");
+        }
+        let (nonce, sealed) = payload.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("split at nonce length");
+
+        let plaintext = open(&key.bytes, &nonce, column.as_bytes(), sealed)
+            .context("Encrypted value failed authentication")?;
+        String::from_utf8(plaintext).context("Decrypted value is not valid UTF-8")
+    }
+
+    /// True when `stored` is already encrypted with the current key
+    pub fn is_current(&self, stored: &str) -> bool {
+        key_id(stored) == Some(self.current.id.as_str())
+    }
+}
+
+/// True when a stored value carries the encryption prefix
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Id of the key an encrypted value was sealed with
+pub fn key_id(stored: &str) -> Option<&str> {
+    stored
+        .strip_prefix(ENCRYPTED_PREFIX)?
+        .split_once(':')
+        .map(|(key_id, _)| key_id)
+}
+
+/// AEAD encryption; returns ciphertext followed by the tag
+fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(
+            nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("ChaCha20-Poly1305 accepts any message that fits in memory")
+}
+
+/// AEAD decryption of ciphertext followed by the tag
+fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Option<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(nonce.into(), Payload { msg: sealed, aad })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc8439_vectors() {
+        // Section 2.8.2; stored values are the nonce followed by this layout
+        let key: [u8; 32] = (0x80..=0x9f).collect::<Vec<u8>>().try_into().unwrap();
+        let nonce: [u8; 12] = hex::decode("070000004041424344454647")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let aad = hex::decode("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+only one tip for the future, sunscreen would be it.";
+
+        let sealed = seal(&key, &nonce, &aad, plaintext);
+        let (ciphertext, tag) = sealed.split_at(plaintext.len());
+        assert_eq!(
+            hex::encode(ciphertext),
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+             3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+             92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+             3ff4def08e4b7a9de576d26586cec64b6116"
+        );
+        assert_eq!(hex::encode(tag), "1ae10b594f09e26a7e902ecbd0600691");
+        assert_eq!(open(&key, &nonce, &aad, &sealed).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_column_round_trip_and_tampering() {
+        let cipher = ColumnCipher::new(DataKey::generate(), vec![]);
+
+        let stored = cipher.encrypt("clipboard_history.content", "hunter2");
+        assert!(is_encrypted(&stored));
+        assert!(!stored.contains("hunter2"));
+        assert!(cipher.is_current(&stored));
+        assert_eq!(
+            cipher
+                .decrypt("clipboard_history.content", &stored)
+                .unwrap(),
+            "hunter2"
+        );
+
+        // Bound to its column
+        assert!(cipher.decrypt("workflows.steps", &stored).is_err());
+
+        // Flipping a ciphertext bit fails authentication
+        let (prefix, encoded) = stored.rsplit_once(':').unwrap();
+        let mut payload = BASE64.decode(encoded).unwrap();
+        payload[NONCE_LEN] ^= 1;
+        let tampered = format!("{}:{}", prefix, BASE64.encode(payload));
+        assert!(cipher
+            .decrypt("clipboard_history.content", &tampered)
+            .is_err());
+
+        // Unprefixed values are not taken as plaintext
+        assert!(cipher
+            .decrypt("clipboard_history.content", "plain")
+            .is_err());
+    }
+
+    #[test]
+    fn test_previous_keys_decrypt() {
+        let old = DataKey::generate();
+        let stored = ColumnCipher::new(old.clone(), vec![]).encrypt("workflows.steps", "[]");
+
+        let rotated = ColumnCipher::new(DataKey::generate(), vec![old]);
+        assert!(!rotated.is_current(&stored));
+        assert_eq!(rotated.decrypt("workflows.steps", &stored).unwrap(), "[]");
+
+        let unrelated = ColumnCipher::new(DataKey::generate(), vec![]);
+        assert!(unrelated.decrypt("workflows.steps", &stored).is_err());
+
+        let key = DataKey::from_hex(&unrelated.current_key().to_hex()).unwrap();
+        assert_eq!(key.id(), unrelated.current_key().id());
+    }
+}
@@ -0,0 +1,380 @@
+// Persistent storage for the AT-SPI element cache
+use super::Database;
+use crate::atspi::cache::{Invalidation, InvalidationScope};
+use crate::atspi::element::Element;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+
+/// Timestamp format used for cache columns; sorts and compares as text
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// SQL expression for the current time in `TIMESTAMP_FORMAT`
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+/// A cached element row as stored for one desktop
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct StoredElement {
+    pub element: Element,
+    pub expires_at: DateTime<Utc>,
+    pub hit_count: u64,
+    pub miss_count: u64,
+    /// Object paths of the element's ancestors, root first; `None` when unknown
+    pub ancestors: Option<Vec<String>>,
+}
+
+impl StoredElement {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+impl Database {
+    /// Insert or refresh a cached element, keeping its hit/miss statistics
+    pub fn store_cached_element(
+        &self,
+        desktop_id: &str,
+        key: &str,
+        element: &Element,
+        ancestors: Option<&[String]>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let conn = self.get_conn()?;
+        let properties = serde_json::to_string(element)?;
+        let ancestors = ancestors.map(serde_json::to_string).transpose()?;
+
+        conn.execute(
+            &format!(
+                "INSERT INTO element_cache
+                    (desktop_id, cache_key, role, name, description, bus_name, path,
+                     properties, ancestors, cached_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, {NOW}, ?10)
+                 ON CONFLICT (desktop_id, cache_key) DO UPDATE SET
+                    role = excluded.role,
+                    name = excluded.name,
+                    description = excluded.description,
+                    bus_name = excluded.bus_name,
+                    path = excluded.path,
+                    properties = excluded.properties,
+                    ancestors = excluded.ancestors,
+                    cached_at = excluded.cached_at,
+                    expires_at = excluded.expires_at"
+            ),
+            params![
+                desktop_id,
+                key,
+                element.role,
+                element.name,
+                element.description,
+                element.bus_name,
+                element.path,
+                properties,
+                ancestors,
+                expires_at.format(TIMESTAMP_FORMAT).to_string(),
+            ],
+        )
+        .context("Failed to store cached element")?;
+
+        Ok(())
+    }
+
+    /// Load a cached element, including expired entries
+    pub fn load_cached_element(
+        &self,
+        desktop_id: &str,
+        key: &str,
+    ) -> Result<Option<StoredElement>> {
+        let conn = self.get_conn()?;
+
+        let row = conn
+            .query_row(
+                "SELECT properties, expires_at, hit_count, miss_count, ancestors
+                 FROM element_cache WHERE desktop_id = ?1 AND cache_key = ?2",
+                params![desktop_id, key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
+                },
+            )
+            .optional()
+            .context("Failed to load cached element")?;
+
+        let Some((properties, expires_at, hit_count, miss_count, ancestors)) = row else {
+            return Ok(None);
+        };
+
+        let element: Element =
+            serde_json::from_str(&properties).context("Cached element has invalid properties")?;
+        let expires_at = NaiveDateTime::parse_from_str(&expires_at, TIMESTAMP_FORMAT)
+            .context("Cached element has invalid expiry")?
+            .and_utc();
+        let ancestors = ancestors
+            .map(|ancestors| serde_json::from_str(&ancestors))
+            .transpose()
+            .context("Cached element has invalid ancestors")?;
+
+        Ok(Some(StoredElement {
+            element,
+            expires_at,
+            hit_count: hit_count as u64,
+            miss_count: miss_count as u64,
+            ancestors,
+        }))
+    }
+
+    /// Count a successful lookup of a cached element
+    pub fn record_element_cache_hit(&self, desktop_id: &str, key: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            &format!(
+                "UPDATE element_cache SET hit_count = hit_count + 1, last_hit_at = {NOW}
+                 WHERE desktop_id = ?1 AND cache_key = ?2"
+            ),
+            params![desktop_id, key],
+        )?;
+        Ok(())
+    }
+
+    /// Count a lookup that found the entry expired or stale
+    pub fn record_element_cache_miss(&self, desktop_id: &str, key: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE element_cache SET miss_count = miss_count + 1
+             WHERE desktop_id = ?1 AND cache_key = ?2",
+            params![desktop_id, key],
+        )?;
+        Ok(())
+    }
+
+    /// Remove a single cached element
+    pub fn delete_cached_element(&self, desktop_id: &str, key: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "DELETE FROM element_cache WHERE desktop_id = ?1 AND cache_key = ?2",
+            params![desktop_id, key],
+        )?;
+        Ok(())
+    }
+
+    /// Remove the cached elements an accessibility event made stale, by the
+    /// same rule as `Invalidation::affects`
+    pub fn invalidate_cached_elements(
+        &self,
+        desktop_id: &str,
+        invalidation: &Invalidation,
+    ) -> Result<usize> {
+        const BELOW: &str = "(ancestors IS NULL OR EXISTS
+            (SELECT 1 FROM json_each(element_cache.ancestors) WHERE value = ?3))";
+        let affected = match invalidation.scope {
+            InvalidationScope::Object => "path = ?3".to_string(),
+            InvalidationScope::Lookups => return self.clear_cached_elements(desktop_id),
+            InvalidationScope::Subtree => format!("(path = ?3 OR {BELOW})"),
+        };
+
+        let conn = self.get_conn()?;
+        let removed = conn
+            .execute(
+                &format!(
+                    "DELETE FROM element_cache
+                     WHERE desktop_id = ?1 AND bus_name = ?2 AND {affected}"
+                ),
+                params![desktop_id, invalidation.bus_name, invalidation.path],
+            )
+            .context("Failed to invalidate cached elements")?;
+        Ok(removed)
+    }
+
+    /// Remove expired cached elements for a desktop
+    pub fn purge_expired_elements(&self, desktop_id: &str) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let removed = conn.execute(
+            &format!("DELETE FROM element_cache WHERE desktop_id = ?1 AND expires_at <= {NOW}"),
+            params![desktop_id],
+        )?;
+        Ok(removed)
+    }
+
+    /// Remove elements cached for any other desktop. A desktop's
+    /// accessibility bus is gone once it ends, and with it everything its
+    /// bus names and object paths referred to.
+    pub fn purge_other_desktops(&self, desktop_id: &str) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let removed = conn.execute(
+            "DELETE FROM element_cache WHERE desktop_id != ?1",
+            params![desktop_id],
+        )?;
+        Ok(removed)
+    }
+
+    /// Remove every cached element for a desktop
+    pub fn clear_cached_elements(&self, desktop_id: &str) -> Result<usize> {
+        let conn = self.get_conn()?;
+        let removed = conn.execute(
+            "DELETE FROM element_cache WHERE desktop_id = ?1",
+            params![desktop_id],
+        )?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+    use chrono::Duration;
+
+    fn element(name: &str) -> Element {
+        Element {
+            name: name.to_string(),
+            role: "PushButton".to_string(),
+            description: String::new(),
+            states: vec![],
+            bounds: None,
+            bus_name: ":1.42".to_string(),
+            app_name: "test-app".to_string(),
+            path: "/org/a11y/atspi/accessible/1234".to_string(),
+            handle: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_and_load_per_desktop() {
+        let db = TestDatabase::new().await;
+        let expires = Utc::now() + Duration::seconds(60);
+
+        db.store_cached_element("desk-1", "ok", &element("OK"), None, expires)
+            .unwrap();
+        db.store_cached_element("desk-2", "ok", &element("Okay"), None, expires)
+            .unwrap();
+
+        let one = db.load_cached_element("desk-1", "ok").unwrap().unwrap();
+        let two = db.load_cached_element("desk-2", "ok").unwrap().unwrap();
+        assert_eq!(one.element.name, "OK");
+        assert_eq!(two.element.name, "Okay");
+        assert!(!one.is_expired());
+        assert!(db.load_cached_element("desk-3", "ok").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_statistics_survive_refresh() {
+        let db = TestDatabase::new().await;
+        let expires = Utc::now() + Duration::seconds(60);
+
+        db.store_cached_element("desk-1", "ok", &element("OK"), None, expires)
+            .unwrap();
+        db.record_element_cache_hit("desk-1", "ok").unwrap();
+        db.record_element_cache_hit("desk-1", "ok").unwrap();
+        db.record_element_cache_miss("desk-1", "ok").unwrap();
+        db.store_cached_element("desk-1", "ok", &element("OK"), None, expires)
+            .unwrap();
+
+        let stored = db.load_cached_element("desk-1", "ok").unwrap().unwrap();
+        assert_eq!(stored.hit_count, 2);
+        assert_eq!(stored.miss_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired() {
+        let db = TestDatabase::new().await;
+
+        db.store_cached_element(
+            "desk-1",
+            "old",
+            &element("Old"),
+            None,
+            Utc::now() - Duration::seconds(1),
+        )
+        .unwrap();
+        db.store_cached_element(
+            "desk-1",
+            "new",
+            &element("New"),
+            None,
+            Utc::now() + Duration::seconds(60),
+        )
+        .unwrap();
+
+        db.store_cached_element(
+            "desk-2",
+            "new",
+            &element("New"),
+            None,
+            Utc::now() + Duration::seconds(60),
+        )
+        .unwrap();
+
+        assert_eq!(db.purge_expired_elements("desk-1").unwrap(), 1);
+        assert!(db.load_cached_element("desk-1", "new").unwrap().is_some());
+        assert_eq!(db.purge_other_desktops("desk-1").unwrap(), 1);
+        assert!(db.load_cached_element("desk-2", "new").unwrap().is_none());
+        assert_eq!(db.clear_cached_elements("desk-1").unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_by_ancestry() {
+        let db = TestDatabase::new().await;
+        let expires = Utc::now() + Duration::seconds(60);
+        let dialog = "/org/a11y/atspi/accessible/7";
+        let at = |path: &str| Element {
+            path: path.to_string(),
+            ..element("OK")
+        };
+
+        let in_dialog = vec![
+            "/org/a11y/atspi/accessible/root".to_string(),
+            dialog.to_string(),
+        ];
+        db.store_cached_element("desk-1", "dialog", &at(dialog), None, expires)
+            .unwrap();
+        db.store_cached_element("desk-1", "ok", &at("/ok"), Some(&in_dialog), expires)
+            .unwrap();
+        db.store_cached_element("desk-1", "other", &at("/other"), Some(&[]), expires)
+            .unwrap();
+        db.store_cached_element("desk-1", "unknown", &at("/unknown"), None, expires)
+            .unwrap();
+        assert_eq!(
+            db.load_cached_element("desk-1", "ok")
+                .unwrap()
+                .unwrap()
+                .ancestors,
+            Some(in_dialog)
+        );
+
+        let invalidation = |bus_name: &str, scope| Invalidation {
+            bus_name: bus_name.to_string(),
+            path: dialog.to_string(),
+            scope,
+        };
+        let removed = db
+            .invalidate_cached_elements("desk-1", &invalidation(":1.42", InvalidationScope::Object))
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert!(db.load_cached_element("desk-1", "ok").unwrap().is_some());
+
+        // Below the dialog: its button, and the entries of unknown ancestry
+        let removed = db
+            .invalidate_cached_elements(
+                "desk-1",
+                &invalidation(":1.42", InvalidationScope::Subtree),
+            )
+            .unwrap();
+        assert_eq!(removed, 2);
+        assert!(db.load_cached_element("desk-1", "other").unwrap().is_some());
+
+        // Any application's new objects invalidate every lookup of the desktop
+        db.store_cached_element("desk-2", "other", &at("/other"), Some(&[]), expires)
+            .unwrap();
+        let removed = db
+            .invalidate_cached_elements("desk-1", &invalidation(":1.7", InvalidationScope::Lookups))
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert!(db.load_cached_element("desk-1", "other").unwrap().is_none());
+        assert!(db.load_cached_element("desk-2", "other").unwrap().is_some());
+    }
+}
@@ -0,0 +1,279 @@
+// Transparent encryption of sensitive columns
+use super::crypto::{self, ColumnCipher};
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::warn;
+
+/// A column whose values are encrypted when a database key is configured
+#[derive(Debug, Clone, Copy)]
+pub struct SensitiveColumn {
+    pub table: &'static str,
+    pub column: &'static str,
+}
+
+impl SensitiveColumn {
+    /// Label authenticated with each value, binding it to this column
+    fn label(&self) -> String {
+        format!("{}.{}", self.table, self.column)
+    }
+}
+
+pub const SENSITIVE_COLUMNS: &[SensitiveColumn] = &[
+    SensitiveColumn {
+        table: "clipboard_history",
+        column: "content",
+    },
+    SensitiveColumn {
+        table: "workflows",
+        column: "steps",
+    },
+    SensitiveColumn {
+        table: "tree_snapshots",
+        column: "tree",
+    },
+];
+
+/// Outcome of re-encrypting the sensitive columns
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResealReport {
+    /// Values that were plaintext or under an older key
+    pub resealed: usize,
+    /// Of those, values that were plaintext
+    pub plaintext: usize,
+    /// Values no known key could decrypt
+    pub unreadable: usize,
+}
+
+/// Whether `table.column` is encrypted at rest
+pub fn is_sensitive(table: &str, column: &str) -> bool {
+    SENSITIVE_COLUMNS
+        .iter()
+        .any(|c| c.table == table && c.column == column)
+}
+
+impl Database {
+    /// Install or remove the cipher used for sensitive columns. Without one,
+    /// sensitive values are written as plaintext again.
+    pub fn set_cipher(&self, cipher: Option<ColumnCipher>) {
+        if cipher.is_none() {
+            self.mark_unsealed();
+        }
+        *self.cipher.write().unwrap_or_else(|e| e.into_inner()) = cipher.map(Arc::new);
+    }
+
+    /// Note that sensitive columns may hold plaintext until the next reseal
+    pub(super) fn mark_unsealed(&self) {
+        self.sealed.store(false, Ordering::SeqCst);
+    }
+
+    pub fn cipher(&self) -> Option<Arc<ColumnCipher>> {
+        self.cipher
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Prepare a value of `table.column` for storage; plaintext when
+    /// encryption is disabled
+    pub fn seal(&self, table: &str, column: &str, value: &str) -> String {
+        match self.cipher() {
+            Some(cipher) if is_sensitive(table, column) => {
+                cipher.encrypt(&format!("{}.{}", table, column), value)
+            }
+            _ => value.to_string(),
+        }
+    }
+
+    /// Read back a stored value of `table.column`. Plaintext is accepted
+    /// only until the sensitive columns have been resealed: after that it
+    /// did not come from this daemon.
+    pub fn unseal(&self, table: &str, column: &str, stored: String) -> Result<String> {
+        if !crypto::is_encrypted(&stored) {
+            if is_sensitive(table, column) && self.sealed.load(Ordering::SeqCst) {
+                anyhow::bail!(
+                    "{}.{} holds a value that is not encrypted; refusing to read it",
+                    table,
+                    column
+                );
+            }
+            return Ok(stored);
+        }
+        let cipher = self.cipher().with_context(|| {
+            format!(
+                "{}.{} is encrypted but no database key is configured",
+                table, column
+            )
+        })?;
+        cipher.decrypt(&format!("{}.{}", table, column), &stored)
+    }
+
+    /// Encrypt every sensitive value that is plaintext or under an older key
+    /// with the current key, in one transaction. With `require_all`, nothing is
+    /// committed if any value cannot be decrypted.
+    pub fn reseal_sensitive_columns(&self, require_all: bool) -> Result<ResealReport> {
+        let Some(cipher) = self.cipher() else {
+            return Ok(ResealReport::default());
+        };
+
+        let mut conn = self.get_conn()?;
+        // Zero the cells the replaced values leave behind
+        conn.execute_batch("PRAGMA secure_delete = ON;")?;
+        let tx = conn.transaction()?;
+        let mut report = ResealReport::default();
+
+        for sensitive in SENSITIVE_COLUMNS {
+            let label = sensitive.label();
+            let rows: Vec<(i64, String)> = {
+                let mut stmt = tx.prepare(&format!(
+                    "SELECT rowid, {} FROM {} WHERE {} IS NOT NULL",
+                    sensitive.column, sensitive.table, sensitive.column
+                ))?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect::<Result<_, _>>()?
+            };
+
+            for (rowid, stored) in rows {
+                if cipher.is_current(&stored) {
+                    continue;
+                }
+                let plaintext = match crypto::is_encrypted(&stored) {
+                    false => {
+                        report.plaintext += 1;
+                        stored
+                    }
+                    true => match cipher.decrypt(&label, &stored) {
+                        Ok(plaintext) => plaintext,
+                        Err(_) => {
+                            report.unreadable += 1;
+                            continue;
+                        }
+                    },
+                };
+                tx.execute(
+                    &format!(
+                        "UPDATE {} SET {} = ?1 WHERE rowid = ?2",
+                        sensitive.table, sensitive.column
+                    ),
+                    params![cipher.encrypt(&label, &plaintext), rowid],
+                )?;
+                report.resealed += 1;
+            }
+        }
+
+        if require_all && report.unreadable > 0 {
+            anyhow::bail!(
+                "{} encrypted values could not be decrypted with any known key",
+                report.unreadable
+            );
+        }
+
+        tx.commit()
+            .context("Failed to commit re-encrypted values")?;
+        self.sealed.store(true, Ordering::SeqCst);
+
+        if report.plaintext > 0 {
+            if let Err(e) = scrub_plaintext(&conn) {
+                warn!(
+                    "Values were encrypted but old plaintext may remain on disk: {:#}",
+                    e
+                );
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Remove the copies of replaced plaintext that outlive the update: the
+/// clipboard search index, free pages and the WAL
+fn scrub_plaintext(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute_batch(
+        "INSERT INTO clipboard_history_fts (clipboard_history_fts) VALUES ('rebuild');
+         VACUUM;",
+    )
+    .context("Failed to rebuild the database without plaintext")?;
+
+    let busy: bool = conn
+        .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))
+        .context("Failed to checkpoint the WAL")?;
+    if busy {
+        anyhow::bail!("the WAL is still in use and could not be truncated");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::crypto::DataKey;
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_reseal_encrypts_plaintext_and_old_keys() {
+        let db = TestDatabase::new().await;
+
+        db.get_conn()
+            .unwrap()
+            .execute(
+                "INSERT INTO clipboard_history (content) VALUES ('secret-token')",
+                [],
+            )
+            .unwrap();
+
+        let first = DataKey::generate();
+        db.set_cipher(Some(ColumnCipher::new(first.clone(), vec![])));
+        let report = db.reseal_sensitive_columns(false).unwrap();
+        assert_eq!(report.resealed, 1);
+
+        let stored = || -> String {
+            db.get_conn()
+                .unwrap()
+                .query_row("SELECT content FROM clipboard_history", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        assert!(!stored().contains("secret-token"));
+
+        // No copy is left in the search index, free pages or the WAL
+        let query =
+            crate::db::search::SearchQuery::new("secret", vec![], None, None, None).unwrap();
+        assert!(db.search(&query).unwrap().is_empty());
+        for file in ["state.db", "state.db-wal"] {
+            let bytes = std::fs::read(db.dir().join(file)).unwrap_or_default();
+            assert!(
+                !bytes.windows(12).any(|window| window == b"secret-token"),
+                "plaintext left in {}",
+                file
+            );
+        }
+
+        // Rotating re-encrypts with the new key only
+        db.set_cipher(Some(ColumnCipher::new(DataKey::generate(), vec![first])));
+        assert_eq!(db.reseal_sensitive_columns(true).unwrap().resealed, 1);
+        assert_eq!(db.reseal_sensitive_columns(true).unwrap().resealed, 0);
+        assert_eq!(
+            db.unseal("clipboard_history", "content", stored()).unwrap(),
+            "secret-token"
+        );
+
+        // Values under a forgotten key block a strict reseal
+        db.set_cipher(Some(ColumnCipher::new(DataKey::generate(), vec![])));
+        assert!(db.reseal_sensitive_columns(true).is_err());
+        assert_eq!(db.reseal_sensitive_columns(false).unwrap().unreadable, 1);
+
+        // Once everything is encrypted, plaintext is not accepted as stored data
+        db.get_conn()
+            .unwrap()
+            .execute("UPDATE clipboard_history SET content = 'injected'", [])
+            .unwrap();
+        assert!(db.unseal("clipboard_history", "content", stored()).is_err());
+        assert_eq!(db.reseal_sensitive_columns(false).unwrap().resealed, 1);
+
+        db.set_cipher(None);
+        assert!(db.unseal("clipboard_history", "content", stored()).is_err());
+    }
+}
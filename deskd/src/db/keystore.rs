@@ -0,0 +1,657 @@
+// Storage for the database encryption key (keyfile or desktop keyring)
+use super::backup::BackupManager;
+use super::crypto::{ColumnCipher, DataKey};
+use super::Database;
+use crate::config::{EncryptionConfig, KeySource};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+/// Which key is being stored. A rotation writes the new key to `Pending`
+/// first so data re-encrypted with it stays readable if the daemon stops
+/// before the key is promoted to `Current`. Keys rotated out are kept in
+/// `Retired` for as long as a rotated backup still needs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySlot {
+    Current,
+    Pending,
+    Retired,
+}
+
+impl KeySlot {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::Pending => "pending",
+            Self::Retired => "retired",
+        }
+    }
+}
+
+/// Where the database key lives
+#[derive(Debug, Clone)]
+pub enum KeyStore {
+    /// Hex key in a file only the owner can read (mode 0600)
+    Keyfile(PathBuf),
+    /// Secret Service keyring (GNOME Keyring, KWallet, KeePassXC)
+    Keyring,
+}
+
+/// Outcome of `deskctl db rekey`
+#[derive(Debug, Clone, Serialize)]
+pub struct RekeyReport {
+    pub previous_key_id: String,
+    pub key_id: String,
+    pub values_reencrypted: usize,
+    /// Retired keys dropped because no rotated backup uses them any more
+    pub keys_pruned: usize,
+}
+
+impl KeyStore {
+    pub fn from_config(config: &EncryptionConfig) -> Self {
+        match config.key_source {
+            KeySource::Keyfile => Self::Keyfile(PathBuf::from(&config.keyfile)),
+            KeySource::Keyring => Self::Keyring,
+        }
+    }
+
+    /// Human-readable location for log messages
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Keyfile(path) => format!("keyfile {}", path.display()),
+            Self::Keyring => "desktop keyring".to_string(),
+        }
+    }
+
+    pub async fn load(&self, slot: KeySlot) -> Result<Option<DataKey>> {
+        Ok(self.load_keys(slot).await?.into_iter().next())
+    }
+
+    pub async fn save(&self, slot: KeySlot, key: &DataKey) -> Result<()> {
+        self.save_keys(slot, std::slice::from_ref(key)).await
+    }
+
+    /// All keys in a slot; only `Retired` holds more than one
+    pub async fn load_keys(&self, slot: KeySlot) -> Result<Vec<DataKey>> {
+        let text = match self {
+            Self::Keyfile(path) => read_keyfile(&keyfile_path(path, slot))?,
+            Self::Keyring => secret_service::load(slot).await?,
+        };
+        let Some(text) = text else {
+            return Ok(Vec::new());
+        };
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(DataKey::from_hex)
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid {} key in {}", slot.name(), self.describe()))
+    }
+
+    /// Replace the keys in a slot, one hex key per line
+    pub async fn save_keys(&self, slot: KeySlot, keys: &[DataKey]) -> Result<()> {
+        let mut text = Zeroizing::new(String::new());
+        for key in keys {
+            text.push_str(&key.to_hex());
+            text.push('\n');
+        }
+        match self {
+            Self::Keyfile(path) => save_keyfile(&keyfile_path(path, slot), &text),
+            Self::Keyring => secret_service::save(slot, &text).await,
+        }
+    }
+
+    pub async fn delete(&self, slot: KeySlot) -> Result<()> {
+        match self {
+            Self::Keyfile(path) => {
+                let path = keyfile_path(path, slot);
+                match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)
+                        .with_context(|| format!("Failed to remove keyfile: {}", path.display())),
+                    _ => Ok(()),
+                }
+            }
+            Self::Keyring => secret_service::delete(slot).await,
+        }
+    }
+}
+
+/// Load the cipher for the configured key, creating a key on first use.
+/// Retired keys and a leftover pending key from an interrupted rotation are
+/// kept for decryption.
+pub async fn load_cipher(store: &KeyStore) -> Result<ColumnCipher> {
+    let current = match store.load(KeySlot::Current).await? {
+        Some(key) => key,
+        None => {
+            let key = DataKey::generate();
+            store.save(KeySlot::Current, &key).await?;
+            info!("Created database encryption key in {}", store.describe());
+            key
+        }
+    };
+
+    let mut previous = Vec::new();
+    if let Some(pending) = store.load(KeySlot::Pending).await? {
+        if pending.id() != current.id() {
+            warn!(
+                "Found pending database key {} from an interrupted rotation",
+                pending.id()
+            );
+            previous.push(pending);
+        }
+    }
+    previous.extend(store.load_keys(KeySlot::Retired).await?);
+    let previous = retain_unique(previous, &current);
+
+    Ok(ColumnCipher::new(current, previous))
+}
+
+/// Rotate the database key: every sensitive value is re-encrypted with a new
+/// key in one transaction, then the new key replaces the old one in the store.
+/// The old key is retired rather than deleted while backups still need it.
+pub async fn rekey(
+    database: &Arc<Database>,
+    store: &KeyStore,
+    backups: &Arc<BackupManager>,
+) -> Result<RekeyReport> {
+    let old = database
+        .cipher()
+        .context("Database encryption is not enabled")?;
+
+    let new_key = DataKey::generate();
+    store
+        .save(KeySlot::Pending, &new_key)
+        .await
+        .context("Failed to store the new key")?;
+
+    let mut known = vec![old.current_key().clone()];
+    known.extend(old.previous_keys().iter().cloned());
+    let retired = retain_unique(known, &new_key);
+    if let Err(e) = store.save_keys(KeySlot::Retired, &retired).await {
+        if let Err(cleanup) = store.delete(KeySlot::Pending).await {
+            warn!("Failed to remove pending key: {:#}", cleanup);
+        }
+        return Err(e.context("Key rotation aborted; the old key could not be retired"));
+    }
+    database.set_cipher(Some(ColumnCipher::new(new_key.clone(), retired.clone())));
+
+    let db = Arc::clone(database);
+    let resealed = tokio::task::spawn_blocking(move || db.reseal_sensitive_columns(true))
+        .await
+        .context("Re-encryption task failed")
+        .and_then(|result| result);
+
+    let report = match resealed {
+        Ok(report) => report,
+        Err(e) => {
+            roll_back(database, store, &old, &retired, new_key).await;
+            return Err(e.context("Key rotation aborted; the old key is still in use"));
+        }
+    };
+
+    // The data now needs the new key; until it is saved as current the
+    // pending copy is what keeps it readable across restarts.
+    store.save(KeySlot::Current, &new_key).await.context(
+        "Data was re-encrypted but the new key could not be saved as current; \
+         it stays in the pending slot and will be picked up on restart",
+    )?;
+    if let Err(e) = store.delete(KeySlot::Pending).await {
+        warn!("Failed to remove pending key: {:#}", e);
+    }
+    database.set_cipher(Some(ColumnCipher::new(new_key.clone(), retired)));
+
+    info!(
+        "Rotated database key {} -> {} ({} values re-encrypted)",
+        old.current_key().id(),
+        new_key.id(),
+        report.resealed
+    );
+
+    // Every live value now uses the new key, so retired keys are only
+    // needed for backups
+    let keys_pruned = prune_retired(database, store, backups)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to prune retired keys: {:#}", e);
+            0
+        });
+
+    Ok(RekeyReport {
+        previous_key_id: old.current_key().id().to_string(),
+        key_id: new_key.id().to_string(),
+        values_reencrypted: report.resealed,
+        keys_pruned,
+    })
+}
+
+/// Drop retired keys that no rotated backup was sealed with, from the store
+/// and the live cipher. Only call this once the live database has been
+/// resealed, since its own values may otherwise still need them.
+pub async fn prune_retired(
+    database: &Database,
+    store: &KeyStore,
+    backups: &Arc<BackupManager>,
+) -> Result<usize> {
+    let Some(cipher) = database.cipher() else {
+        return Ok(0);
+    };
+
+    let manager = Arc::clone(backups);
+    let in_use = tokio::task::spawn_blocking(move || manager.key_ids_in_use())
+        .await
+        .context("Backup scan task failed")?
+        .context("Failed to read the keys used by backups")?;
+
+    let (keep, unused): (Vec<DataKey>, Vec<DataKey>) = store
+        .load_keys(KeySlot::Retired)
+        .await?
+        .into_iter()
+        .partition(|key| in_use.contains(key.id()));
+    if unused.is_empty() {
+        return Ok(0);
+    }
+
+    if keep.is_empty() {
+        store.delete(KeySlot::Retired).await?;
+    } else {
+        store.save_keys(KeySlot::Retired, &keep).await?;
+    }
+    let previous = cipher
+        .previous_keys()
+        .iter()
+        .filter(|key| unused.iter().all(|dropped| dropped.id() != key.id()))
+        .cloned()
+        .collect();
+    database.set_cipher(Some(ColumnCipher::new(
+        cipher.current_key().clone(),
+        previous,
+    )));
+
+    for key in &unused {
+        info!(
+            "Dropped retired database key {}; no backup uses it",
+            key.id()
+        );
+    }
+    Ok(unused.len())
+}
+
+/// Go back to the old key after a failed reseal. Values written while the
+/// new key was installed are sealed with it, so it is retired rather than
+/// forgotten; if that fails it stays in the pending slot, which `load_cipher`
+/// also reads.
+async fn roll_back(
+    database: &Database,
+    store: &KeyStore,
+    old: &ColumnCipher,
+    retired: &[DataKey],
+    new_key: DataKey,
+) {
+    let mut previous = old.previous_keys().to_vec();
+    previous.push(new_key.clone());
+    database.set_cipher(Some(ColumnCipher::new(old.current_key().clone(), previous)));
+
+    let mut retired = retired.to_vec();
+    retired.push(new_key);
+    match store.save_keys(KeySlot::Retired, &retired).await {
+        Ok(()) => {
+            if let Err(e) = store.delete(KeySlot::Pending).await {
+                warn!("Failed to remove pending key: {:#}", e);
+            }
+        }
+        Err(e) => warn!(
+            "Failed to retire the unused new key; keeping it as pending: {:#}",
+            e
+        ),
+    }
+}
+
+/// Keys other than `current`, each once, in their original order
+fn retain_unique(keys: Vec<DataKey>, current: &DataKey) -> Vec<DataKey> {
+    let mut unique: Vec<DataKey> = Vec::new();
+    for key in keys {
+        if key.id() != current.id() && unique.iter().all(|seen| seen.id() != key.id()) {
+            unique.push(key);
+        }
+    }
+    unique
+}
+
+fn keyfile_path(path: &Path, slot: KeySlot) -> PathBuf {
+    match slot {
+        KeySlot::Current => path.to_path_buf(),
+        KeySlot::Pending | KeySlot::Retired => {
+            let mut other = path.as_os_str().to_owned();
+            other.push(".");
+            other.push(slot.name());
+            PathBuf::from(other)
+        }
+    }
+}
+
+fn read_keyfile(path: &Path) -> Result<Option<Zeroizing<String>>> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read keyfile: {}", path.display()))
+        }
+    };
+
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        anyhow::bail!(
+            "Keyfile {} has mode {:o}; it must only be accessible by its owner (chmod 600)",
+            path.display(),
+            mode
+        );
+    }
+
+    std::fs::read_to_string(path)
+        .map(|text| Some(Zeroizing::new(text)))
+        .with_context(|| format!("Failed to read keyfile: {}", path.display()))
+}
+
+fn save_keyfile(path: &Path, text: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)
+            .with_context(|| format!("Failed to create key directory: {}", parent.display()))?;
+    }
+
+    // Write next to the target and rename so a crash never leaves a partial key
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp)
+        .with_context(|| format!("Failed to create keyfile: {}", temp.display()))?;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+
+    std::fs::rename(&temp, path)
+        .with_context(|| format!("Failed to write keyfile: {}", path.display()))?;
+    Ok(())
+}
+
+/// Minimal client for the freedesktop Secret Service API
+mod secret_service {
+    use super::KeySlot;
+    use anyhow::{Context, Result};
+    use std::collections::HashMap;
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+    use zbus::{Connection, Proxy};
+    use zeroize::Zeroizing;
+
+    const SERVICE: &str = "org.freedesktop.secrets";
+    const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+    const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+    const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+    const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+
+    /// (session, parameters, value, content type)
+    type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+    struct Session {
+        connection: Connection,
+        path: OwnedObjectPath,
+    }
+
+    fn attributes(slot: KeySlot) -> HashMap<&'static str, &'static str> {
+        HashMap::from([
+            ("application", "deskd"),
+            ("deskd-key", "database"),
+            ("deskd-slot", slot.name()),
+        ])
+    }
+
+    async fn open_session() -> Result<Session> {
+        let connection = Connection::session()
+            .await
+            .context("Failed to connect to the session bus for the keyring")?;
+        let service = Proxy::new(&connection, SERVICE, SERVICE_PATH, SERVICE_INTERFACE).await?;
+        let (_, path): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .await
+            .context("Failed to open a keyring session (is a Secret Service running?)")?;
+        Ok(Session { connection, path })
+    }
+
+    async fn find_item(session: &Session, slot: KeySlot) -> Result<Option<OwnedObjectPath>> {
+        let service = Proxy::new(
+            &session.connection,
+            SERVICE,
+            SERVICE_PATH,
+            SERVICE_INTERFACE,
+        )
+        .await?;
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service
+            .call("SearchItems", &(attributes(slot),))
+            .await
+            .context("Failed to search the keyring")?;
+
+        if let Some(item) = unlocked.into_iter().next() {
+            return Ok(Some(item));
+        }
+        if locked.is_empty() {
+            return Ok(None);
+        }
+
+        let (unlocked, _prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) = service
+            .call("Unlock", &(locked,))
+            .await
+            .context("Failed to unlock the keyring")?;
+        unlocked
+            .into_iter()
+            .next()
+            .map(Some)
+            .context("The keyring is locked; unlock it and restart deskd")
+    }
+
+    pub async fn load(slot: KeySlot) -> Result<Option<Zeroizing<String>>> {
+        let session = open_session().await?;
+        let Some(item) = find_item(&session, slot).await? else {
+            return Ok(None);
+        };
+
+        let item = Proxy::new(&session.connection, SERVICE, item, ITEM_INTERFACE).await?;
+        let (_, _, value, _): Secret = item
+            .call("GetSecret", &(&session.path,))
+            .await
+            .context("Failed to read the key from the keyring")?;
+        String::from_utf8(value)
+            .map(|text| Some(Zeroizing::new(text)))
+            .context("Keyring entry is not valid text")
+    }
+
+    pub async fn save(slot: KeySlot, secret: &str) -> Result<()> {
+        let session = open_session().await?;
+        let collection = Proxy::new(
+            &session.connection,
+            SERVICE,
+            DEFAULT_COLLECTION,
+            COLLECTION_INTERFACE,
+        )
+        .await?;
+
+        let label = format!("deskd database key ({})", slot.name());
+        let properties: HashMap<&str, Value> = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(attributes(slot)),
+            ),
+        ]);
+        let secret = (
+            session.path.as_ref(),
+            Vec::<u8>::new(),
+            secret.as_bytes(),
+            "text/plain",
+        );
+
+        let (item, _prompt): (OwnedObjectPath, OwnedObjectPath) = collection
+            .call("CreateItem", &(properties, secret, true))
+            .await
+            .context("Failed to store the key in the keyring")?;
+        if item.as_str() == "/" {
+            anyhow::bail!("The keyring is locked; unlock it and retry");
+        }
+        Ok(())
+    }
+
+    pub async fn delete(slot: KeySlot) -> Result<()> {
+        let session = open_session().await?;
+        if let Some(item) = find_item(&session, slot).await? {
+            let item = Proxy::new(&session.connection, SERVICE, item, ITEM_INTERFACE).await?;
+            let _prompt: OwnedObjectPath = item
+                .call("Delete", &())
+                .await
+                .context("Failed to remove the key from the keyring")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{TempDir, TestDatabase};
+
+    #[tokio::test]
+    async fn test_keyfile_permissions_and_slots() {
+        let dir = TempDir::new();
+        let store = KeyStore::Keyfile(dir.join("keys").join("db.key"));
+
+        assert!(store.load(KeySlot::Current).await.unwrap().is_none());
+        let cipher = load_cipher(&store).await.unwrap();
+
+        let path = dir.join("keys").join("db.key");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        let reloaded = store.load(KeySlot::Current).await.unwrap().unwrap();
+        assert_eq!(reloaded.id(), cipher.current_key().id());
+
+        // A pending key left by an interrupted rotation is still usable
+        let pending = DataKey::generate();
+        store.save(KeySlot::Pending, &pending).await.unwrap();
+        let stored = ColumnCipher::new(pending, vec![]).encrypt("workflows.steps", "[]");
+        let cipher = load_cipher(&store).await.unwrap();
+        assert_eq!(cipher.decrypt("workflows.steps", &stored).unwrap(), "[]");
+
+        // World-readable keyfiles are refused
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = store.load(KeySlot::Current).await.unwrap_err();
+        assert!(err.to_string().contains("chmod 600"));
+    }
+
+    #[tokio::test]
+    async fn test_rekey_retires_keys_backups_need() {
+        let test_database = TestDatabase::new().await;
+        let database = test_database.shared();
+        let dir = test_database.dir();
+        let store = KeyStore::Keyfile(dir.join("keys").join("db.key"));
+        let backups = Arc::new(BackupManager::new(
+            dir.join("backups"),
+            5,
+            &dir.join("state.db"),
+        ));
+
+        let first = load_cipher(&store).await.unwrap();
+        database.set_cipher(Some(first.clone()));
+        database
+            .add_clipboard_entry("secret", None, None, 10)
+            .unwrap();
+        let backup = backups.create_backup(&database).unwrap();
+        let backed_up: String = rusqlite::Connection::open(&backup.path)
+            .unwrap()
+            .query_row("SELECT content FROM clipboard_history", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+
+        // The first key is kept for the backup, the second is never needed
+        let report = rekey(&database, &store, &backups).await.unwrap();
+        assert_eq!(report.keys_pruned, 0);
+        let report = rekey(&database, &store, &backups).await.unwrap();
+        assert_eq!(report.keys_pruned, 1);
+        assert_ne!(report.key_id, first.current_key().id());
+
+        let retired = dir.join("keys").join("db.key.retired");
+        let mode = std::fs::metadata(&retired).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+        let kept = store.load_keys(KeySlot::Retired).await.unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].id(), first.current_key().id());
+
+        // Both in the running daemon and after a restart
+        let live = database.cipher().unwrap();
+        assert_eq!(live.previous_keys().len(), 1);
+        let column = "clipboard_history.content";
+        assert_eq!(live.decrypt(column, &backed_up).unwrap(), "secret");
+        let reloaded = load_cipher(&store).await.unwrap();
+        assert_eq!(reloaded.current_key().id(), report.key_id);
+        assert_eq!(reloaded.decrypt(column, &backed_up).unwrap(), "secret");
+
+        // Once the backup is gone its key goes too
+        std::fs::remove_file(&backup.path).unwrap();
+        let report = rekey(&database, &store, &backups).await.unwrap();
+        assert_eq!(report.keys_pruned, 2);
+        assert!(!retired.exists());
+        assert!(database.cipher().unwrap().previous_keys().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_rekey_keeps_new_key() {
+        let test_database = TestDatabase::new().await;
+        let database = test_database.shared();
+        let dir = test_database.dir();
+        let store = KeyStore::Keyfile(dir.join("keys").join("db.key"));
+
+        let first = load_cipher(&store).await.unwrap();
+        database.set_cipher(Some(first.clone()));
+        // A value no known key can decrypt makes the reseal fail
+        let lost = ColumnCipher::new(DataKey::generate(), vec![]);
+        database
+            .get_conn()
+            .unwrap()
+            .execute(
+                "INSERT INTO clipboard_history (content) VALUES (?1)",
+                [lost.encrypt("clipboard_history.content", "gone")],
+            )
+            .unwrap();
+
+        let backups = Arc::new(BackupManager::new(
+            dir.join("backups"),
+            5,
+            &dir.join("state.db"),
+        ));
+        assert!(rekey(&database, &store, &backups).await.is_err());
+
+        // Values written while the new key was installed stay readable
+        let live = database.cipher().unwrap();
+        assert_eq!(live.current_key().id(), first.current_key().id());
+        let [new_key] = live.previous_keys() else {
+            panic!("expected the new key to be kept");
+        };
+        let written = ColumnCipher::new(new_key.clone(), vec![]).encrypt("workflows.steps", "[]");
+        assert_eq!(live.decrypt("workflows.steps", &written).unwrap(), "[]");
+
+        assert!(store.load(KeySlot::Pending).await.unwrap().is_none());
+        let reloaded = load_cipher(&store).await.unwrap();
+        assert_eq!(reloaded.decrypt("workflows.steps", &written).unwrap(), "[]");
+    }
+}
@@ -1,22 +1,53 @@
 use anyhow::{Context, Result};
+use backup::BackupManager;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use std::path::Path;
-use tracing::info;
-
+use recovery::RecoveryReport;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+pub mod async_db;
+pub mod audit;
+pub mod backup;
+pub mod clipboard;
+pub mod crypto;
+pub mod element_cache;
+pub mod encryption;
+pub mod keystore;
+pub mod preferences;
+pub mod query;
+pub mod recovery;
 pub mod schema;
+pub mod search;
+pub mod snapshots;
+pub mod stats;
 pub mod tasks;
+pub mod transfer;
 pub mod workflows;
 
 type DbPool = Pool<SqliteConnectionManager>;
 
+/// Maximum number of open connections
+pub const POOL_SIZE: u32 = 15;
+
 pub struct Database {
     pool: DbPool,
+    path: PathBuf,
+    recovery: Option<RecoveryReport>,
+    /// Cipher for sensitive columns; `None` when encryption is disabled
+    cipher: RwLock<Option<Arc<crypto::ColumnCipher>>>,
+    /// Whether every sensitive value has been encrypted; from then on a
+    /// plaintext value in a sensitive column is refused
+    sealed: AtomicBool,
 }
 
 impl Database {
-    /// Create a new database connection pool
-    pub fn new(path: &str) -> Result<Self> {
+    /// Create a new database connection pool.
+    /// An existing file is integrity-checked first; if it is corrupt it is moved
+    /// aside and replaced by the newest valid backup (or recreated empty).
+    pub fn new(path: &str, backups: Option<&BackupManager>) -> Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = Path::new(path).parent() {
             std::fs::create_dir_all(parent).with_context(|| {
@@ -24,26 +55,47 @@ impl Database {
             })?;
         }
 
-        let manager = SqliteConnectionManager::file(path);
+        let mut recovery = None;
+        if Path::new(path).exists() {
+            if let Some(problem) = recovery::quick_check(Path::new(path))? {
+                recovery = Some(recovery::recover(Path::new(path), problem, backups)?);
+            }
+        }
+
+        // Connection settings apply per connection, so every pooled
+        // connection gets them when it is opened
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            conn.execute_batch(
+                "PRAGMA foreign_keys = ON;
+                 PRAGMA synchronous = NORMAL;
+                 PRAGMA temp_store = MEMORY;
+                 PRAGMA mmap_size = 30000000000;",
+            )
+        });
         let pool = Pool::builder()
-            .max_size(15)
+            .max_size(POOL_SIZE)
             .build(manager)
             .context("Failed to create connection pool")?;
 
-        // Enable foreign keys and WAL mode for better concurrency
+        // WAL mode is stored in the database file and allows readers during writes
         let conn = pool.get().context("Failed to get connection from pool")?;
-        conn.execute_batch(
-            "PRAGMA foreign_keys = ON;
-             PRAGMA journal_mode = WAL;
-             PRAGMA synchronous = NORMAL;
-             PRAGMA temp_store = MEMORY;
-             PRAGMA mmap_size = 30000000000;",
-        )
-        .context("Failed to set database pragmas")?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")
+            .context("Failed to set database pragmas")?;
 
         info!("Database connection pool created: {}", path);
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            path: PathBuf::from(path),
+            recovery,
+            cipher: RwLock::new(None),
+            sealed: AtomicBool::new(false),
+        })
+    }
+
+    /// Details of the corruption recovery performed at startup, if any
+    pub fn recovery(&self) -> Option<&RecoveryReport> {
+        self.recovery.as_ref()
     }
 
     /// Get the current schema version
@@ -71,16 +123,26 @@ impl Database {
 
     /// Run database migrations
     pub async fn migrate(&self) -> Result<()> {
+        self.apply_migrations()
+    }
+
+    /// Apply every migration newer than the current schema version
+    fn apply_migrations(&self) -> Result<()> {
         let current_version = self.get_schema_version()?;
         info!("Current schema version: {}", current_version);
 
-        if current_version == 0 {
-            info!("Initializing database schema...");
-            self.apply_initial_schema()?;
-        }
+        for (version, migration_sql) in schema::MIGRATIONS {
+            if *version <= current_version {
+                continue;
+            }
 
-        // Future migrations would go here
-        // if current_version < 2 { self.apply_migration_2()?; }
+            info!("Applying schema migration {}...", version);
+            let mut conn = self.pool.get()?;
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration_sql)
+                .with_context(|| format!("Failed to apply schema migration {}", version))?;
+            tx.commit()?;
+        }
 
         let new_version = self.get_schema_version()?;
         info!("Schema migration complete. New version: {}", new_version);
@@ -88,22 +150,7 @@ impl Database {
         Ok(())
     }
 
-    /// Apply the initial database schema
-    fn apply_initial_schema(&self) -> Result<()> {
-        let conn = self.pool.get()?;
-
-        // Read the migration SQL from the migrations directory
-        let migration_sql = include_str!("../../../migrations/001_initial_schema.sql");
-
-        conn.execute_batch(migration_sql)
-            .context("Failed to apply initial schema")?;
-
-        info!("Initial schema applied successfully");
-        Ok(())
-    }
-
     /// Backup the database to a file
-    #[allow(dead_code)]
     pub fn backup(&self, backup_path: &str) -> Result<()> {
         let conn = self.pool.get()?;
 
@@ -112,49 +159,140 @@ impl Database {
         }
 
         let mut backup_conn = rusqlite::Connection::open(backup_path)?;
-        let backup = rusqlite::backup::Backup::new(&conn, &mut backup_conn)?;
+        {
+            let backup = rusqlite::backup::Backup::new(&conn, &mut backup_conn)?;
+            backup.run_to_completion(100, std::time::Duration::from_millis(10), None)?;
+        }
 
-        backup.run_to_completion(5, std::time::Duration::from_millis(250), None)?;
+        // Backups are standalone files, so don't leave them depending on -wal/-shm
+        backup_conn
+            .execute_batch("PRAGMA journal_mode = DELETE;")
+            .context("Failed to finalize backup file")?;
 
         info!("Database backed up to: {}", backup_path);
         Ok(())
     }
 
+    /// Replace the live database contents with those of a backup file.
+    /// Callers are expected to validate the backup first (see `backup::validate_backup`).
+    pub fn restore_from(&self, backup_path: &Path) -> Result<()> {
+        let source = rusqlite::Connection::open_with_flags(
+            backup_path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .with_context(|| format!("Failed to open backup: {}", backup_path.display()))?;
+
+        // Backups taken before encryption was enabled hold plaintext
+        self.mark_unsealed();
+
+        // Copying pages into a pooled connection swaps the contents in place, so
+        // every other connection in the pool sees the restored data immediately.
+        let mut conn = self.pool.get()?;
+        let restore = rusqlite::backup::Backup::new(&source, &mut conn)?;
+        restore
+            .run_to_completion(100, std::time::Duration::from_millis(50), None)
+            .context("Failed to copy backup into the live database")?;
+
+        drop(restore);
+        drop(conn);
+
+        // Older backups are brought up to the current schema
+        self.apply_migrations()?;
+
+        let report = self.reseal_sensitive_columns(false)?;
+        if report.unreadable > 0 {
+            warn!(
+                "{} restored values are encrypted with a key that is no longer known",
+                report.unreadable
+            );
+        }
+
+        info!("Database restored from: {}", backup_path.display());
+        Ok(())
+    }
+
     /// Get a connection from the pool
-    #[allow(dead_code)]
     pub fn get_conn(&self) -> Result<r2d2::PooledConnection<SqliteConnectionManager>> {
         self.pool.get().context("Failed to get database connection")
     }
 
-    /// Execute a raw SQL query (for CLI db commands)
-    #[allow(dead_code)]
-    pub fn execute_query(&self, sql: &str) -> Result<Vec<Vec<String>>> {
-        let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(sql)?;
-
-        let column_count = stmt.column_count();
-        let mut results = Vec::new();
-
-        // Add header row
-        let headers: Vec<String> = (0..column_count)
-            .map(|i| stmt.column_name(i).unwrap_or("").to_string())
-            .collect();
-        results.push(headers);
-
-        // Add data rows
-        let rows = stmt.query_map([], |row| {
-            let mut row_data = Vec::new();
-            for i in 0..column_count {
-                let value: Result<String, rusqlite::Error> = row.get(i);
-                row_data.push(value.unwrap_or_else(|_| "NULL".to_string()));
+    /// Run a read-only SQL query on a separate connection (for CLI db commands)
+    pub fn execute_query(
+        &self,
+        sql: &str,
+        limits: &query::QueryLimits,
+    ) -> Result<query::QueryResult> {
+        query::run_read_only(&self.path, sql, limits)
+    }
+}
+
+/// Temporary databases for tests
+#[cfg(test)]
+pub mod testing {
+    use super::Database;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    /// A temporary directory that is removed when dropped, also when the
+    /// test using it panics
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("deskd-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A migrated `state.db` in its own temporary directory
+    pub struct TestDatabase {
+        // Declared first so the database closes before its directory goes
+        database: Arc<Database>,
+        dir: TempDir,
+    }
+
+    impl TestDatabase {
+        pub async fn new() -> Self {
+            let dir = TempDir::new();
+            let database = Database::new(dir.join("state.db").to_str().unwrap(), None).unwrap();
+            database.migrate().await.unwrap();
+            Self {
+                database: Arc::new(database),
+                dir,
             }
-            Ok(row_data)
-        })?;
+        }
+
+        pub fn dir(&self) -> &Path {
+            &self.dir
+        }
 
-        for row in rows {
-            results.push(row?);
+        /// The database for code that keeps its own handle to it
+        pub fn shared(&self) -> Arc<Database> {
+            self.database.clone()
         }
+    }
+
+    impl Deref for TestDatabase {
+        type Target = Database;
 
-        Ok(results)
+        fn deref(&self) -> &Database {
+            &self.database
+        }
     }
 }
@@ -0,0 +1,48 @@
+// Storage for runtime preferences
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::params;
+
+/// A stored preference; `value` is JSON text
+#[derive(Debug, Clone)]
+pub struct StoredPreference {
+    pub key: String,
+    pub value: String,
+}
+
+impl Database {
+    pub fn list_preferences(&self) -> Result<Vec<StoredPreference>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare("SELECT key, value FROM preferences ORDER BY key")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(StoredPreference {
+                key: row.get(0)?,
+                value: row.get(1)?,
+            })
+        })?;
+
+        rows.collect::<Result<_, _>>()
+            .context("Failed to read preferences")
+    }
+
+    pub fn set_preference(&self, key: &str, value: &str, description: &str) -> Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "INSERT INTO preferences (key, value, description) VALUES (?1, ?2, ?3)
+             ON CONFLICT (key) DO UPDATE SET
+                value = excluded.value,
+                description = excluded.description,
+                updated_at = CURRENT_TIMESTAMP",
+            params![key, value, description],
+        )
+        .context("Failed to store preference")?;
+        Ok(())
+    }
+
+    /// Remove a preference; returns false if it was not set
+    pub fn delete_preference(&self, key: &str) -> Result<bool> {
+        let conn = self.get_conn()?;
+        let removed = conn.execute("DELETE FROM preferences WHERE key = ?1", [key])?;
+        Ok(removed > 0)
+    }
+}
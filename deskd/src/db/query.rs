@@ -0,0 +1,255 @@
+// Read-only SQL queries for deskctl db commands
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Default and maximum number of rows returned by a query
+pub const DEFAULT_MAX_ROWS: usize = 1000;
+pub const MAX_ROWS_LIMIT: usize = 10_000;
+
+/// Default and maximum query run time
+pub const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+pub const MAX_TIMEOUT_MS: u64 = 30_000;
+
+/// Pragmas that only report information and may be queried without a value
+const READ_ONLY_PRAGMAS: &[&str] = &[
+    "table_info",
+    "table_xinfo",
+    "table_list",
+    "index_list",
+    "index_info",
+    "index_xinfo",
+    "foreign_key_list",
+    "user_version",
+    "page_count",
+    "page_size",
+];
+
+/// Row and time limits for a query
+#[derive(Debug, Clone)]
+pub struct QueryLimits {
+    pub max_rows: usize,
+    pub timeout: Duration,
+}
+
+impl QueryLimits {
+    /// Build limits from optional client values, clamped to the hard maximums
+    pub fn new(max_rows: Option<usize>, timeout_ms: Option<u64>) -> Self {
+        Self {
+            max_rows: max_rows
+                .unwrap_or(DEFAULT_MAX_ROWS)
+                .clamp(1, MAX_ROWS_LIMIT),
+            timeout: Duration::from_millis(
+                timeout_ms
+                    .unwrap_or(DEFAULT_TIMEOUT_MS)
+                    .clamp(1, MAX_TIMEOUT_MS),
+            ),
+        }
+    }
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Typed query result; blobs are encoded as `{"base64": "..."}`
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// True when more rows were available than `max_rows`
+    pub truncated: bool,
+    pub elapsed_ms: u64,
+}
+
+/// Run a single SELECT statement against the database at `path`.
+/// The connection is opened read-only and an authorizer rejects anything that
+/// could write, attach other files or change connection state.
+pub fn run_read_only(path: &Path, sql: &str, limits: &QueryLimits) -> Result<QueryResult> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("Failed to open database read-only: {}", path.display()))?;
+
+    conn.authorizer(Some(authorize));
+
+    let started = Instant::now();
+    let deadline = started + limits.timeout;
+    conn.progress_handler(1000, Some(move || Instant::now() > deadline));
+
+    let result = collect_rows(&conn, sql, limits.max_rows);
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok((columns, rows, truncated)) => Ok(QueryResult {
+            columns,
+            rows,
+            truncated,
+            elapsed_ms,
+        }),
+        Err(e) => Err(describe_error(e, limits)),
+    }
+}
+
+type Rows = (Vec<String>, Vec<Vec<serde_json::Value>>, bool);
+
+fn collect_rows(conn: &Connection, sql: &str, max_rows: usize) -> rusqlite::Result<Rows> {
+    let mut stmt = conn.prepare(sql)?;
+
+    let columns: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect();
+    let column_count = columns.len();
+
+    let mut rows = Vec::new();
+    let mut truncated = false;
+    let mut cursor = stmt.query([])?;
+
+    while let Some(row) = cursor.next()? {
+        if rows.len() == max_rows {
+            truncated = true;
+            break;
+        }
+
+        let mut values = Vec::with_capacity(column_count);
+        for i in 0..column_count {
+            values.push(to_json(row.get_ref(i)?));
+        }
+        rows.push(values);
+    }
+
+    Ok((columns, rows, truncated))
+}
+
+fn to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => serde_json::Value::from(i),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        ValueRef::Text(bytes) => serde_json::Value::String(String::from_utf8_lossy(bytes).into()),
+        ValueRef::Blob(bytes) => serde_json::json!({ "base64": BASE64.encode(bytes) }),
+    }
+}
+
+fn authorize(ctx: AuthContext<'_>) -> Authorization {
+    match ctx.action {
+        AuthAction::Select | AuthAction::Read { .. } | AuthAction::Recursive => {
+            Authorization::Allow
+        }
+        AuthAction::Function { function_name } if function_name != "load_extension" => {
+            Authorization::Allow
+        }
+        AuthAction::Pragma {
+            pragma_name,
+            pragma_value: None,
+        } if READ_ONLY_PRAGMAS.contains(&pragma_name.to_ascii_lowercase().as_str()) => {
+            Authorization::Allow
+        }
+        _ => Authorization::Deny,
+    }
+}
+
+fn describe_error(e: rusqlite::Error, limits: &QueryLimits) -> anyhow::Error {
+    match e.sqlite_error_code() {
+        Some(ErrorCode::AuthorizationForStatementDenied) => {
+            anyhow::anyhow!("Query rejected: only read-only SELECT statements are allowed")
+        }
+        Some(ErrorCode::OperationInterrupted) => anyhow::anyhow!(
+            "Query exceeded time limit of {}ms",
+            limits.timeout.as_millis()
+        ),
+        Some(ErrorCode::ReadOnly) => anyhow::anyhow!("Query rejected: database is read-only"),
+        _ => anyhow::Error::new(e).context("Query failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+
+    async fn test_database() -> TestDatabase {
+        let db = TestDatabase::new().await;
+        db.get_conn()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO tasks (id, task_type, status) VALUES ('t1', 'click', 'completed');
+                 INSERT INTO tasks (id, task_type, status) VALUES ('t2', 'type', 'failed');",
+            )
+            .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_typed_values() {
+        let db = test_database().await;
+
+        let result = db
+            .execute_query(
+                "SELECT 42 AS i, 1.5 AS r, 'x' AS t, NULL AS n, X'DEADBEEF' AS b",
+                &QueryLimits::default(),
+            )
+            .unwrap();
+
+        assert_eq!(result.columns, ["i", "r", "t", "n", "b"]);
+        let row = &result.rows[0];
+        assert_eq!(row[0], serde_json::json!(42));
+        assert_eq!(row[1], serde_json::json!(1.5));
+        assert_eq!(row[2], serde_json::json!("x"));
+        assert!(row[3].is_null());
+        assert_eq!(row[4], serde_json::json!({ "base64": "3q2+7w==" }));
+    }
+
+    #[tokio::test]
+    async fn test_writes_and_attach_denied() {
+        let db = test_database().await;
+        let limits = QueryLimits::default();
+
+        for sql in [
+            "DELETE FROM tasks",
+            "UPDATE tasks SET status = 'x'",
+            "INSERT INTO preferences (key, value) VALUES ('a', 'b')",
+            "DROP TABLE tasks",
+            "ATTACH DATABASE '/tmp/other.db' AS other",
+            "PRAGMA journal_mode = DELETE",
+        ] {
+            assert!(db.execute_query(sql, &limits).is_err(), "allowed: {}", sql);
+        }
+
+        let count = db
+            .execute_query("SELECT COUNT(*) FROM tasks", &limits)
+            .unwrap();
+        assert_eq!(count.rows[0][0], serde_json::json!(2));
+    }
+
+    #[tokio::test]
+    async fn test_row_and_time_limits() {
+        let db = test_database().await;
+
+        let result = db
+            .execute_query("SELECT * FROM tasks", &QueryLimits::new(Some(1), None))
+            .unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert!(result.truncated);
+
+        let endless = "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) \
+                       SELECT MAX(x) FROM c";
+        let err = db
+            .execute_query(endless, &QueryLimits::new(None, Some(50)))
+            .unwrap_err();
+        assert!(err.to_string().contains("time limit"));
+    }
+}
@@ -0,0 +1,216 @@
+// Startup integrity check and recovery from corruption
+use super::backup::{validate_backup, BackupManager};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::{error, info, warn};
+
+/// What happened when a corrupt database was found at startup
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryReport {
+    pub detected_at: DateTime<Utc>,
+    pub problem: String,
+    /// Where the corrupt file was moved to
+    pub quarantined_path: String,
+    /// Backup that was restored, or None if the schema was recreated empty
+    pub restored_from: Option<String>,
+}
+
+/// Run `PRAGMA quick_check` on a database file.
+/// Returns `Ok(None)` when healthy and `Ok(Some(problem))` when corrupt;
+/// errors that don't indicate corruption (permissions, I/O) are returned as `Err`.
+pub fn quick_check(path: &Path) -> Result<Option<String>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .with_context(|| format!("Failed to open database: {}", path.display()))?;
+
+    let rows: Result<Vec<String>, rusqlite::Error> = conn
+        .prepare("PRAGMA quick_check")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get::<_, String>(0))?.collect());
+
+    match rows {
+        Ok(rows) if rows == ["ok"] => Ok(None),
+        Ok(rows) => Ok(Some(rows.join("; "))),
+        Err(e) if is_corruption(&e) => Ok(Some(e.to_string())),
+        Err(e) => Err(e).context("Failed to run integrity check"),
+    }
+}
+
+fn is_corruption(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(ErrorCode::DatabaseCorrupt) | Some(ErrorCode::NotADatabase)
+    )
+}
+
+/// Move a corrupt database aside and put the newest valid backup in its place.
+/// If no usable backup exists the path is left empty so the schema gets recreated.
+pub fn recover(
+    path: &Path,
+    problem: String,
+    backups: Option<&BackupManager>,
+) -> Result<RecoveryReport> {
+    let detected_at = Utc::now();
+
+    error!("==================================================================");
+    error!("DATABASE CORRUPTION DETECTED: {}", path.display());
+    error!("Problem: {}", problem);
+    error!("==================================================================");
+
+    let quarantined = quarantine(path, &detected_at)?;
+    warn!("Corrupt database moved to: {}", quarantined.display());
+
+    let restored_from = match backups {
+        Some(manager) => restore_newest_valid(manager, path)?,
+        None => None,
+    };
+
+    match &restored_from {
+        Some(backup) => warn!("Database restored from backup: {}", backup),
+        None => warn!("No valid backup found, recreating an empty database schema"),
+    }
+
+    Ok(RecoveryReport {
+        detected_at,
+        problem,
+        quarantined_path: quarantined.display().to_string(),
+        restored_from,
+    })
+}
+
+/// Rename the database (and its WAL/SHM side files) out of the way
+fn quarantine(path: &Path, detected_at: &DateTime<Utc>) -> Result<PathBuf> {
+    let suffix = format!("corrupt-{}", detected_at.format("%Y%m%d-%H%M%S"));
+    let target = side_path(path, &format!(".{}", suffix));
+
+    std::fs::rename(path, &target).with_context(|| {
+        format!(
+            "Failed to move corrupt database {} to {}",
+            path.display(),
+            target.display()
+        )
+    })?;
+
+    for side in ["-wal", "-shm"] {
+        let side_file = side_path(path, side);
+        if side_file.exists() {
+            let side_target = side_path(&target, side);
+            if let Err(e) = std::fs::rename(&side_file, &side_target) {
+                warn!("Failed to move {}: {}", side_file.display(), e);
+            }
+        }
+    }
+
+    Ok(target)
+}
+
+fn restore_newest_valid(manager: &BackupManager, path: &Path) -> Result<Option<String>> {
+    let candidates = match manager.list_backups() {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            warn!("Failed to list backups: {:#}", e);
+            return Ok(None);
+        }
+    };
+
+    for candidate in candidates {
+        let candidate_path = Path::new(&candidate.path);
+        match validate_backup(candidate_path) {
+            Ok(_) => {
+                std::fs::copy(candidate_path, path).with_context(|| {
+                    format!("Failed to copy backup {} into place", candidate.path)
+                })?;
+                info!("Restored valid backup: {}", candidate.path);
+                return Ok(Some(candidate.path));
+            }
+            Err(e) => warn!("Skipping unusable backup {}: {:#}", candidate.path, e),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Append a suffix to the file name of a path
+fn side_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TempDir;
+    use crate::db::Database;
+
+    fn corrupt(path: &Path) {
+        std::fs::write(path, b"this is not a sqlite database at all").unwrap();
+        let _ = std::fs::remove_file(side_path(path, "-wal"));
+        let _ = std::fs::remove_file(side_path(path, "-shm"));
+    }
+
+    #[tokio::test]
+    async fn test_healthy_database_passes() {
+        let dir = TempDir::new();
+        let path = dir.join("state.db");
+        let db = Database::new(path.to_str().unwrap(), None).unwrap();
+        db.migrate().await.unwrap();
+        drop(db);
+
+        assert!(quick_check(&path).unwrap().is_none());
+        let db = Database::new(path.to_str().unwrap(), None).unwrap();
+        assert!(db.recovery().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_database_restored_from_backup() {
+        let dir = TempDir::new();
+        let path = dir.join("state.db");
+        let manager = BackupManager::new(dir.join("backups"), 3, &path);
+
+        let db = Database::new(path.to_str().unwrap(), Some(&manager)).unwrap();
+        db.migrate().await.unwrap();
+        db.get_conn()
+            .unwrap()
+            .execute(
+                "INSERT INTO workflows (id, name, steps) VALUES ('wf-1', 'login', '[]')",
+                [],
+            )
+            .unwrap();
+        manager.create_backup(&db).unwrap();
+        drop(db);
+
+        corrupt(&path);
+
+        let db = Database::new(path.to_str().unwrap(), Some(&manager)).unwrap();
+        let report = db.recovery().expect("recovery should have happened");
+        assert!(report.restored_from.is_some());
+        assert!(Path::new(&report.quarantined_path).exists());
+
+        let count: i64 = db
+            .get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM workflows", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_database_recreated_without_backup() {
+        let dir = TempDir::new();
+        let path = dir.join("state.db");
+        corrupt(&path);
+
+        let db = Database::new(path.to_str().unwrap(), None).unwrap();
+        let report = db.recovery().expect("recovery should have happened");
+        assert!(report.restored_from.is_none());
+
+        assert_eq!(db.get_schema_version().unwrap(), 0);
+        db.migrate().await.unwrap();
+        assert_eq!(
+            db.get_schema_version().unwrap(),
+            crate::db::schema::CURRENT_SCHEMA_VERSION
+        );
+    }
+}
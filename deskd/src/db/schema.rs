@@ -1,3 +1,30 @@
 // Database schema definitions and migrations
 // Tables: tasks, task_history, auth_tokens, workflows, workflow_state,
-// element_cache, desktop_sessions, preferences, schema_version
+// element_cache, desktop_sessions, preferences, tree_snapshots, schema_version
+
+/// Migrations in the order they are applied, keyed by the version they produce
+pub const MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        include_str!("../../../migrations/001_initial_schema.sql"),
+    ),
+    (
+        2,
+        include_str!("../../../migrations/002_element_cache_persistence.sql"),
+    ),
+    (
+        3,
+        include_str!("../../../migrations/003_full_text_search.sql"),
+    ),
+    (
+        4,
+        include_str!("../../../migrations/004_tree_snapshots.sql"),
+    ),
+    (
+        5,
+        include_str!("../../../migrations/005_element_cache_ancestors.sql"),
+    ),
+];
+
+/// Highest schema version this build knows how to run against
+pub const CURRENT_SCHEMA_VERSION: i32 = 5;
@@ -0,0 +1,254 @@
+// Full-text search over task history and clipboard history (FTS5)
+use super::transfer::normalize_timestamp;
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::params_from_iter;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Default and maximum number of results
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+/// Indexed sources that can be searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSource {
+    TaskHistory,
+    Clipboard,
+}
+
+impl SearchSource {
+    pub const ALL: [SearchSource; 2] = [SearchSource::TaskHistory, SearchSource::Clipboard];
+
+    fn sql(&self) -> &'static str {
+        match self {
+            Self::TaskHistory => {
+                "SELECT h.id, h.task_id, h.event_type, h.timestamp, h.session_id,
+                        snippet(task_history_fts, 0, '[', ']', '...', 16),
+                        task_history_fts.rank
+                 FROM task_history_fts
+                 JOIN task_history h ON h.id = task_history_fts.rowid
+                 WHERE task_history_fts MATCH ?1"
+            }
+            Self::Clipboard => {
+                "SELECT h.id, NULL, NULL, h.timestamp, h.session_id,
+                        snippet(clipboard_history_fts, 0, '[', ']', '...', 16),
+                        clipboard_history_fts.rank
+                 FROM clipboard_history_fts
+                 JOIN clipboard_history h ON h.id = clipboard_history_fts.rowid
+                 WHERE clipboard_history_fts MATCH ?1"
+            }
+        }
+    }
+}
+
+impl FromStr for SearchSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "task_history" | "history" => Ok(Self::TaskHistory),
+            "clipboard" | "clipboard_history" => Ok(Self::Clipboard),
+            other => anyhow::bail!(
+                "Unknown search source: {} (expected task_history or clipboard)",
+                other
+            ),
+        }
+    }
+}
+
+/// A parsed search request
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    /// FTS5 match expression
+    expression: String,
+    sources: Vec<SearchSource>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: usize,
+}
+
+impl SearchQuery {
+    /// Every whitespace-separated word must match; a trailing `*` matches
+    /// prefixes. Other FTS5 syntax is treated as literal text.
+    pub fn new(
+        text: &str,
+        sources: Vec<SearchSource>,
+        since: Option<&str>,
+        until: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Self> {
+        let terms: Vec<String> = text
+            .split_whitespace()
+            .map(|word| match word.strip_suffix('*') {
+                Some(prefix) if !prefix.is_empty() => format!("{}*", quote_term(prefix)),
+                _ => quote_term(word),
+            })
+            .collect();
+        if terms.is_empty() {
+            anyhow::bail!("Search query is empty");
+        }
+
+        Ok(Self {
+            expression: terms.join(" "),
+            sources: if sources.is_empty() {
+                SearchSource::ALL.to_vec()
+            } else {
+                sources
+            },
+            since: since.map(normalize_timestamp).transpose()?,
+            until: until.map(normalize_timestamp).transpose()?,
+            limit: limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        })
+    }
+}
+
+fn quote_term(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+/// A ranked match; lower `rank` is a better match
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub source: SearchSource,
+    pub id: i64,
+    pub task_id: Option<String>,
+    pub event_type: Option<String>,
+    pub timestamp: Option<String>,
+    pub session_id: Option<String>,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+impl Database {
+    /// Search the full-text indexes, best matches first
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        let conn = self.get_conn()?;
+        let mut hits = Vec::new();
+
+        for source in &query.sources {
+            let mut sql = source.sql().to_string();
+            let mut params = vec![query.expression.clone()];
+            if let Some(since) = &query.since {
+                params.push(since.clone());
+                sql.push_str(&format!(" AND h.timestamp >= ?{}", params.len()));
+            }
+            if let Some(until) = &query.until {
+                params.push(until.clone());
+                sql.push_str(&format!(" AND h.timestamp < ?{}", params.len()));
+            }
+            sql.push_str(&format!(" ORDER BY rank LIMIT {}", query.limit));
+
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+                Ok(SearchHit {
+                    source: *source,
+                    id: row.get(0)?,
+                    task_id: row.get(1)?,
+                    event_type: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    session_id: row.get(4)?,
+                    snippet: row.get(5)?,
+                    rank: row.get(6)?,
+                })
+            })?;
+            for hit in rows {
+                hits.push(hit.context("Search failed")?);
+            }
+        }
+
+        hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+        hits.truncate(query.limit);
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+
+    async fn test_database() -> TestDatabase {
+        let db = TestDatabase::new().await;
+        db.get_conn()
+            .unwrap()
+            .execute_batch(
+                r#"INSERT INTO tasks (id, task_type, status) VALUES ('t1', 'type', 'failed');
+                   INSERT INTO task_history (task_id, event_type, timestamp, details, session_id)
+                   VALUES ('t1', 'failed', '2025-01-02 03:00:00',
+                           '{"error": "Timeout waiting for Firefox window"}', 's-1');
+                   INSERT INTO task_history (task_id, event_type, timestamp, details, session_id)
+                   VALUES ('t1', 'started', '2025-01-01 03:00:00',
+                           '{"text": "[secure input]"}', 's-1');
+                   INSERT INTO task_history (task_id, event_type, details)
+                   VALUES ('t1', 'started', '{"text": "firefox hunter2", "secure": true}');
+                   INSERT INTO clipboard_history (content, content_type, session_id)
+                   VALUES ('copied from firefox', 'text/plain', 's-2');"#,
+            )
+            .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_ranked_matches_across_sources() {
+        let db = test_database().await;
+
+        let query = SearchQuery::new("firefox", vec![], None, None, None).unwrap();
+        let hits = db.search(&query).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().any(|h| h.source == SearchSource::Clipboard));
+
+        let history = hits
+            .iter()
+            .find(|h| h.source == SearchSource::TaskHistory)
+            .unwrap();
+        assert_eq!(history.task_id.as_deref(), Some("t1"));
+        assert_eq!(history.session_id.as_deref(), Some("s-1"));
+        assert!(history.snippet.contains("[Firefox]"));
+
+        // Prefix terms, time filters and source selection
+        let query = SearchQuery::new(
+            "time*",
+            vec![SearchSource::TaskHistory],
+            Some("2025-01-02"),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(db.search(&query).unwrap().len(), 1);
+        let query = SearchQuery::new("firefox", vec![], None, Some("2025-01-02"), None).unwrap();
+        assert!(db.search(&query).unwrap().is_empty());
+
+        // FTS syntax in user input is literal
+        let query = SearchQuery::new("\"firefox OR", vec![], None, None, None).unwrap();
+        assert!(db.search(&query).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_secure_and_encrypted_entries_not_indexed() {
+        let db = test_database().await;
+
+        for term in ["hunter2", "secure"] {
+            let query = SearchQuery::new(term, vec![], None, None, None).unwrap();
+            assert!(db.search(&query).unwrap().is_empty(), "indexed: {}", term);
+        }
+
+        // Encrypting clipboard content removes it from the index
+        db.set_cipher(Some(crate::db::crypto::ColumnCipher::new(
+            crate::db::crypto::DataKey::generate(),
+            vec![],
+        )));
+        db.reseal_sensitive_columns(true).unwrap();
+        let query = SearchQuery::new("copied", vec![], None, None, None).unwrap();
+        assert!(db.search(&query).unwrap().is_empty());
+
+        // Deleting a source row removes it from the index
+        db.get_conn()
+            .unwrap()
+            .execute("DELETE FROM task_history WHERE event_type = 'failed'", [])
+            .unwrap();
+        let query = SearchQuery::new("timeout", vec![], None, None, None).unwrap();
+        assert!(db.search(&query).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+// Accessibility tree snapshots
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+/// A stored snapshot without its tree
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub id: i64,
+    pub name: String,
+    pub scope: String,
+    pub node_count: i64,
+    pub taken_at: Option<String>,
+}
+
+/// A stored snapshot; `tree` is encrypted at rest when encryption is enabled
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeSnapshot {
+    #[serde(flatten)]
+    pub info: SnapshotInfo,
+    pub tree: serde_json::Value,
+}
+
+impl Database {
+    /// Store a tree dump, returning the snapshot id
+    pub fn save_tree_snapshot(
+        &self,
+        name: &str,
+        scope: &str,
+        node_count: usize,
+        tree: &serde_json::Value,
+    ) -> Result<i64> {
+        let conn = self.get_conn()?;
+        let tree = self.seal("tree_snapshots", "tree", &tree.to_string());
+
+        conn.execute(
+            "INSERT INTO tree_snapshots (name, scope, node_count, tree)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![name, scope, node_count as i64, tree],
+        )
+        .context("Failed to save tree snapshot")?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Snapshots, newest first
+    pub fn list_tree_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, scope, node_count, taken_at
+             FROM tree_snapshots ORDER BY id DESC",
+        )?;
+        let rows = stmt
+            .query_map([], read_info)?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to list tree snapshots")?;
+        Ok(rows)
+    }
+
+    pub fn get_tree_snapshot(&self, id: i64) -> Result<Option<TreeSnapshot>> {
+        let conn = self.get_conn()?;
+        let row = conn
+            .query_row(
+                "SELECT id, name, scope, node_count, taken_at, tree
+                 FROM tree_snapshots WHERE id = ?1",
+                [id],
+                |row| Ok((read_info(row)?, row.get::<_, String>(5)?)),
+            )
+            .optional()
+            .context("Failed to load tree snapshot")?;
+
+        let Some((info, tree)) = row else {
+            return Ok(None);
+        };
+        let tree = self.unseal("tree_snapshots", "tree", tree)?;
+        Ok(Some(TreeSnapshot {
+            tree: serde_json::from_str(&tree)
+                .with_context(|| format!("Tree snapshot {} is not valid JSON", info.id))?,
+            info,
+        }))
+    }
+
+    pub fn delete_tree_snapshot(&self, id: i64) -> Result<bool> {
+        let conn = self.get_conn()?;
+        let removed = conn.execute("DELETE FROM tree_snapshots WHERE id = ?1", [id])?;
+        Ok(removed > 0)
+    }
+}
+
+fn read_info(row: &rusqlite::Row<'_>) -> rusqlite::Result<SnapshotInfo> {
+    Ok(SnapshotInfo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        scope: row.get(2)?,
+        node_count: row.get(3)?,
+        taken_at: row.get(4)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::crypto::{ColumnCipher, DataKey};
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip_encrypted() {
+        let db = TestDatabase::new().await;
+        db.set_cipher(Some(ColumnCipher::new(DataKey::generate(), vec![])));
+
+        let tree = serde_json::json!([{ "role": "Frame", "name": "Quarterly report.odt" }]);
+        let first = db
+            .save_tree_snapshot("before", "app=writer", 1, &tree)
+            .unwrap();
+        let second = db.save_tree_snapshot("after", "desktop", 1, &tree).unwrap();
+
+        let raw: String = db
+            .get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT tree FROM tree_snapshots WHERE id = ?1",
+                [first],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!raw.contains("Quarterly"));
+
+        let snapshot = db.get_tree_snapshot(first).unwrap().unwrap();
+        assert_eq!(snapshot.info.name, "before");
+        assert_eq!(snapshot.info.scope, "app=writer");
+        assert_eq!(snapshot.tree, tree);
+
+        let ids: Vec<i64> = db
+            .list_tree_snapshots()
+            .unwrap()
+            .iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids, vec![second, first]);
+
+        assert!(db.delete_tree_snapshot(first).unwrap());
+        assert!(!db.delete_tree_snapshot(first).unwrap());
+        assert!(db.get_tree_snapshot(first).unwrap().is_none());
+    }
+}
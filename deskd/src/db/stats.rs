@@ -0,0 +1,328 @@
+// Operation statistics computed from the audit trail
+use super::transfer::normalize_timestamp;
+use super::Database;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::params_from_iter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Window used when neither `since` nor `window` is given
+pub const DEFAULT_WINDOW: &str = "24h";
+
+/// Dimension a report can be broken down by, besides the operation itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StatsGroup {
+    /// The input method an operation would try first, not necessarily the
+    /// one that ended up delivering it
+    PlannedInputMethod,
+    Compositor,
+}
+
+impl FromStr for StatsGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "planned_input_method" => Ok(Self::PlannedInputMethod),
+            "compositor" => Ok(Self::Compositor),
+            other => anyhow::bail!(
+                "Unknown stats grouping: {} (expected planned_input_method or compositor)",
+                other
+            ),
+        }
+    }
+}
+
+/// A parsed statistics request
+#[derive(Debug, Clone)]
+pub struct StatsQuery {
+    since: String,
+    until: Option<String>,
+    groups: Vec<StatsGroup>,
+}
+
+impl StatsQuery {
+    /// `window` (e.g. `30m`, `24h`, `7d`) counts back from now and is
+    /// ignored when `since` is given
+    pub fn new(
+        since: Option<&str>,
+        until: Option<&str>,
+        window: Option<&str>,
+        mut groups: Vec<StatsGroup>,
+    ) -> Result<Self> {
+        let since = match since {
+            Some(since) => normalize_timestamp(since)?,
+            None => {
+                let window = parse_window(window.unwrap_or(DEFAULT_WINDOW))?;
+                (Utc::now() - window)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            }
+        };
+        groups.sort();
+        groups.dedup();
+
+        Ok(Self {
+            since,
+            until: until.map(normalize_timestamp).transpose()?,
+            groups,
+        })
+    }
+}
+
+/// Parse a duration such as `90s`, `30m`, `24h`, `7d` or `2w`
+pub fn parse_window(window: &str) -> Result<chrono::Duration> {
+    let window = window.trim();
+    let split = window
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(window.len());
+    let (amount, unit) = window.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid time window: {} (e.g. 30m, 24h, 7d)", window))?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "" | "d" => 86_400,
+        "w" => 604_800,
+        _ => anyhow::bail!("Invalid time window: {} (e.g. 30m, 24h, 7d)", window),
+    };
+    Ok(chrono::Duration::seconds(amount.saturating_mul(seconds)))
+}
+
+/// Statistics for one operation, or one operation on one backend/compositor
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationStats {
+    pub operation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub planned_input_method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compositor: Option<String>,
+    pub count: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Fraction of finished operations that succeeded, 0.0-1.0
+    pub success_rate: f64,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub since: String,
+    pub until: Option<String>,
+    /// Finished operations in the window
+    pub total: usize,
+    /// Least reliable first, then slowest
+    pub operations: Vec<OperationStats>,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    succeeded: usize,
+    failed: usize,
+    durations: Vec<f64>,
+}
+
+impl Database {
+    /// Count, success rate and latency percentiles of finished operations
+    pub fn operation_stats(&self, query: &StatsQuery) -> Result<StatsReport> {
+        let conn = self.get_conn()?;
+
+        let mut sql = "SELECT t.task_type, t.status,
+                    COALESCE(json_extract(t.metadata, '$.planned_input_method'),
+                             json_extract(t.metadata, '$.input_method')),
+                    json_extract(t.metadata, '$.compositor'),
+                    (SELECT json_extract(h.details, '$.duration_ms') FROM task_history h
+                     WHERE h.task_id = t.id AND h.event_type = t.status
+                     ORDER BY h.id DESC LIMIT 1)
+             FROM tasks t
+             WHERE t.status IN ('completed', 'failed') AND t.created_at >= ?1"
+            .to_string();
+        let mut params = vec![query.since.clone()];
+        if let Some(until) = &query.until {
+            params.push(until.clone());
+            sql.push_str(" AND t.created_at < ?2");
+        }
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<f64>>(4)?,
+            ))
+        })?;
+
+        let mut groups: BTreeMap<(String, Option<String>, Option<String>), Accumulator> =
+            BTreeMap::new();
+        let mut total = 0;
+        for row in rows {
+            let (operation, status, planned_input_method, compositor, duration) =
+                row.context("Failed to read operation statistics")?;
+            total += 1;
+
+            let key = (
+                operation,
+                query
+                    .groups
+                    .contains(&StatsGroup::PlannedInputMethod)
+                    .then(|| planned_input_method.unwrap_or_else(|| "none".to_string())),
+                query
+                    .groups
+                    .contains(&StatsGroup::Compositor)
+                    .then(|| compositor.unwrap_or_else(|| "unknown".to_string())),
+            );
+            let group = groups.entry(key).or_default();
+            if status == "completed" {
+                group.succeeded += 1;
+            } else {
+                group.failed += 1;
+            }
+            group.durations.extend(duration);
+        }
+
+        let mut operations: Vec<OperationStats> = groups
+            .into_iter()
+            .map(
+                |((operation, planned_input_method, compositor), mut group)| {
+                    group.durations.sort_by(f64::total_cmp);
+                    let count = group.succeeded + group.failed;
+                    OperationStats {
+                        operation,
+                        planned_input_method,
+                        compositor,
+                        count,
+                        succeeded: group.succeeded,
+                        failed: group.failed,
+                        success_rate: group.succeeded as f64 / count as f64,
+                        p50_ms: percentile(&group.durations, 50.0),
+                        p95_ms: percentile(&group.durations, 95.0),
+                        p99_ms: percentile(&group.durations, 99.0),
+                        max_ms: group.durations.last().copied(),
+                    }
+                },
+            )
+            .collect();
+
+        operations.sort_by(|a, b| {
+            a.success_rate
+                .total_cmp(&b.success_rate)
+                .then(b.p95_ms.unwrap_or(0.0).total_cmp(&a.p95_ms.unwrap_or(0.0)))
+        });
+
+        Ok(StatsReport {
+            since: query.since.clone(),
+            until: query.until.clone(),
+            total,
+            operations,
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tasks::{AuditEntry, TaskEvent};
+    use crate::db::testing::TestDatabase;
+    use serde_json::json;
+
+    #[test]
+    fn test_percentiles_and_windows() {
+        let values: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&values, 50.0), Some(50.0));
+        assert_eq!(percentile(&values, 95.0), Some(95.0));
+        assert_eq!(percentile(&values, 99.0), Some(99.0));
+        assert_eq!(percentile(&[7.0], 99.0), Some(7.0));
+        assert_eq!(percentile(&[], 50.0), None);
+
+        assert_eq!(parse_window("30m").unwrap().num_seconds(), 1800);
+        assert_eq!(parse_window("7d").unwrap().num_seconds(), 604_800);
+        assert!(parse_window("soon").is_err());
+        assert!(parse_window("5y").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stats_grouped_by_backend() {
+        let db = TestDatabase::new().await;
+
+        let mut entries = Vec::new();
+        for i in 0..20 {
+            let id = format!("t{}", i);
+            let (method, status) = match i {
+                0..=9 => ("portal", "completed"),
+                10..=16 => ("ydotool", "completed"),
+                _ => ("ydotool", "failed"),
+            };
+            entries.push(AuditEntry::TaskCreated {
+                id: id.clone(),
+                task_type: "Type".to_string(),
+                status: "running".to_string(),
+                metadata: Some(json!({ "planned_input_method": method, "compositor": "Sway" })),
+            });
+            entries.push(AuditEntry::Event(TaskEvent {
+                task_id: id.clone(),
+                event_type: status.to_string(),
+                details: Some(json!({ "duration_ms": i as f64 })),
+                user_id: None,
+                session_id: None,
+            }));
+            entries.push(AuditEntry::TaskStatus {
+                id,
+                status: status.to_string(),
+                error: None,
+            });
+        }
+        // Still running: not counted
+        entries.push(AuditEntry::TaskCreated {
+            id: "running".to_string(),
+            task_type: "Type".to_string(),
+            status: "running".to_string(),
+            metadata: None,
+        });
+        db.write_audit_entries(&entries).unwrap();
+
+        let report = db
+            .operation_stats(&StatsQuery::new(None, None, None, vec![]).unwrap())
+            .unwrap();
+        assert_eq!(report.total, 20);
+        assert_eq!(report.operations.len(), 1);
+        let all = &report.operations[0];
+        assert_eq!((all.succeeded, all.failed), (17, 3));
+        assert_eq!(all.p50_ms, Some(9.0));
+        assert_eq!(all.max_ms, Some(19.0));
+        assert!(all.planned_input_method.is_none());
+
+        let groups = vec![StatsGroup::PlannedInputMethod];
+        let query = StatsQuery::new(None, None, Some("1h"), groups).unwrap();
+        let report = db.operation_stats(&query).unwrap();
+        assert_eq!(report.operations.len(), 2);
+        // Least reliable first
+        assert_eq!(
+            report.operations[0].planned_input_method.as_deref(),
+            Some("ydotool")
+        );
+        assert!((report.operations[0].success_rate - 0.7).abs() < 1e-9);
+        assert_eq!(report.operations[1].success_rate, 1.0);
+
+        let query = StatsQuery::new(Some("2000-01-01"), Some("2000-01-02"), None, vec![]).unwrap();
+        assert_eq!(db.operation_stats(&query).unwrap().total, 0);
+    }
+}
@@ -1 +1,209 @@
 // Task persistence and history tracking
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::{params, ErrorCode, Transaction};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+/// A row written to the audit trail (`tasks` and `task_history`)
+#[derive(Debug, Clone)]
+pub enum AuditEntry {
+    /// Create a task; ignored if the id already exists
+    TaskCreated {
+        id: String,
+        task_type: String,
+        status: String,
+        metadata: Option<Value>,
+    },
+    /// Move a task to a new status; `completed` and `failed` are final
+    TaskStatus {
+        id: String,
+        status: String,
+        error: Option<String>,
+    },
+    Event(TaskEvent),
+}
+
+/// A `task_history` event; `details` must not contain secure input
+#[derive(Debug, Clone)]
+pub struct TaskEvent {
+    pub task_id: String,
+    pub event_type: String,
+    pub details: Option<Value>,
+    pub user_id: Option<String>,
+    pub session_id: Option<String>,
+}
+
+/// A `task_history` row as returned to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskHistoryEntry {
+    pub id: i64,
+    pub task_id: String,
+    pub task_type: Option<String>,
+    pub event_type: String,
+    pub timestamp: Option<String>,
+    pub details: Option<Value>,
+    pub user_id: Option<String>,
+    pub session_id: Option<String>,
+}
+
+impl Database {
+    /// Write a batch of audit entries in one transaction, in order.
+    /// Entries that violate a constraint (e.g. an event for an unknown task)
+    /// are skipped with a warning; returns the number written. Any other
+    /// error rolls the whole batch back so it can be retried.
+    pub fn write_audit_entries(&self, entries: &[AuditEntry]) -> Result<usize> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let mut written = 0;
+
+        for entry in entries {
+            match write_audit_entry(&tx, entry) {
+                Ok(()) => written += 1,
+                Err(e) if e.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+                    warn!("Dropping audit entry {:?}: {}", entry, e)
+                }
+                Err(e) => return Err(e).context("Failed to write audit entry"),
+            }
+        }
+
+        tx.commit().context("Failed to commit audit entries")?;
+        Ok(written)
+    }
+
+    /// Most recent history events first
+    pub fn list_task_history(&self, limit: usize) -> Result<Vec<TaskHistoryEntry>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT h.id, h.task_id, t.task_type, h.event_type, h.timestamp, h.details,
+                    h.user_id, h.session_id
+             FROM task_history h LEFT JOIN tasks t ON t.id = h.task_id
+             ORDER BY h.id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            let details: Option<String> = row.get(5)?;
+            Ok(TaskHistoryEntry {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                task_type: row.get(2)?,
+                event_type: row.get(3)?,
+                timestamp: row.get(4)?,
+                details: details
+                    .map(|text| serde_json::from_str(&text).unwrap_or(Value::String(text))),
+                user_id: row.get(6)?,
+                session_id: row.get(7)?,
+            })
+        })?;
+
+        rows.collect::<Result<_, _>>()
+            .context("Failed to read task history")
+    }
+}
+
+fn write_audit_entry(tx: &Transaction, entry: &AuditEntry) -> rusqlite::Result<()> {
+    match entry {
+        AuditEntry::TaskCreated {
+            id,
+            task_type,
+            status,
+            metadata,
+        } => {
+            tx.prepare_cached(
+                "INSERT OR IGNORE INTO tasks (id, task_type, status, metadata)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![
+                id,
+                task_type,
+                status,
+                metadata.as_ref().map(Value::to_string)
+            ])?;
+        }
+        AuditEntry::TaskStatus { id, status, error } => {
+            tx.prepare_cached(
+                "UPDATE tasks SET
+                    status = ?2,
+                    error = ?3,
+                    updated_at = CURRENT_TIMESTAMP,
+                    completed_at = CASE WHEN ?2 IN ('completed', 'failed')
+                                        THEN CURRENT_TIMESTAMP ELSE completed_at END
+                 WHERE id = ?1",
+            )?
+            .execute(params![id, status, error])?;
+        }
+        AuditEntry::Event(event) => {
+            tx.prepare_cached(
+                "INSERT INTO task_history (task_id, event_type, details, user_id, session_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                event.task_id,
+                event.event_type,
+                event.details.as_ref().map(Value::to_string),
+                event.user_id,
+                event.session_id
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_audit_batch_skips_invalid_entries() {
+        let db = TestDatabase::new().await;
+
+        let event = |task_id: &str, event_type: &str| {
+            AuditEntry::Event(TaskEvent {
+                task_id: task_id.to_string(),
+                event_type: event_type.to_string(),
+                details: Some(serde_json::json!({ "duration_ms": 12 })),
+                user_id: None,
+                session_id: Some("s-1".to_string()),
+            })
+        };
+        let written = db
+            .write_audit_entries(&[
+                AuditEntry::TaskCreated {
+                    id: "t1".to_string(),
+                    task_type: "Click".to_string(),
+                    status: "running".to_string(),
+                    metadata: None,
+                },
+                event("t1", "started"),
+                // Unknown task: rejected by the foreign key, the rest still commits
+                event("missing", "started"),
+                AuditEntry::TaskStatus {
+                    id: "t1".to_string(),
+                    status: "completed".to_string(),
+                    error: None,
+                },
+                event("t1", "completed"),
+            ])
+            .unwrap();
+        assert_eq!(written, 4);
+
+        let history = db.list_task_history(10).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].event_type, "completed");
+        assert_eq!(history[0].task_type.as_deref(), Some("Click"));
+        assert_eq!(history[0].details.as_ref().unwrap()["duration_ms"], 12);
+
+        let (status, completed): (String, Option<String>) = db
+            .get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT status, completed_at FROM tasks WHERE id = 't1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "completed");
+        assert!(completed.is_some());
+    }
+}
@@ -0,0 +1,766 @@
+// Export and import of audit history and workflows
+use super::{encryption, Database};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use rusqlite::types::{ToSqlOutput, Value as SqlValue};
+use rusqlite::{params_from_iter, OptionalExtension, ToSql};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Tables that can be exported and imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataTable {
+    Tasks,
+    TaskHistory,
+    Workflows,
+}
+
+impl DataTable {
+    /// Import order that satisfies foreign keys
+    pub const ALL: [DataTable; 3] = [Self::Tasks, Self::Workflows, Self::TaskHistory];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tasks => "tasks",
+            Self::TaskHistory => "task_history",
+            Self::Workflows => "workflows",
+        }
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Self::Tasks => &[
+                "id",
+                "task_type",
+                "status",
+                "created_at",
+                "updated_at",
+                "completed_at",
+                "error",
+                "metadata",
+            ],
+            Self::TaskHistory => &[
+                "id",
+                "task_id",
+                "event_type",
+                "timestamp",
+                "details",
+                "user_id",
+                "session_id",
+            ],
+            Self::Workflows => &[
+                "id",
+                "name",
+                "description",
+                "steps",
+                "created_at",
+                "updated_at",
+            ],
+        }
+    }
+
+    /// Column used for time-range filters
+    fn time_column(&self) -> &'static str {
+        match self {
+            Self::Tasks => "created_at",
+            Self::TaskHistory => "timestamp",
+            Self::Workflows => "updated_at",
+        }
+    }
+
+    /// Column used for type filters
+    fn type_column(&self) -> Option<&'static str> {
+        match self {
+            Self::Tasks => Some("task_type"),
+            Self::TaskHistory => Some("event_type"),
+            Self::Workflows => None,
+        }
+    }
+}
+
+impl FromStr for DataTable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tasks" => Ok(Self::Tasks),
+            "task_history" | "history" => Ok(Self::TaskHistory),
+            "workflows" => Ok(Self::Workflows),
+            _ => anyhow::bail!(
+                "Unknown table: {} (expected tasks, task_history or workflows)",
+                s
+            ),
+        }
+    }
+}
+
+/// Serialization format for exported data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    /// One `{"table": ..., "data": {...}}` object per line
+    Jsonl,
+    /// Header row plus one row per record; a single table per file
+    Csv,
+}
+
+impl FromStr for DataFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" | "json" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => anyhow::bail!("Unknown format: {} (expected jsonl or csv)", s),
+        }
+    }
+}
+
+/// Which records to export
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Inclusive lower bound, normalized to `YYYY-MM-DD HH:MM:SS`
+    pub since: Option<String>,
+    /// Exclusive upper bound, normalized to `YYYY-MM-DD HH:MM:SS`
+    pub until: Option<String>,
+    /// Task types (tasks) or event types (task_history) to include
+    pub types: Vec<String>,
+}
+
+impl ExportFilter {
+    pub fn new(since: Option<&str>, until: Option<&str>, types: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            since: since.map(normalize_timestamp).transpose()?,
+            until: until.map(normalize_timestamp).transpose()?,
+            types,
+        })
+    }
+}
+
+/// Result of an export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportResult {
+    pub format: DataFormat,
+    pub content: String,
+    pub counts: BTreeMap<DataTable, usize>,
+}
+
+/// Per-table outcome of an import
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Result of an import
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportResult {
+    pub tables: BTreeMap<DataTable, ImportCounts>,
+    /// First few record errors, for display
+    pub errors: Vec<String>,
+}
+
+const MAX_REPORTED_ERRORS: usize = 20;
+
+static NULL: Value = Value::Null;
+
+/// A record's value for a column, NULL when the column is absent
+fn column_value<'a>(record: &'a Map<String, Value>, column: &str) -> &'a Value {
+    record.get(column).unwrap_or(&NULL)
+}
+
+impl Database {
+    /// Export tables in the given format
+    pub fn export_data(
+        &self,
+        tables: &[DataTable],
+        format: DataFormat,
+        filter: &ExportFilter,
+    ) -> Result<ExportResult> {
+        if format == DataFormat::Csv && tables.len() != 1 {
+            anyhow::bail!("CSV export needs exactly one table");
+        }
+
+        let mut content = String::new();
+        let mut counts = BTreeMap::new();
+
+        for table in tables {
+            let records = self.read_records(*table, filter)?;
+            counts.insert(*table, records.len());
+
+            match format {
+                DataFormat::Jsonl => {
+                    for record in records {
+                        let line = serde_json::json!({ "table": table.name(), "data": record });
+                        content.push_str(&line.to_string());
+                        content.push('\n');
+                    }
+                }
+                DataFormat::Csv => {
+                    content.push_str(&csv_line(table.columns().iter().copied()));
+                    for record in &records {
+                        let cells = table.columns().iter().map(|column| {
+                            match column_value(record, column) {
+                                Value::Null => String::new(),
+                                Value::String(s) => s.clone(),
+                                other => other.to_string(),
+                            }
+                        });
+                        content.push_str(&csv_line(cells));
+                    }
+                }
+            }
+        }
+
+        Ok(ExportResult {
+            format,
+            content,
+            counts,
+        })
+    }
+
+    /// Import previously exported data. Re-importing the same data is a no-op:
+    /// tasks and workflows are keyed by id (newer `updated_at` wins) and history
+    /// rows are matched on task, event, timestamp and session.
+    pub fn import_data(
+        &self,
+        content: &str,
+        format: DataFormat,
+        csv_table: Option<DataTable>,
+    ) -> Result<ImportResult> {
+        let mut records: Vec<(DataTable, Map<String, Value>)> = match format {
+            DataFormat::Jsonl => parse_jsonl(content)?,
+            DataFormat::Csv => {
+                let table = csv_table.context("CSV import needs the target table")?;
+                parse_csv(content)?
+                    .into_iter()
+                    .map(|record| (table, record))
+                    .collect()
+            }
+        };
+
+        // Parents first so history rows can reference imported tasks
+        records.sort_by_key(|(table, _)| {
+            DataTable::ALL
+                .iter()
+                .position(|t| t == table)
+                .unwrap_or(usize::MAX)
+        });
+
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        let mut result = ImportResult::default();
+
+        for (index, (table, record)) in records.iter().enumerate() {
+            let counts = result.tables.entry(*table).or_default();
+            match import_record(&tx, *table, &self.seal_record(*table, record)) {
+                Ok(Outcome::Inserted) => counts.inserted += 1,
+                Ok(Outcome::Updated) => counts.updated += 1,
+                Ok(Outcome::Skipped) => counts.skipped += 1,
+                Err(e) => {
+                    counts.failed += 1;
+                    if result.errors.len() < MAX_REPORTED_ERRORS {
+                        result.errors.push(format!(
+                            "{} record {}: {:#}",
+                            table.name(),
+                            index + 1,
+                            e
+                        ));
+                    }
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(result)
+    }
+
+    fn read_records(
+        &self,
+        table: DataTable,
+        filter: &ExportFilter,
+    ) -> Result<Vec<Map<String, Value>>> {
+        let conn = self.get_conn()?;
+
+        let mut conditions = Vec::new();
+        let mut params: Vec<String> = Vec::new();
+        if let Some(since) = &filter.since {
+            params.push(since.clone());
+            conditions.push(format!("{} >= ?{}", table.time_column(), params.len()));
+        }
+        if let Some(until) = &filter.until {
+            params.push(until.clone());
+            conditions.push(format!("{} < ?{}", table.time_column(), params.len()));
+        }
+        if let (Some(column), false) = (table.type_column(), filter.types.is_empty()) {
+            let placeholders: Vec<String> = filter
+                .types
+                .iter()
+                .map(|t| {
+                    params.push(t.clone());
+                    format!("?{}", params.len())
+                })
+                .collect();
+            conditions.push(format!("{} IN ({})", column, placeholders.join(", ")));
+        }
+
+        let mut sql = format!(
+            "SELECT {} FROM {}",
+            table.columns().join(", "),
+            table.name()
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(&format!(" ORDER BY {}", table.time_column()));
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter()), |row| {
+            let mut record = Map::new();
+            for (i, column) in table.columns().iter().enumerate() {
+                let value = match row.get::<_, SqlValue>(i)? {
+                    SqlValue::Null => Value::Null,
+                    SqlValue::Integer(n) => Value::from(n),
+                    SqlValue::Real(f) => Value::from(f),
+                    SqlValue::Text(s) => Value::String(s),
+                    SqlValue::Blob(_) => Value::Null,
+                };
+                record.insert(column.to_string(), value);
+            }
+            Ok(record)
+        })?;
+
+        let mut records: Vec<Map<String, Value>> = rows
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("Failed to read {}", table.name()))?;
+
+        // Exports carry plaintext so they can be imported under another key
+        for record in &mut records {
+            for (column, value) in record.iter_mut() {
+                if let (true, Value::String(stored)) =
+                    (encryption::is_sensitive(table.name(), column), &*value)
+                {
+                    *value = Value::String(self.unseal(table.name(), column, stored.clone())?);
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Encrypt the sensitive fields of an imported record
+    fn seal_record(&self, table: DataTable, record: &Map<String, Value>) -> Map<String, Value> {
+        let mut record = record.clone();
+        for (column, value) in record.iter_mut() {
+            if let (true, Value::String(plain)) =
+                (encryption::is_sensitive(table.name(), column), &*value)
+            {
+                *value = Value::String(self.seal(table.name(), column, plain));
+            }
+        }
+        record
+    }
+}
+
+enum Outcome {
+    Inserted,
+    Updated,
+    Skipped,
+}
+
+fn import_record(
+    conn: &rusqlite::Connection,
+    table: DataTable,
+    record: &Map<String, Value>,
+) -> Result<Outcome> {
+    match table {
+        DataTable::Tasks | DataTable::Workflows => upsert_by_id(conn, table, record),
+        DataTable::TaskHistory => insert_history(conn, record),
+    }
+}
+
+fn upsert_by_id(
+    conn: &rusqlite::Connection,
+    table: DataTable,
+    record: &Map<String, Value>,
+) -> Result<Outcome> {
+    let id = text_field(record, "id").context("Record has no id")?;
+
+    let existing: Option<Option<String>> = conn
+        .query_row(
+            &format!("SELECT updated_at FROM {} WHERE id = ?1", table.name()),
+            [&id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let columns = table.columns();
+    let values: Vec<JsonParam> = columns
+        .iter()
+        .map(|column| JsonParam(column_value(record, column)))
+        .collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+
+    match existing {
+        None => {
+            conn.execute(
+                &format!(
+                    "INSERT INTO {} ({}) VALUES ({})",
+                    table.name(),
+                    columns.join(", "),
+                    placeholders.join(", ")
+                ),
+                params_from_iter(values.iter()),
+            )?;
+            Ok(Outcome::Inserted)
+        }
+        Some(current) => {
+            let incoming = text_field(record, "updated_at");
+            if incoming.is_none() || incoming <= current {
+                return Ok(Outcome::Skipped);
+            }
+
+            let assignments: Vec<String> = columns
+                .iter()
+                .zip(&placeholders)
+                .skip(1)
+                .map(|(column, placeholder)| format!("{} = {}", column, placeholder))
+                .collect();
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET {} WHERE id = ?1",
+                    table.name(),
+                    assignments.join(", ")
+                ),
+                params_from_iter(values.iter()),
+            )?;
+            Ok(Outcome::Updated)
+        }
+    }
+}
+
+fn insert_history(conn: &rusqlite::Connection, record: &Map<String, Value>) -> Result<Outcome> {
+    let field = |name: &str| JsonParam(column_value(record, name));
+
+    let duplicate: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM task_history
+         WHERE task_id = ?1 AND event_type = ?2 AND timestamp IS ?3 AND session_id IS ?4",
+        rusqlite::params![
+            field("task_id"),
+            field("event_type"),
+            field("timestamp"),
+            field("session_id")
+        ],
+        |row| row.get(0),
+    )?;
+    if duplicate {
+        return Ok(Outcome::Skipped);
+    }
+
+    // Keep the original id when it is free; ids from another machine may collide
+    let id_taken: bool = match record.get("id").and_then(|v| v.as_i64()) {
+        Some(id) => conn.query_row(
+            "SELECT COUNT(*) > 0 FROM task_history WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )?,
+        None => true,
+    };
+
+    let id = if id_taken {
+        JsonParam(&NULL)
+    } else {
+        field("id")
+    };
+    conn.execute(
+        "INSERT INTO task_history (id, task_id, event_type, timestamp, details, user_id, session_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            id,
+            field("task_id"),
+            field("event_type"),
+            field("timestamp"),
+            field("details"),
+            field("user_id"),
+            field("session_id")
+        ],
+    )?;
+    Ok(Outcome::Inserted)
+}
+
+/// Binds a JSON value as the matching SQLite type
+struct JsonParam<'a>(&'a Value);
+
+impl ToSql for JsonParam<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self.0 {
+            Value::Null => ToSqlOutput::Owned(SqlValue::Null),
+            Value::Bool(b) => ToSqlOutput::Owned(SqlValue::Integer(*b as i64)),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => ToSqlOutput::Owned(SqlValue::Integer(i)),
+                None => ToSqlOutput::Owned(SqlValue::Real(n.as_f64().unwrap_or_default())),
+            },
+            Value::String(s) => ToSqlOutput::Borrowed(s.as_str().into()),
+            other => ToSqlOutput::Owned(SqlValue::Text(other.to_string())),
+        })
+    }
+}
+
+fn text_field(record: &Map<String, Value>, name: &str) -> Option<String> {
+    match record.get(name)? {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn parse_jsonl(content: &str) -> Result<Vec<(DataTable, Map<String, Value>)>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let mut value: Value = serde_json::from_str(line)
+                .with_context(|| format!("Line {}: invalid JSON", i + 1))?;
+            let table: DataTable = value["table"]
+                .as_str()
+                .with_context(|| format!("Line {}: missing table", i + 1))?
+                .parse()?;
+            match value["data"].take() {
+                Value::Object(record) => Ok((table, record)),
+                _ => anyhow::bail!("Line {}: missing data object", i + 1),
+            }
+        })
+        .collect()
+}
+
+/// Parse CSV with a header row; empty fields become NULL
+fn parse_csv(content: &str) -> Result<Vec<Map<String, Value>>> {
+    let mut rows = split_csv(content)?.into_iter();
+    let header = rows.next().context("CSV has no header row")?;
+
+    rows.enumerate()
+        .map(|(i, row)| {
+            if row.len() != header.len() {
+                anyhow::bail!(
+                    "CSV row {}: expected {} fields, got {}",
+                    i + 2,
+                    header.len(),
+                    row.len()
+                );
+            }
+
+            Ok(header
+                .iter()
+                .zip(row)
+                .map(|(column, cell)| {
+                    let value = if cell.is_empty() {
+                        Value::Null
+                    } else if column == "id" {
+                        // task_history ids are integers, the others are text
+                        cell.parse::<i64>()
+                            .map(Value::from)
+                            .unwrap_or(Value::String(cell))
+                    } else {
+                        Value::String(cell)
+                    };
+                    (column.clone(), value)
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// Split CSV text into rows of fields (RFC 4180 quoting)
+fn split_csv(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        anyhow::bail!("CSV has an unterminated quoted field");
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn csv_line<S: AsRef<str>>(cells: impl Iterator<Item = S>) -> String {
+    let mut line = cells
+        .map(|cell| {
+            let cell = cell.as_ref();
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+/// Accept `YYYY-MM-DD`, `YYYY-MM-DD HH:MM:SS` or RFC 3339 and normalize to
+/// SQLite's `CURRENT_TIMESTAMP` format (UTC)
+pub(crate) fn normalize_timestamp(input: &str) -> Result<String> {
+    const SQLITE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+        return Ok(dt.naive_utc().format(SQLITE_FORMAT).to_string());
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(input, SQLITE_FORMAT) {
+        return Ok(dt.format(SQLITE_FORMAT).to_string());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Ok(format!("{} 00:00:00", date));
+    }
+
+    anyhow::bail!(
+        "Invalid time: {} (expected YYYY-MM-DD, 'YYYY-MM-DD HH:MM:SS' or RFC 3339)",
+        input
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::TestDatabase;
+
+    fn seed(db: &Database) {
+        db.get_conn()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO tasks (id, task_type, status, created_at, updated_at)
+                    VALUES ('t1', 'click', 'completed', '2025-01-01 10:00:00', '2025-01-01 10:00:00');
+                 INSERT INTO tasks (id, task_type, status, created_at, updated_at)
+                    VALUES ('t2', 'type', 'failed', '2025-02-01 10:00:00', '2025-02-01 10:00:00');
+                 INSERT INTO task_history (task_id, event_type, timestamp, details, session_id)
+                    VALUES ('t1', 'click', '2025-01-01 10:00:01', '{\"x\":1,\"y\":\"a,b\"}', 's1');
+                 INSERT INTO task_history (task_id, event_type, timestamp, details, session_id)
+                    VALUES ('t2', 'type', '2025-02-01 10:00:01', NULL, 's1');
+                 INSERT INTO workflows (id, name, steps, updated_at)
+                    VALUES ('wf-1', 'login', '[{\"action\":\"click\"}]', '2025-01-05 00:00:00');",
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_round_trip_is_idempotent() {
+        let source = TestDatabase::new().await;
+        seed(&source);
+        let export = source
+            .export_data(&DataTable::ALL, DataFormat::Jsonl, &ExportFilter::default())
+            .unwrap();
+        assert_eq!(export.counts[&DataTable::TaskHistory], 2);
+
+        let target = TestDatabase::new().await;
+        let first = target
+            .import_data(&export.content, DataFormat::Jsonl, None)
+            .unwrap();
+        assert!(first.errors.is_empty(), "{:?}", first.errors);
+        assert_eq!(first.tables[&DataTable::Tasks].inserted, 2);
+        assert_eq!(first.tables[&DataTable::TaskHistory].inserted, 2);
+        assert_eq!(first.tables[&DataTable::Workflows].inserted, 1);
+
+        let second = target
+            .import_data(&export.content, DataFormat::Jsonl, None)
+            .unwrap();
+        assert_eq!(second.tables[&DataTable::Tasks].skipped, 2);
+        assert_eq!(second.tables[&DataTable::TaskHistory].skipped, 2);
+        assert_eq!(second.tables[&DataTable::Workflows].skipped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_filters() {
+        let db = TestDatabase::new().await;
+        seed(&db);
+
+        let filter = ExportFilter::new(Some("2025-01-15"), None, vec![]).unwrap();
+        let export = db
+            .export_data(&[DataTable::Tasks], DataFormat::Jsonl, &filter)
+            .unwrap();
+        assert_eq!(export.counts[&DataTable::Tasks], 1);
+        assert!(export.content.contains("\"t2\""));
+
+        let filter = ExportFilter::new(None, None, vec!["click".to_string()]).unwrap();
+        let export = db
+            .export_data(&[DataTable::TaskHistory], DataFormat::Jsonl, &filter)
+            .unwrap();
+        assert_eq!(export.counts[&DataTable::TaskHistory], 1);
+
+        assert!(ExportFilter::new(Some("last tuesday"), None, vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_csv_round_trip_and_newer_update_wins() {
+        let db = TestDatabase::new().await;
+        seed(&db);
+
+        let export = db
+            .export_data(
+                &[DataTable::Workflows],
+                DataFormat::Csv,
+                &ExportFilter::default(),
+            )
+            .unwrap();
+        let updated = export
+            .content
+            .replace("login", "login-v2")
+            .replace("2025-01-05 00:00:00", "2025-03-01 00:00:00");
+
+        let result = db
+            .import_data(&updated, DataFormat::Csv, Some(DataTable::Workflows))
+            .unwrap();
+        assert_eq!(result.tables[&DataTable::Workflows].updated, 1);
+
+        let name: String = db
+            .get_conn()
+            .unwrap()
+            .query_row("SELECT name FROM workflows WHERE id = 'wf-1'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, "login-v2");
+
+        let history = db
+            .export_data(
+                &[DataTable::TaskHistory],
+                DataFormat::Csv,
+                &ExportFilter::default(),
+            )
+            .unwrap();
+        let rows = parse_csv(&history.content).unwrap();
+        assert_eq!(rows[0]["details"], "{\"x\":1,\"y\":\"a,b\"}");
+        assert!(rows[1]["details"].is_null());
+    }
+}
@@ -1 +1,146 @@
 // Workflow state management
+use super::Database;
+use anyhow::{Context, Result};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+/// A stored workflow; `steps` is encrypted at rest when encryption is enabled
+#[derive(Debug, Clone, Serialize)]
+pub struct Workflow {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: serde_json::Value,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[allow(dead_code)]
+impl Database {
+    /// Insert or update a workflow definition
+    pub fn save_workflow(
+        &self,
+        id: &str,
+        name: &str,
+        description: Option<&str>,
+        steps: &serde_json::Value,
+    ) -> Result<()> {
+        let conn = self.get_conn()?;
+        let steps = self.seal("workflows", "steps", &steps.to_string());
+
+        conn.execute(
+            "INSERT INTO workflows (id, name, description, steps)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                steps = excluded.steps,
+                updated_at = CURRENT_TIMESTAMP",
+            params![id, name, description, steps],
+        )
+        .context("Failed to save workflow")?;
+
+        Ok(())
+    }
+
+    pub fn get_workflow(&self, id: &str) -> Result<Option<Workflow>> {
+        let conn = self.get_conn()?;
+        let row = conn
+            .query_row(
+                "SELECT id, name, description, steps, created_at, updated_at
+                 FROM workflows WHERE id = ?1",
+                [id],
+                read_row,
+            )
+            .optional()
+            .context("Failed to load workflow")?;
+
+        row.map(|row| self.decode(row)).transpose()
+    }
+
+    pub fn list_workflows(&self) -> Result<Vec<Workflow>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, description, steps, created_at, updated_at
+             FROM workflows ORDER BY name",
+        )?;
+        let rows = stmt
+            .query_map([], read_row)?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to list workflows")?;
+
+        rows.into_iter().map(|row| self.decode(row)).collect()
+    }
+
+    pub fn delete_workflow(&self, id: &str) -> Result<bool> {
+        let conn = self.get_conn()?;
+        conn.execute("DELETE FROM workflow_state WHERE workflow_id = ?1", [id])?;
+        let removed = conn.execute("DELETE FROM workflows WHERE id = ?1", [id])?;
+        Ok(removed > 0)
+    }
+
+    fn decode(&self, row: WorkflowRow) -> Result<Workflow> {
+        let steps = self.unseal("workflows", "steps", row.steps)?;
+        Ok(Workflow {
+            steps: serde_json::from_str(&steps)
+                .with_context(|| format!("Workflow {} has invalid steps", row.id))?,
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// A workflow row before its steps are decrypted and parsed
+struct WorkflowRow {
+    id: String,
+    name: String,
+    description: Option<String>,
+    steps: String,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+fn read_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<WorkflowRow> {
+    Ok(WorkflowRow {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        steps: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::crypto::{ColumnCipher, DataKey};
+    use crate::db::testing::TestDatabase;
+
+    #[tokio::test]
+    async fn test_steps_encrypted_at_rest() {
+        let db = TestDatabase::new().await;
+        db.set_cipher(Some(ColumnCipher::new(DataKey::generate(), vec![])));
+
+        let steps = serde_json::json!([{ "type": "Type", "text": "s3cret-password" }]);
+        db.save_workflow("login", "Login", None, &steps).unwrap();
+
+        let raw: String = db
+            .get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT steps FROM workflows WHERE id = 'login'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!raw.contains("s3cret-password"));
+
+        let workflow = db.get_workflow("login").unwrap().unwrap();
+        assert_eq!(workflow.steps, steps);
+        assert_eq!(db.list_workflows().unwrap().len(), 1);
+        assert!(db.delete_workflow("login").unwrap());
+    }
+}
@@ -1,18 +1,19 @@
 // Unix socket IPC server
 use anyhow::Result;
+use std::sync::Arc;
 
 pub mod protocol;
 pub mod server;
 
-pub use server::UnixSocketServer;
+pub use server::{ServerContext, UnixSocketServer};
 
 pub struct IpcServer {
     server: UnixSocketServer,
 }
 
 impl IpcServer {
-    pub fn new(socket_path: &str) -> Result<Self> {
-        let server = UnixSocketServer::new(socket_path)?;
+    pub fn new(socket_path: &str, context: Arc<ServerContext>) -> Result<Self> {
+        let server = UnixSocketServer::new(socket_path, context)?;
         Ok(Self { server })
     }
 
@@ -1,48 +1,255 @@
 // Protocol definitions and message serialization
+use crate::atspi::element::{Candidate, Element};
+use crate::config::profiles::TypingStrategy;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum Request {
+    // Daemon status
+    Health,
+
     // Input operations
-    Type { text: String },
-    TypeSecure { text: String },
-    Click { x: i32, y: i32 },
+    Type {
+        text: String,
+    },
+    TypeSecure {
+        text: String,
+    },
+    Click {
+        x: i32,
+        y: i32,
+    },
 
     // Focus operations
-    Focus { element_id: String },
+    Focus {
+        element_id: String,
+    }, // an element handle from FindElement
 
     // Desktop operations
     ListDesktops,
 
     // Clipboard operations
     ClipboardGet,
-    ClipboardSet { content: String },
+    ClipboardSet {
+        content: String,
+    },
 
     // Database queries
-    GetTaskHistory { limit: Option<usize> },
+    GetTaskHistory {
+        limit: Option<usize>,
+    },
+
+    // Database maintenance
+    DbQuery {
+        sql: String,
+        max_rows: Option<usize>,
+        timeout_ms: Option<u64>,
+    },
+    DbBackup {
+        path: Option<String>,
+    },
+    DbListBackups,
+    DbRestore {
+        path: String,
+    },
+    DbRekey,
+
+    // Data export and import
+    ExportData {
+        tables: Option<Vec<String>>, // tasks, task_history, workflows
+        format: Option<String>,      // "jsonl" or "csv"
+        since: Option<String>,
+        until: Option<String>,
+        types: Option<Vec<String>>,
+    },
+    ImportData {
+        content: String,
+        format: Option<String>,
+        table: Option<String>, // required for csv
+    },
+
+    // Runtime preferences (override the config file)
+    GetPreference {
+        key: String,
+    },
+    SetPreference {
+        key: String,
+        value: serde_json::Value, // null removes the override
+    },
+    ListPreferences,
+    ReloadConfig, // re-read the config file
+    GetConfig,    // every effective setting with its origin
+    SetLogLevel {
+        level: String, // a level (`debug`) or a full filter directive
+    },
+    // Profile and settings for an application; the focused application when
+    // no identity is given
+    GetProfile {
+        app_name: Option<String>,
+        window_class: Option<String>,
+        app_id: Option<String>,
+    },
+
+    // Full-text search
+    Search {
+        query: String,
+        sources: Option<Vec<String>>, // task_history, clipboard
+        since: Option<String>,
+        until: Option<String>,
+        limit: Option<usize>,
+    },
+
+    // Operation statistics from the audit trail
+    GetStats {
+        since: Option<String>,
+        until: Option<String>,
+        window: Option<String>, // e.g. "24h"; used when since is not given
+        group_by: Option<Vec<String>>, // planned_input_method, compositor
+    },
 
     // AT-SPI Element operations (Phase 2)
+    // Elements are given by name, by a selector such as
+    // `dialog push-button[name="OK"]:enabled`, or by the handle FindElement
+    // returned for them; exactly one of the three
     FindElement {
         name: Option<String>,
         role: Option<String>,
+        states: Option<Vec<String>>, // all must be set, e.g. ["enabled", "showing"]
+        app: Option<String>,         // only search this application
+        selector: Option<String>,
+        fuzzy: Option<bool>, // names may differ in case, spacing, mnemonics, ellipses
+        max_depth: Option<usize>,
     },
     ClickElement {
-        name: String,
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
         button: Option<String>,
     },
     DoubleClickElement {
-        name: String,
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
     },
     TypeIntoElement {
-        name: String,
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
         text: String,
         secure: Option<bool>,
+        // Overrides the typing strategies of the element application's profile
+        strategy: Option<TypingStrategy>,
     },
     FocusElement {
-        name: String,
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
     },
     GetFocusedElement,
+    // Actions from the element's AT-SPI Action interface, default first
+    ListActions {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+    },
+    // Invoke an action by name ("click", "press", "expand or contract", ...)
+    DoAction {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        action: String,
+    },
+    // Completes once the element has keyboard focus; fails after timeout_ms,
+    // by default the focus timeout of the element application's profile
+    WaitForFocus {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        timeout_ms: Option<u64>,
+    },
+    // The element's text through its AT-SPI Text interface. Offsets count
+    // characters, ranges end before `end` and default to the whole text
+    GetText {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        start: Option<i32>,
+        end: Option<i32>,
+    },
+    // Caret offset and selection
+    GetCaret {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+    },
+    SetCaret {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        offset: i32,
+    },
+    // An empty range clears the selection
+    SetSelection {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        start: i32,
+        end: i32,
+    },
+    // Edits through the EditableText interface, read back afterwards
+    InsertText {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        text: String,
+        position: Option<i32>, // default: at the caret
+    },
+    DeleteText {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        start: Option<i32>,
+        end: Option<i32>,
+    },
+    ReplaceText {
+        name: Option<String>,
+        selector: Option<String>,
+        handle: Option<String>,
+        fuzzy: Option<bool>,
+        text: String,
+        start: Option<i32>,
+        end: Option<i32>,
+    },
+    // The tree below the desktop, an application or a top-level window, for
+    // writing selectors; optionally stored as a snapshot under a name
+    GetTree {
+        app: Option<String>,
+        window: Option<String>, // substring of a window's name
+        max_depth: Option<usize>,
+        filter: Option<String>, // selector; matches are kept with their ancestors
+        snapshot: Option<String>,
+    },
+    ListTreeSnapshots,
+    GetTreeSnapshot {
+        id: i64,
+    },
+    DeleteTreeSnapshot {
+        id: i64,
+    },
 
     // Wayland operations (Phase 3)
     KeyPress {
@@ -76,10 +283,217 @@ pub enum Request {
     },
 }
 
+impl Request {
+    /// Task type recorded in the audit trail for requests that act on the
+    /// desktop; `None` for queries and daemon maintenance
+    pub fn audit_task_type(&self) -> Option<&'static str> {
+        let task_type = match self {
+            Self::Type { .. } => "Type",
+            Self::TypeSecure { .. } => "TypeSecure",
+            Self::Click { .. } => "Click",
+            Self::Focus { .. } => "Focus",
+            Self::ClipboardSet { .. } => "ClipboardSet",
+            Self::ClickElement { .. } => "ClickElement",
+            Self::DoubleClickElement { .. } => "DoubleClickElement",
+            Self::TypeIntoElement { .. } => "TypeIntoElement",
+            Self::FocusElement { .. } => "FocusElement",
+            Self::DoAction { .. } => "DoAction",
+            Self::SetCaret { .. } => "SetCaret",
+            Self::SetSelection { .. } => "SetSelection",
+            Self::InsertText { .. } => "InsertText",
+            Self::DeleteText { .. } => "DeleteText",
+            Self::ReplaceText { .. } => "ReplaceText",
+            Self::KeyPress { .. } => "KeyPress",
+            Self::KeyCombo { .. } => "KeyCombo",
+            Self::KeySequence { .. } => "KeySequence",
+            Self::ClickAt { .. } => "ClickAt",
+            Self::Drag { .. } => "Drag",
+            Self::Screenshot { .. } => "Screenshot",
+            _ => return None,
+        };
+        Some(task_type)
+    }
+
+    /// Whether the request injects input through one of the input backends
+    pub fn uses_input_backend(&self) -> bool {
+        matches!(
+            self,
+            Self::Type { .. }
+                | Self::TypeSecure { .. }
+                | Self::Click { .. }
+                | Self::ClickElement { .. }
+                | Self::DoubleClickElement { .. }
+                | Self::TypeIntoElement { .. }
+                | Self::KeyPress { .. }
+                | Self::KeyCombo { .. }
+                | Self::KeySequence { .. }
+                | Self::ClickAt { .. }
+                | Self::Drag { .. }
+        )
+    }
+
+    /// Whether the request uses settings from an application profile: the
+    /// input backends' timing and methods, or the focus timeout
+    pub fn uses_profile(&self) -> bool {
+        self.uses_input_backend()
+            || matches!(
+                self,
+                Self::Focus { .. } | Self::FocusElement { .. } | Self::WaitForFocus { .. }
+            )
+    }
+
+    /// The element the request acts on, for requests that name one
+    pub fn element_target(&self) -> Option<ElementTarget> {
+        let target = match self {
+            Self::ClickElement {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::DoubleClickElement {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::TypeIntoElement {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::FocusElement {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::ListActions {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::DoAction {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::WaitForFocus {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::GetText {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::GetCaret {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::SetCaret {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::SetSelection {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::InsertText {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::DeleteText {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            }
+            | Self::ReplaceText {
+                name,
+                selector,
+                handle,
+                fuzzy,
+                ..
+            } => ElementTarget {
+                name: name.clone(),
+                selector: selector.clone(),
+                handle: handle.clone(),
+                fuzzy: *fuzzy,
+            },
+            Self::Focus { element_id } => ElementTarget {
+                handle: Some(element_id.clone()),
+                ..ElementTarget::default()
+            },
+            _ => return None,
+        };
+        Some(target)
+    }
+}
+
+/// How an element request names its element: exactly one of name, selector
+/// and handle
+#[derive(Debug, Clone, Default)]
+pub struct ElementTarget {
+    pub name: Option<String>,
+    pub selector: Option<String>,
+    pub handle: Option<String>,
+    pub fuzzy: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum Response {
-    Success { message: String },
-    Error { error: String },
-    Data { data: serde_json::Value },
+    Success {
+        message: String,
+    },
+    Error {
+        error: String,
+    },
+    Data {
+        data: serde_json::Value,
+    },
+    /// An element request matched no element or several; candidates are
+    /// ranked by how closely their names match
+    NoUniqueElement {
+        error: String,
+        matches: usize,
+        candidates: Vec<Candidate>,
+        /// Applications that could only be searched in part
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        incomplete: Vec<String>,
+    },
+    /// An element handle no longer names the element it was made for;
+    /// `candidate` is the element now matching its fingerprint, if exactly one does
+    StaleElement {
+        error: String,
+        candidate: Option<Element>,
+    },
 }
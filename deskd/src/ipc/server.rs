@@ -1,17 +1,66 @@
 // Unix socket server implementation
-use super::protocol::{Request, Response};
-use anyhow::{Context, Result};
-use std::path::Path;
+use super::protocol::{ElementTarget, Request, Response};
+use crate::atspi::action;
+use crate::atspi::element::{self, Element, ElementSelector, LookupError, StaleError};
+use crate::atspi::input::{ClickButton, ClickMethod, InputHandler, Typed};
+use crate::atspi::selector::Selector;
+use crate::atspi::text::{self, Readback, TextRange};
+use crate::atspi::tree::TreeScope;
+use crate::atspi::AtSpiClient;
+use crate::config::preferences;
+use crate::config::profiles::{AppIdentity, AppSettings};
+use crate::config::runtime::RuntimeConfig;
+use crate::db::async_db::AsyncDatabase;
+use crate::db::backup::BackupManager;
+use crate::db::keystore::{self, KeyStore};
+use crate::db::query::QueryLimits;
+use crate::db::schema::CURRENT_SCHEMA_VERSION;
+use crate::db::search::{SearchQuery, SearchSource};
+use crate::db::stats::{StatsGroup, StatsQuery};
+use crate::db::tasks::{AuditEntry, TaskEvent};
+use crate::db::transfer::{DataFormat, DataTable, ExportFilter};
+use crate::logging::LogControl;
+use crate::wayland::compositor::{self, FocusedWindow};
+use crate::wayland::detection::CompositorType;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
-use tracing::{error, info};
+use tracing::{debug, error, info, Instrument};
+
+/// History rows returned when the client doesn't ask for a number
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// How long to wait for the compositor to report the focused window, or for
+/// the focused application to give its name
+const FOCUSED_APP_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Daemon state shared by all client connections
+pub struct ServerContext {
+    pub database: AsyncDatabase,
+    /// Config file values with runtime preferences applied
+    pub config: Arc<RuntimeConfig>,
+    pub backups: Arc<BackupManager>,
+    /// Key storage when column encryption is enabled
+    pub keys: Option<Arc<KeyStore>>,
+    /// Compositor detected at startup, recorded with each audited operation
+    pub compositor: CompositorType,
+    /// Log filter, adjustable with `SetLogLevel`
+    pub logging: Arc<LogControl>,
+    /// Accessibility bus connection; `None` when AT-SPI is unavailable
+    pub atspi: Option<Arc<AtSpiClient>>,
+}
 
 pub struct UnixSocketServer {
     listener: UnixListener,
+    context: Arc<ServerContext>,
 }
 
 impl UnixSocketServer {
-    pub fn new(socket_path: &str) -> Result<Self> {
+    pub fn new(socket_path: &str, context: Arc<ServerContext>) -> Result<Self> {
         // Remove existing socket file if it exists
         if Path::new(socket_path).exists() {
             std::fs::remove_file(socket_path)
@@ -30,15 +79,16 @@ impl UnixSocketServer {
 
         info!("Unix socket server listening on: {}", socket_path);
 
-        Ok(Self { listener })
+        Ok(Self { listener, context })
     }
 
     pub async fn run(&self) -> Result<()> {
         loop {
             match self.listener.accept().await {
                 Ok((stream, _addr)) => {
+                    let context = self.context.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_client(stream).await {
+                        if let Err(e) = handle_client(stream, context).await {
                             error!("Error handling client: {}", e);
                         }
                     });
@@ -51,7 +101,7 @@ impl UnixSocketServer {
     }
 }
 
-async fn handle_client(stream: UnixStream) -> Result<()> {
+async fn handle_client(stream: UnixStream, context: Arc<ServerContext>) -> Result<()> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
@@ -73,9 +123,16 @@ async fn handle_client(stream: UnixStream) -> Result<()> {
             continue;
         }
 
-        // Parse request
+        // Parse request; everything logged while handling it carries the
+        // request id, which is also its audit task id
         let response = match serde_json::from_str::<Request>(line) {
-            Ok(request) => process_request(request).await,
+            Ok(request) => {
+                let request_id = uuid::Uuid::new_v4().to_string();
+                let span = tracing::info_span!("request", request_id = %request_id);
+                process_request(request, request_id, &context)
+                    .instrument(span)
+                    .await
+            }
             Err(e) => Response::Error {
                 error: format!("Invalid request: {}", e),
             },
@@ -97,10 +154,407 @@ async fn handle_client(stream: UnixStream) -> Result<()> {
     Ok(())
 }
 
-async fn process_request(request: Request) -> Response {
+/// Handle a request, recording desktop actions in the audit trail. Audit rows
+/// are queued for the background writer, so they add no SQLite round trips.
+async fn process_request(
+    request: Request,
+    task_id: String,
+    context: &Arc<ServerContext>,
+) -> Response {
+    // Element requests pick up the profile of the element's application, so
+    // the element is looked up first. Other requests that send input or wait
+    // for focus pick up the profile of the focused application.
+    let started = Instant::now();
+    let (target, settings) = match request.element_target() {
+        Some(target) => match target_element(context, target).await {
+            Ok((element, settings)) => (Some(Ok(element)), Some(settings)),
+            Err(e) => (Some(Err(e)), None),
+        },
+        None if request.uses_profile() => (None, Some(app_settings(context).await)),
+        None => (None, None),
+    };
+    let Some(task_type) = request.audit_task_type() else {
+        return handle_request(request, target, settings.as_ref(), context).await;
+    };
+
+    let database = &context.database;
+    let event = |event_type: &str, details: Option<serde_json::Value>| {
+        AuditEntry::Event(TaskEvent {
+            task_id: task_id.clone(),
+            event_type: event_type.to_string(),
+            details,
+            user_id: None,
+            session_id: None,
+        })
+    };
+
+    // The input backends do not report which method delivered the input, so
+    // only the method tried first is known, and recorded as planned
+    let planned_input_method = settings
+        .as_ref()
+        .filter(|_| request.uses_input_backend())
+        .and_then(|settings| settings.method_order.first());
+    let metadata = serde_json::json!({
+        "compositor": context.compositor,
+        "planned_input_method": planned_input_method,
+        "profile": settings.as_ref().and_then(|settings| settings.profile.as_ref()),
+    });
+
+    database
+        .audit(AuditEntry::TaskCreated {
+            id: task_id.clone(),
+            task_type: task_type.to_string(),
+            status: "running".to_string(),
+            metadata: Some(metadata),
+        })
+        .await;
+    database.audit(event("started", None)).await;
+
+    let response = handle_request(request, target, settings.as_ref(), context).await;
+
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let (status, error) = match &response {
+        Response::Error { error }
+        | Response::NoUniqueElement { error, .. }
+        | Response::StaleElement { error, .. } => ("failed", Some(error.clone())),
+        _ => ("completed", None),
+    };
+    database
+        .audit(event(
+            status,
+            Some(serde_json::json!({ "duration_ms": duration_ms, "error": error })),
+        ))
+        .await;
+    database
+        .audit(AuditEntry::TaskStatus {
+            id: task_id.clone(),
+            status: status.to_string(),
+            error,
+        })
+        .await;
+
+    response
+}
+
+/// The application with focus: the window the compositor reports, and the
+/// application AT-SPI last reported focus in. GNOME and KDE do not report
+/// windows, so there the AT-SPI application name is all that is known.
+async fn focused_app(context: &ServerContext) -> AppIdentity {
+    let (window, app_name) = tokio::join!(focused_window(context), focused_app_name(context));
+    AppIdentity::new(window, app_name)
+}
+
+/// The focused window, as far as the compositor can tell
+async fn focused_window(context: &ServerContext) -> Option<FocusedWindow> {
+    let window = tokio::time::timeout(
+        FOCUSED_APP_TIMEOUT,
+        compositor::focused_window(context.compositor),
+    )
+    .await
+    .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+    match window {
+        Ok(window) => window,
+        Err(e) => {
+            debug!("Focused window unknown: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Name of the AT-SPI application the focused object belongs to
+async fn focused_app_name(context: &ServerContext) -> Option<String> {
+    let atspi = context.atspi.as_ref()?;
+    let focused = atspi.focus().focused()?;
+    let name = tokio::time::timeout(
+        FOCUSED_APP_TIMEOUT,
+        element::application_name(atspi.connection(), &focused.bus_name),
+    )
+    .await
+    .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+    match name {
+        Ok(name) => Some(name).filter(|name| !name.is_empty()),
+        Err(e) => {
+            debug!("Focused application unknown: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Build a selector from request fields, rejecting unknown roles and states.
+/// A selector string replaces the individual fields.
+fn element_selector(
+    name: Option<String>,
+    role: Option<String>,
+    states: Option<Vec<String>>,
+    app: Option<String>,
+    selector: Option<String>,
+    fuzzy: bool,
+) -> Result<Selector> {
+    if let Some(selector) = selector {
+        if name.is_some() || role.is_some() || states.is_some() || app.is_some() || fuzzy {
+            bail!("A selector cannot be combined with name, role, states, app or fuzzy");
+        }
+        return selector.parse();
+    }
+
+    let role = role
+        .map(|role| element::parse_role(&role).with_context(|| format!("Unknown role: {}", role)))
+        .transpose()?;
+    let states = states
+        .map(|states| {
+            states
+                .iter()
+                .map(|state| {
+                    element::parse_state(state).with_context(|| format!("Unknown state: {}", state))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
+    Ok(ElementSelector {
+        name,
+        role,
+        states,
+        app,
+        fuzzy,
+    }
+    .into())
+}
+
+/// The element an element request acts on, given by name, selector or
+/// handle, with the settings of its application's profile. Names and
+/// selectors must match exactly one element.
+async fn target_element(
+    context: &ServerContext,
+    target: ElementTarget,
+) -> Result<(Element, AppSettings)> {
+    let ElementTarget {
+        name,
+        selector,
+        handle,
+        fuzzy,
+    } = target;
+    let fuzzy = fuzzy.unwrap_or(false);
+    let selector = match (&name, selector, handle) {
+        (Some(name), None, None) => ElementSelector {
+            fuzzy,
+            ..ElementSelector::by_name(name)
+        }
+        .into(),
+        (None, Some(_), None) if fuzzy => {
+            bail!("Fuzzy matching only applies to element names; selectors take the f flag")
+        }
+        (None, None, Some(_)) if fuzzy => {
+            bail!("Fuzzy matching does not apply to handles, which name one element exactly")
+        }
+        (None, Some(selector), None) => selector.parse::<Selector>()?,
+        (None, None, Some(handle)) => {
+            let element = resolve_handle(context, &handle).await?;
+            let settings = element_settings(context, &element).await;
+            return Ok((element, settings));
+        }
+        (None, None, None) => bail!("An element name, selector or handle is required"),
+        _ => bail!("Give only one of element name, selector and handle"),
+    };
+    let Some(atspi) = &context.atspi else {
+        bail!("AT-SPI is not available");
+    };
+
+    // Profiles can turn caching off for applications it misleads, so what
+    // the cache holds is checked against the profile of its application
+    let enabled = context.config.current().atspi.enable_cache;
+    let cache = atspi.cache().filter(|_| enabled);
+    let key = selector.to_string();
+    if let Some(cache) = cache {
+        if let Some(element) = cache.get_verified(&key, atspi.connection()).await {
+            let settings = element_settings(context, &element).await;
+            if settings.atspi.enable_cache {
+                return Ok((element, settings));
+            }
+            cache.invalidate(&key).await;
+        }
+    }
+
+    let finder = atspi.finder();
+    let element = finder.find_unique(&selector, name.as_deref()).await?;
+    let settings = element_settings(context, &element).await;
+    if let Some(cache) = cache.filter(|_| settings.atspi.enable_cache) {
+        match finder
+            .ancestor_paths(&element.bus_name, &element.path)
+            .await
+        {
+            Ok(ancestors) => {
+                let ttl = Duration::from_secs(settings.atspi.cache_expiration_seconds);
+                cache
+                    .set_in_tree(key, element.clone(), ancestors, ttl)
+                    .await
+            }
+            Err(e) => debug!("Not caching {}: {:#}", key, e),
+        }
+    }
+    Ok((element, settings))
+}
+
+/// The element `process_request` looked up for an element request
+fn targeted(target: Option<Result<Element>>) -> Result<Element> {
+    target.unwrap_or_else(|| Err(anyhow::anyhow!("No element was looked up")))
+}
+
+/// The element a handle names, or a stale element error
+async fn resolve_handle(context: &ServerContext, handle: &str) -> Result<Element> {
+    let Some(atspi) = &context.atspi else {
+        bail!("AT-SPI is not available");
+    };
+    atspi.finder().resolve(handle).await
+}
+
+/// Response for a text edit: where the new text is, and whether it read back
+/// as edited
+fn edit_response(element: &Element, edit: Result<(TextRange, Readback)>) -> Response {
+    let edit = edit.and_then(|(range, readback)| Ok((range, readback.checked(element)?)));
+    match edit {
+        Ok((range, verified)) => Response::Data {
+            data: serde_json::json!({
+                "range": range,
+                "verified": verified,
+            }),
+        },
+        Err(e) => Response::Error {
+            error: format!("{:#}", e),
+        },
+    }
+}
+
+/// Input handler with the timing of the focused application's profile
+fn input_handler(context: &ServerContext, settings: Option<&AppSettings>) -> InputHandler {
+    let timing = match settings {
+        Some(settings) => settings.input_timing.clone(),
+        None => context.config.current().input_timing.clone(),
+    };
+    InputHandler::new(timing.typing_delay_ms, timing.click_delay_ms)
+}
+
+/// How long focus may take to arrive: the request's own timeout, else the
+/// focused application's profile, else the configured default
+fn focus_timeout(
+    context: &ServerContext,
+    settings: Option<&AppSettings>,
+    timeout_ms: Option<u64>,
+) -> Duration {
+    let timeout_ms = timeout_ms
+        .or(settings.map(|settings| settings.input_timing.focus_timeout_ms))
+        .unwrap_or_else(|| context.config.current().input_timing.focus_timeout_ms);
+    Duration::from_millis(timeout_ms)
+}
+
+/// Give an element keyboard focus, succeeding once it has it
+async fn focus(context: &ServerContext, element: &Element, timeout: Duration) -> Response {
+    let Some(atspi) = &context.atspi else {
+        return Response::Error {
+            error: "AT-SPI is not available".to_string(),
+        };
+    };
+    match atspi.focus().focus_element(element, timeout).await {
+        Ok(()) => Response::Success {
+            message: format!("Focused: {}", element.name),
+        },
+        Err(e) => Response::Error {
+            error: format!("Failed to focus {}: {:#}", element.name, e),
+        },
+    }
+}
+
+/// Error response for a failed element lookup, with candidates when the
+/// lookup was ambiguous or came close
+fn element_error(error: anyhow::Error) -> Response {
+    let error = match error.downcast::<LookupError>() {
+        Ok(lookup) => {
+            return Response::NoUniqueElement {
+                error: lookup.message,
+                matches: lookup.matches,
+                candidates: lookup.candidates,
+                incomplete: lookup.incomplete,
+            }
+        }
+        Err(error) => error,
+    };
+    match error.downcast::<StaleError>() {
+        Ok(stale) => Response::StaleElement {
+            error: stale.message,
+            candidate: stale.candidate,
+        },
+        Err(error) => Response::Error {
+            error: format!("{:#}", error),
+        },
+    }
+}
+
+/// Settings for a request sent to the focused application
+async fn app_settings(context: &ServerContext) -> AppSettings {
+    let app = focused_app(context).await;
+    settings_for(context, &app)
+}
+
+/// Settings for a request acting on an element: those of the element's
+/// application. The focused window is only taken to be the element's when
+/// AT-SPI focus is in the same application.
+async fn element_settings(context: &ServerContext, element: &Element) -> AppSettings {
+    let focused = context
+        .atspi
+        .as_ref()
+        .and_then(|atspi| atspi.focus().focused());
+    let window = match focused {
+        Some(focused) if focused.bus_name == element.bus_name => focused_window(context).await,
+        _ => None,
+    };
+    let app_name = Some(element.app_name.clone()).filter(|name| !name.is_empty());
+    settings_for(context, &AppIdentity::new(window, app_name))
+}
+
+fn settings_for(context: &ServerContext, app: &AppIdentity) -> AppSettings {
+    let settings = context.config.current().settings_for(app);
+    if let Some(profile) = &settings.profile {
+        debug!("Using profile {} for {:?}", profile, app);
+    }
+    settings
+}
+
+async fn handle_request(
+    request: Request,
+    target: Option<Result<Element>>,
+    settings: Option<&AppSettings>,
+    context: &Arc<ServerContext>,
+) -> Response {
     match request {
+        Request::Health => {
+            info!("Health request");
+            let database = &context.database;
+            let recovery = database.blocking().recovery();
+            let schema_version = database.get_schema_version().await.ok();
+            let element_cache = match context.atspi.as_ref().and_then(|atspi| atspi.cache()) {
+                Some(cache) => Some(cache.stats().await),
+                None => None,
+            };
+
+            Response::Data {
+                data: serde_json::json!({
+                    "status": if recovery.is_some() { "degraded" } else { "ok" },
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "schema_version": schema_version,
+                    "database_recovery": recovery,
+                    "encryption": database.blocking().cipher().map(|c| c.current_key().id().to_string()),
+                    "element_cache": element_cache,
+                    "audit": database.audit_stats(),
+                }),
+            }
+        }
         Request::Type { text } => {
             info!("Type request: {}", text);
+            if let Some(settings) = settings {
+                debug!(
+                    "Typing with {} ms between keys, strategies: {:?}",
+                    settings.input_timing.typing_delay_ms, settings.typing_strategy
+                );
+            }
             Response::Success {
                 message: format!("Would type: {}", text),
             }
@@ -118,10 +572,12 @@ async fn process_request(request: Request) -> Response {
             }
         }
         Request::Focus { element_id } => {
-            info!("Focus request: {}", element_id);
-            Response::Success {
-                message: format!("Would focus: {}", element_id),
-            }
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Focus request: {} ({})", element_id, element.name);
+            focus(context, &element, focus_timeout(context, settings, None)).await
         }
         Request::ListDesktops => {
             info!("List desktops request");
@@ -147,60 +603,856 @@ async fn process_request(request: Request) -> Response {
         }
         Request::GetTaskHistory { limit } => {
             info!("Get task history request: {:?}", limit);
+            let limit = limit
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .min(MAX_HISTORY_LIMIT);
+
+            match context.database.list_task_history(limit).await {
+                Ok(tasks) => Response::Data {
+                    data: serde_json::json!({ "tasks": tasks }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to read task history: {:#}", e),
+                },
+            }
+        }
+        Request::DbQuery {
+            sql,
+            max_rows,
+            timeout_ms,
+        } => {
+            info!("Database query request: {}", sql);
+            let limits = QueryLimits::new(max_rows, timeout_ms);
+
+            match context.database.execute_query(sql, limits).await {
+                Ok(result) => Response::Data {
+                    data: serde_json::json!(result),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::DbBackup { path } => {
+            info!("Database backup request: path={:?}", path);
+            let backups = context.backups.clone();
+            let result = context
+                .database
+                .run(move |db| match path {
+                    Some(path) => backups.create_backup_at(db, Path::new(&path)),
+                    None => backups.create_backup(db),
+                })
+                .await;
+
+            match result {
+                Ok(backup) => Response::Data {
+                    data: serde_json::json!({ "backup": backup }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Backup failed: {:#}", e),
+                },
+            }
+        }
+        Request::DbListBackups => {
+            info!("List backups request");
+            match context.backups.list_backups() {
+                Ok(backups) => Response::Data {
+                    data: serde_json::json!({ "backups": backups }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to list backups: {:#}", e),
+                },
+            }
+        }
+        Request::DbRestore { path } => {
+            info!("Database restore request: {}", path);
+            let backups = context.backups.clone();
+            let backup_path = PathBuf::from(&path);
+            let result = context
+                .database
+                .run(move |db| backups.restore(db, &backup_path))
+                .await;
+
+            match result {
+                Ok(backup) => {
+                    let version = backup.schema_version.unwrap_or_default();
+                    let message = match version < CURRENT_SCHEMA_VERSION {
+                        true => format!(
+                            "Restored database from {} (schema version {}, migrated to {})",
+                            backup.path, version, CURRENT_SCHEMA_VERSION
+                        ),
+                        false => format!(
+                            "Restored database from {} (schema version {})",
+                            backup.path, version
+                        ),
+                    };
+                    Response::Success { message }
+                }
+                Err(e) => Response::Error {
+                    error: format!("Restore failed: {:#}", e),
+                },
+            }
+        }
+        Request::DbRekey => {
+            info!("Database rekey request");
+            let Some(keys) = &context.keys else {
+                return Response::Error {
+                    error: "Database encryption is not enabled ([encryption] enabled = true)"
+                        .to_string(),
+                };
+            };
+
+            match keystore::rekey(context.database.blocking(), keys, &context.backups).await {
+                Ok(report) => Response::Data {
+                    data: serde_json::json!({ "rekey": report }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Rekey failed: {:#}", e),
+                },
+            }
+        }
+        Request::ExportData {
+            tables,
+            format,
+            since,
+            until,
+            types,
+        } => {
+            info!(
+                "Export request: tables={:?}, format={:?}, since={:?}, until={:?}",
+                tables, format, since, until
+            );
+            let parsed = (|| {
+                let tables = match tables {
+                    Some(names) => names
+                        .iter()
+                        .map(|name| name.parse())
+                        .collect::<Result<Vec<DataTable>>>()?,
+                    None => DataTable::ALL.to_vec(),
+                };
+                let format = format.as_deref().unwrap_or("jsonl").parse()?;
+                let filter = ExportFilter::new(
+                    since.as_deref(),
+                    until.as_deref(),
+                    types.unwrap_or_default(),
+                )?;
+                anyhow::Ok((tables, format, filter))
+            })();
+            let result = match parsed {
+                Ok((tables, format, filter)) => {
+                    context.database.export_data(tables, format, filter).await
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(export) => Response::Data {
+                    data: serde_json::json!(export),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Export failed: {:#}", e),
+                },
+            }
+        }
+        Request::ImportData {
+            content,
+            format,
+            table,
+        } => {
+            info!(
+                "Import request: {} bytes, format={:?}, table={:?}",
+                content.len(),
+                format,
+                table
+            );
+            let parsed = (|| {
+                let format: DataFormat = format.as_deref().unwrap_or("jsonl").parse()?;
+                let table = table.as_deref().map(str::parse).transpose()?;
+                anyhow::Ok((format, table))
+            })();
+            let result = match parsed {
+                Ok((format, table)) => context.database.import_data(content, format, table).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(import) => Response::Data {
+                    data: serde_json::json!(import),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Import failed: {:#}", e),
+                },
+            }
+        }
+        Request::GetPreference { key } => {
+            info!("Get preference request: {}", key);
+            match preferences::find(&key) {
+                Ok(preference) => Response::Data {
+                    data: serde_json::json!({ "preference": context.config.describe(preference) }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::SetPreference { key, value } => {
+            info!("Set preference request: {} = {}", key, value);
+            let preference = match preferences::find(&key) {
+                Ok(preference) => preference,
+                Err(e) => {
+                    return Response::Error {
+                        error: format!("{:#}", e),
+                    }
+                }
+            };
+            let value = match value {
+                serde_json::Value::Null => None,
+                value => match preference.validate(&value) {
+                    Ok(value) => Some(value),
+                    Err(e) => {
+                        return Response::Error {
+                            error: format!("{:#}", e),
+                        }
+                    }
+                },
+            };
+
+            let database = &context.database;
+            let result = async {
+                match &value {
+                    Some(value) => {
+                        database
+                            .set_preference(
+                                preference.key,
+                                value.to_string(),
+                                preference.description,
+                            )
+                            .await?
+                    }
+                    None => {
+                        database.delete_preference(preference.key).await?;
+                    }
+                }
+
+                let stored = database.list_preferences().await?;
+                context
+                    .config
+                    .apply_preferences(stored.iter().map(|p| (p.key.as_str(), p.value.as_str())));
+                anyhow::Ok(context.config.describe(preference))
+            }
+            .await;
+
+            match result {
+                Ok(info) => Response::Data {
+                    data: serde_json::json!({ "preference": info }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to set preference: {:#}", e),
+                },
+            }
+        }
+        Request::ListPreferences => {
+            info!("List preferences request");
             Response::Data {
-                data: serde_json::json!({
-                    "tasks": []
-                }),
+                data: serde_json::json!({ "preferences": context.config.describe_all() }),
             }
         }
-        Request::FindElement { name, role } => {
-            info!("Find element request: name={:?}, role={:?}", name, role);
+        Request::GetConfig => {
+            info!("Get config request");
             Response::Data {
-                data: serde_json::json!({
-                    "element": null
-                }),
+                data: serde_json::json!({ "config": context.config.describe_config() }),
             }
         }
-        Request::ClickElement { name, button } => {
-            info!("Click element request: name={}, button={:?}", name, button);
-            Response::Success {
-                message: format!("Would click element: {}", name),
+        Request::ReloadConfig => {
+            info!("Reload config request");
+            let config = context.config.clone();
+            let result = tokio::task::spawn_blocking(move || config.reload())
+                .await
+                .context("Reload task failed")
+                .and_then(|result| result);
+            match result {
+                Ok(report) => Response::Data {
+                    data: serde_json::json!({ "reload": report }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Config reload failed: {:#}", e),
+                },
+            }
+        }
+        Request::SetLogLevel { level } => {
+            info!("Set log level request: {}", level);
+            match context.logging.set(&level) {
+                Ok(previous) => Response::Data {
+                    data: serde_json::json!({
+                        "log_level": {
+                            "previous": previous,
+                            "current": context.logging.directive(),
+                        }
+                    }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::GetProfile {
+            app_name,
+            window_class,
+            app_id,
+        } => {
+            info!("Get profile request");
+            let given = AppIdentity {
+                app_name,
+                window_class,
+                app_id,
+            };
+            let app = if given.is_empty() {
+                focused_app(context).await
+            } else {
+                given
+            };
+            let settings = context.config.current().settings_for(&app);
+            Response::Data {
+                data: serde_json::json!({ "app": app, "settings": settings }),
+            }
+        }
+        Request::Search {
+            query,
+            sources,
+            since,
+            until,
+            limit,
+        } => {
+            // The query text itself may be sensitive, so it is not logged
+            info!(
+                "Search request: sources={:?}, since={:?}, until={:?}",
+                sources, since, until
+            );
+            let parsed = sources
+                .unwrap_or_default()
+                .iter()
+                .map(|s| s.parse::<SearchSource>())
+                .collect::<anyhow::Result<Vec<_>>>()
+                .and_then(|sources| {
+                    SearchQuery::new(&query, sources, since.as_deref(), until.as_deref(), limit)
+                });
+            let result = match parsed {
+                Ok(query) => context.database.search(query).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(results) => Response::Data {
+                    data: serde_json::json!({ "results": results }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Search failed: {:#}", e),
+                },
             }
         }
-        Request::DoubleClickElement { name } => {
-            info!("Double-click element request: name={}", name);
+        Request::GetStats {
+            since,
+            until,
+            window,
+            group_by,
+        } => {
+            info!(
+                "Stats request: since={:?}, until={:?}, window={:?}, group_by={:?}",
+                since, until, window, group_by
+            );
+            let parsed = group_by
+                .unwrap_or_default()
+                .iter()
+                .map(|g| g.parse::<StatsGroup>())
+                .collect::<anyhow::Result<Vec<_>>>()
+                .and_then(|groups| {
+                    StatsQuery::new(
+                        since.as_deref(),
+                        until.as_deref(),
+                        window.as_deref(),
+                        groups,
+                    )
+                });
+            let result = match parsed {
+                Ok(query) => context.database.operation_stats(query).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(stats) => Response::Data {
+                    data: serde_json::json!({ "stats": stats }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Stats failed: {:#}", e),
+                },
+            }
+        }
+        Request::FindElement {
+            name,
+            role,
+            states,
+            app,
+            selector,
+            fuzzy,
+            max_depth,
+        } => {
+            info!(
+                "Find element request: name={:?}, role={:?}, selector={:?}",
+                name, role, selector
+            );
+            let fuzzy = fuzzy.unwrap_or(false);
+            let selector = match element_selector(name, role, states, app, selector, fuzzy) {
+                Ok(selector) => selector,
+                Err(e) => {
+                    return Response::Error {
+                        error: format!("{:#}", e),
+                    }
+                }
+            };
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let finder = atspi
+                .finder()
+                .with_max_depth(max_depth.unwrap_or(element::DEFAULT_MAX_DEPTH));
+            match finder.find_elements(&selector).await {
+                Ok(found) => Response::Data {
+                    data: serde_json::json!({
+                        "element": found.elements.first(),
+                        "elements": found.elements,
+                        "incomplete": found.incomplete,
+                    }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Element search failed: {:#}", e),
+                },
+            }
+        }
+        Request::ClickElement { button, .. } => {
+            let button = match button.as_deref().unwrap_or("left").parse::<ClickButton>() {
+                Ok(button) => button,
+                Err(e) => {
+                    return Response::Error {
+                        error: format!("{:#}", e),
+                    }
+                }
+            };
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!(
+                "Click element request: name={}, path={}, button={:?}",
+                element.name, element.path, button
+            );
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let handler = input_handler(context, settings);
+            match handler.click(atspi.connection(), &element, button).await {
+                Ok(ClickMethod::Action(action)) => Response::Success {
+                    message: format!("Clicked element: {} (action {:?})", element.name, action),
+                },
+                Ok(ClickMethod::Pointer) => Response::Success {
+                    message: format!("Would click element: {}", element.name),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to click {}: {:#}", element.name, e),
+                },
+            }
+        }
+        Request::DoubleClickElement { .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!(
+                "Double-click element request: name={}, path={}",
+                element.name, element.path
+            );
             Response::Success {
-                message: format!("Would double-click element: {}", name),
+                message: format!("Would double-click element: {}", element.name),
             }
         }
         Request::TypeIntoElement {
-            name,
             text,
             secure,
+            strategy,
+            ..
         } => {
-            if secure.unwrap_or(false) {
-                info!("Type secure into element: name={}", name);
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            let secure = secure.unwrap_or(false);
+            if secure {
+                info!("Type secure into element: name={}", element.name);
             } else {
-                info!("Type into element: name={}, text={}", name, text);
+                info!("Type into element: name={}, text={}", element.name, text);
             }
-            Response::Success {
-                message: format!("Would type into element: {}", name),
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let strategies = match (strategy, settings) {
+                (Some(strategy), _) => vec![strategy],
+                (None, Some(settings)) => settings.typing_strategy.clone(),
+                (None, None) => {
+                    let config = context.config.current();
+                    config.settings_for(&AppIdentity::default()).typing_strategy
+                }
+            };
+            let handler = input_handler(context, settings);
+            let typed = handler.type_into(atspi.connection(), &element, &text, secure, &strategies);
+            match typed.await {
+                Ok(Typed::Verified(strategy)) => Response::Success {
+                    message: format!("Typed into element: {} ({})", element.name, strategy),
+                },
+                Ok(Typed::Unverified(strategy)) => Response::Success {
+                    message: format!(
+                        "Typed into element: {} ({}; it hides its text, so unverified)",
+                        element.name, strategy
+                    ),
+                },
+                Ok(Typed::Simulated(strategy)) => Response::Success {
+                    message: format!("Would type into element: {} ({})", element.name, strategy),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
             }
         }
-        Request::FocusElement { name } => {
-            info!("Focus element request: name={}", name);
-            Response::Success {
-                message: format!("Would focus element: {}", name),
-            }
+        Request::FocusElement { .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!(
+                "Focus element request: name={}, path={}",
+                element.name, element.path
+            );
+            focus(context, &element, focus_timeout(context, settings, None)).await
         }
         Request::GetFocusedElement => {
             info!("Get focused element request");
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match atspi.focus().get_focused_element().await {
+                Ok(element) => Response::Data {
+                    data: serde_json::json!({
+                        "element": element
+                    }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to get focused element: {:#}", e),
+                },
+            }
+        }
+        Request::WaitForFocus { timeout_ms, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            let timeout = focus_timeout(context, settings, timeout_ms);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match atspi.focus().wait_for_focus(&element, timeout).await {
+                Ok(()) => Response::Success {
+                    message: format!("{} has focus", element.name),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::ListActions { .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("List actions request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match action::actions(atspi.connection(), &element.bus_name, &element.path).await {
+                Ok(actions) => Response::Data {
+                    data: serde_json::json!({
+                        "element": element,
+                        "actions": actions,
+                    }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to list actions of {}: {:#}", element.name, e),
+                },
+            }
+        }
+        Request::DoAction { action, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!(
+                "Do action request: {:?} on {} ({})",
+                action, element.name, element.path
+            );
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match action::do_action(atspi.connection(), &element, &action).await {
+                Ok(action) => Response::Success {
+                    message: format!("Invoked {:?} on {}", action, element.name),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::GetText { start, end, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Get text request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match text::get_text(atspi.connection(), &element, start, end).await {
+                Ok((text, range)) => Response::Data {
+                    data: serde_json::json!({
+                        "text": text,
+                        "range": range,
+                    }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::GetCaret { .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Get caret request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match text::cursor(atspi.connection(), &element).await {
+                Ok(cursor) => Response::Data {
+                    data: serde_json::json!(cursor),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::SetCaret { offset, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Set caret request: {} to {}", element.name, offset);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            match text::set_caret(atspi.connection(), &element, offset).await {
+                Ok(()) => Response::Success {
+                    message: format!("Moved caret of {} to {}", element.name, offset),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::SetSelection { start, end, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Set selection request: {} {}..{}", element.name, start, end);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let range = TextRange { start, end };
+            match text::set_selection(atspi.connection(), &element, range).await {
+                Ok(()) => Response::Success {
+                    message: format!("Selected {}..{} of {}", start, end, element.name),
+                },
+                Err(e) => Response::Error {
+                    error: format!("{:#}", e),
+                },
+            }
+        }
+        Request::InsertText { text, position, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Insert text request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let edit = text::insert(atspi.connection(), &element, position, &text).await;
+            edit_response(&element, edit)
+        }
+        Request::DeleteText { start, end, .. } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Delete text request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let edit = text::replace(atspi.connection(), &element, start, end, "").await;
+            edit_response(&element, edit)
+        }
+        Request::ReplaceText {
+            text, start, end, ..
+        } => {
+            let element = match targeted(target) {
+                Ok(element) => element,
+                Err(e) => return element_error(e),
+            };
+            info!("Replace text request: {} ({})", element.name, element.path);
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let edit = text::replace(atspi.connection(), &element, start, end, &text).await;
+            edit_response(&element, edit)
+        }
+        Request::GetTree {
+            app,
+            window,
+            max_depth,
+            filter,
+            snapshot,
+        } => {
+            let filter = match filter.as_deref().map(str::parse::<Selector>).transpose() {
+                Ok(filter) => filter,
+                Err(e) => {
+                    return Response::Error {
+                        error: format!("{:#}", e),
+                    }
+                }
+            };
+            let scope = TreeScope {
+                app,
+                window,
+                filter,
+            };
+            info!("Get tree request: {}", scope.describe());
+            let Some(atspi) = &context.atspi else {
+                return Response::Error {
+                    error: "AT-SPI is not available".to_string(),
+                };
+            };
+            let finder = atspi
+                .finder()
+                .with_max_depth(max_depth.unwrap_or(element::DEFAULT_MAX_DEPTH));
+            let dump = match finder.dump(&scope).await {
+                Ok(dump) => dump,
+                Err(e) => {
+                    return Response::Error {
+                        error: format!("Tree dump failed: {:#}", e),
+                    }
+                }
+            };
+            let tree = serde_json::json!(dump.roots);
+            let snapshot_id = match snapshot {
+                Some(name) => {
+                    let saved = context
+                        .database
+                        .save_tree_snapshot(name, scope.describe(), dump.nodes, tree.clone())
+                        .await;
+                    match saved {
+                        Ok(id) => Some(id),
+                        Err(e) => {
+                            return Response::Error {
+                                error: format!("Failed to save tree snapshot: {:#}", e),
+                            }
+                        }
+                    }
+                }
+                None => None,
+            };
             Response::Data {
                 data: serde_json::json!({
-                    "element": null
+                    "tree": tree,
+                    "nodes": dump.nodes,
+                    "incomplete": dump.incomplete,
+                    "snapshot_id": snapshot_id,
                 }),
             }
         }
+        Request::ListTreeSnapshots => {
+            info!("List tree snapshots request");
+            match context.database.list_tree_snapshots().await {
+                Ok(snapshots) => Response::Data {
+                    data: serde_json::json!({ "snapshots": snapshots }),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to list tree snapshots: {:#}", e),
+                },
+            }
+        }
+        Request::GetTreeSnapshot { id } => {
+            info!("Get tree snapshot request: {}", id);
+            match context.database.get_tree_snapshot(id).await {
+                Ok(Some(snapshot)) => Response::Data {
+                    data: serde_json::json!({ "snapshot": snapshot }),
+                },
+                Ok(None) => Response::Error {
+                    error: format!("No tree snapshot with id {}", id),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to load tree snapshot: {:#}", e),
+                },
+            }
+        }
+        Request::DeleteTreeSnapshot { id } => {
+            info!("Delete tree snapshot request: {}", id);
+            match context.database.delete_tree_snapshot(id).await {
+                Ok(true) => Response::Success {
+                    message: format!("Deleted tree snapshot {}", id),
+                },
+                Ok(false) => Response::Error {
+                    error: format!("No tree snapshot with id {}", id),
+                },
+                Err(e) => Response::Error {
+                    error: format!("Failed to delete tree snapshot: {:#}", e),
+                },
+            }
+        }
         Request::KeyPress { key } => {
             info!("Key press request: {}", key);
             Response::Success {
@@ -237,7 +1489,10 @@ async fn process_request(request: Request) -> Response {
                 from_x, from_y, to_x, to_y
             );
             Response::Success {
-                message: format!("Would drag from ({}, {}) to ({}, {})", from_x, from_y, to_x, to_y),
+                message: format!(
+                    "Would drag from ({}, {}) to ({}, {})",
+                    from_x, from_y, to_x, to_y
+                ),
             }
         }
         Request::Screenshot {
@@ -0,0 +1,423 @@
+// Logging setup: level filter, output formats and log file rotation
+use crate::config::Config;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::fmt::{FmtContext, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of enclosing spans
+    Json,
+}
+
+/// Runtime control over the log filter
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directive: Mutex<String>,
+    /// `RUST_LOG` was set, so `log_level` changes are ignored
+    from_env: bool,
+}
+
+impl LogControl {
+    /// The filter directive in effect
+    pub fn directive(&self) -> String {
+        self.directive
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Change the filter. A bare level such as `debug` applies to deskd; a
+    /// full directive (`deskd=debug,zbus=info`) is used as given. Returns
+    /// the previous directive.
+    pub fn set(&self, level: &str) -> Result<String> {
+        let directive = level_directive(level);
+        let filter = EnvFilter::builder()
+            .parse(&directive)
+            .map_err(|e| anyhow::anyhow!("Invalid log level {}: {}", level, e))?;
+        self.handle
+            .reload(filter)
+            .context("Failed to change log level")?;
+
+        let mut current = self.directive.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(std::mem::replace(&mut *current, directive))
+    }
+
+    /// Apply a new `log_level` from the configuration unless `RUST_LOG`
+    /// takes precedence
+    pub fn follow_config(&self, level: &str) {
+        if self.from_env {
+            return;
+        }
+        match self.set(level) {
+            Ok(_) => tracing::info!("Log level changed to {}", level),
+            Err(e) => tracing::warn!("{:#}", e),
+        }
+    }
+}
+
+fn level_directive(level: &str) -> String {
+    let level = level.trim();
+    if level.contains(['=', ',']) {
+        level.to_string()
+    } else {
+        format!("deskd={}", level)
+    }
+}
+
+/// Install the global subscriber from the effective configuration.
+/// `RUST_LOG`, when set, replaces `log_level`.
+pub fn init(config: &Config) -> Result<LogControl> {
+    let rust_log = std::env::var("RUST_LOG").ok();
+    let directive = rust_log
+        .clone()
+        .unwrap_or_else(|| level_directive(&config.log_level));
+    let filter = EnvFilter::builder()
+        .parse(&directive)
+        .with_context(|| format!("Invalid log filter: {}", directive))?;
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let writer = match &config.logging.file {
+        Some(path) => {
+            let file = RotatingFile::open(
+                Path::new(path),
+                config.logging.max_file_size_mb * 1024 * 1024,
+                config.logging.max_files,
+            )
+            .with_context(|| format!("Failed to open log file: {}", path))?;
+            BoxMakeWriter::new(LogFile(Arc::new(Mutex::new(file))))
+        }
+        None => BoxMakeWriter::new(io::stderr),
+    };
+    let to_file = config.logging.file.is_some();
+
+    let (text, json) = match config.logging.format {
+        LogFormat::Text => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(!to_file)
+                    .with_writer(writer),
+            ),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .event_format(JsonFormat)
+                    .fmt_fields(JsonFields)
+                    .with_writer(writer),
+            ),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .try_init()
+        .context("Failed to initialize logging")?;
+
+    Ok(LogControl {
+        handle,
+        directive: Mutex::new(directive),
+        from_env: rust_log.is_some(),
+    })
+}
+
+/// A log file that is renamed to `<file>.1` (shifting older ones up) when
+/// it would grow past `max_bytes`
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            size: file.metadata()?.len(),
+            path: path.to_path_buf(),
+            file,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = std::fs::remove_file(self.rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[derive(Clone)]
+struct LogFile(Arc<Mutex<RotatingFile>>);
+
+struct LogFileWriter<'a>(MutexGuard<'a, RotatingFile>);
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogFileWriter(self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Write for LogFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Formats events as single-line JSON objects:
+/// `{"timestamp":…,"level":…,"target":…,"message":…,<fields>,"spans":[…]}`
+pub struct JsonFormat;
+
+/// Records span fields as a JSON object so `JsonFormat` can nest them
+pub struct JsonFields;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".into(),
+            chrono::Utc::now()
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), metadata.target().into());
+
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        line.extend(fields.0);
+
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope
+                .from_root()
+                .map(|span| {
+                    let mut object = Map::new();
+                    object.insert("name".into(), span.name().into());
+                    if let Some(recorded) = span.extensions().get::<FormattedFields<N>>() {
+                        if let Ok(Value::Object(fields)) = serde_json::from_str(recorded) {
+                            object.extend(fields);
+                        }
+                    }
+                    Value::Object(object)
+                })
+                .collect();
+            if !spans.is_empty() {
+                line.insert("spans".into(), spans.into());
+            }
+        }
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        if let Ok(Value::Object(existing)) = serde_json::from_str(&current.fields) {
+            visitor.0 = existing;
+        }
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("deskd-logging-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("logs/deskd.log");
+        let mut file = RotatingFile::open(&path, 100, 2).unwrap();
+
+        for i in 0..10 {
+            let line = format!("{:02} {}\n", i, "x".repeat(40));
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        // Two 44-byte lines per file; only the two newest rotations are kept
+        assert!(read(&path).starts_with("08 "));
+        assert!(read(&dir.join("logs/deskd.log.1")).starts_with("06 "));
+        assert!(read(&dir.join("logs/deskd.log.2")).starts_with("04 "));
+        assert!(!dir.join("logs/deskd.log.3").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_level_directives() {
+        assert_eq!(level_directive("debug"), "deskd=debug");
+        assert_eq!(
+            level_directive("deskd=trace,zbus=info"),
+            "deskd=trace,zbus=info"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_include_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .fmt_fields(JsonFields)
+                .with_writer(move || writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "r-1");
+            let _entered = span.enter();
+            tracing::info!(count = 3, "handled {}", "Type");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "handled Type");
+        assert_eq!(line["count"], 3);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["request_id"], "r-1");
+    }
+}
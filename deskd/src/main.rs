@@ -1,47 +1,285 @@
 use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal::unix::{signal, SignalKind};
-use tracing::{error, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::{error, info, warn};
 
 mod atspi;
 mod config;
 mod db;
 mod input;
 mod ipc;
+mod logging;
 mod wayland;
 
-use config::Config;
+use config::layers::ConfigLoader;
+use config::runtime::RuntimeConfig;
+use db::async_db::AsyncDatabase;
+use db::backup::{self, BackupManager};
+use db::keystore::{self, KeyStore};
 use db::Database;
-use ipc::IpcServer;
+use ipc::{IpcServer, ServerContext};
+use logging::LogFormat;
+
+/// Desktop automation daemon for Wayland
+#[derive(Parser)]
+#[command(name = "deskd", version, about)]
+struct Cli {
+    /// Read only this config file instead of /etc/deskd and ~/.config/deskd
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Socket to listen on (overrides socket_path)
+    #[arg(long, value_name = "PATH")]
+    socket: Option<String>,
+
+    /// Database file (overrides database_path)
+    #[arg(long, value_name = "PATH")]
+    database: Option<String>,
+
+    /// Validate the configuration, print the effective settings and exit
+    #[arg(long)]
+    check_config: bool,
+
+    /// Detect the compositor and its capabilities, print them and exit
+    #[arg(long)]
+    print_capabilities: bool,
+
+    /// Log output format (overrides logging.format)
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Run database migrations and exit
+    #[arg(long)]
+    migrate_only: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "deskd=info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let cli = Cli::parse();
 
-    info!("Starting deskd daemon");
+    if cli.print_capabilities {
+        // Detection does not depend on the config file; log to stderr
+        let mut defaults = config::Config::default();
+        defaults.logging.format = cli.log_format.unwrap_or_default();
+        logging::init(&defaults)?;
+
+        let compositor = wayland::detection::detect_compositor();
+        let capabilities = wayland::detection::detect_capabilities(&compositor).await;
+        let report = serde_json::json!({
+            "compositor": compositor,
+            "is_wayland": wayland::detection::is_wayland(),
+            "has_xwayland": wayland::detection::has_xwayland(),
+            "capabilities": capabilities,
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    // Load configuration: defaults, system files, user file, environment,
+    // then command-line flags
+    let mut loader = ConfigLoader::default();
+    if let Some(path) = cli.config {
+        anyhow::ensure!(path.is_file(), "Config file not found: {}", path.display());
+        loader = loader.only_file(path);
+    }
+    if let Some(socket) = cli.socket {
+        loader = loader.flag("--socket", "socket_path", socket);
+    }
+    if let Some(database) = cli.database {
+        loader = loader.flag("--database", "database_path", database);
+    }
+    if let Some(format) = cli.log_format.and_then(|f| f.to_possible_value()) {
+        loader = loader.flag("--log-format", "logging.format", format.get_name());
+    }
+    let loaded = loader.load().context("Failed to load configuration")?;
+
+    if cli.check_config {
+        for warning in &loaded.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        for path in &loaded.files {
+            println!("# {}", path.display());
+        }
+        let runtime_config = RuntimeConfig::new(loader, loaded);
+        for setting in runtime_config.describe_config() {
+            let line = format!("{} = {}", setting.key, setting.value);
+            println!("{:<60} # {}", line, setting.origin);
+        }
+        println!("Configuration OK");
+        return Ok(());
+    }
 
-    // Load configuration
-    let config = Config::load().context("Failed to load configuration")?;
+    // Logging follows the effective configuration from here on
+    let log_control = Arc::new(logging::init(&loaded.config)?);
+
+    info!("Starting deskd daemon");
+    for warning in &loaded.warnings {
+        warn!("{}", warning);
+    }
+    if loaded.files.is_empty() {
+        warn!("No configuration file found, using defaults");
+    }
+    for path in &loaded.files {
+        info!("Loaded configuration from: {}", path.display());
+    }
+    let config = loaded.config.clone();
     info!(
         "Configuration loaded: database={}, socket={}",
         config.database_path, config.socket_path
     );
 
-    // Initialize database
-    let database = Database::new(&config.database_path).context("Failed to initialize database")?;
+    // Initialize database, falling back to the newest backup if it is corrupt
+    let backups = Arc::new(BackupManager::from_config(
+        &config.backup,
+        Path::new(&config.database_path),
+    ));
+    let database = Database::new(&config.database_path, Some(&backups))
+        .context("Failed to initialize database")?;
     database
         .migrate()
         .await
         .context("Failed to run database migrations")?;
+    if cli.migrate_only {
+        println!(
+            "Database {} is at schema version {}",
+            config.database_path,
+            database.get_schema_version()?
+        );
+        return Ok(());
+    }
+
+    // Encrypt sensitive columns when a key source is configured
+    let keys = if config.encryption.enabled {
+        let store = KeyStore::from_config(&config.encryption);
+        let cipher = keystore::load_cipher(&store)
+            .await
+            .with_context(|| format!("Failed to load database key from {}", store.describe()))?;
+        info!(
+            "Column encryption enabled (key {} from {})",
+            cipher.current_key().id(),
+            store.describe()
+        );
+        database.set_cipher(Some(cipher));
+
+        let report = database
+            .reseal_sensitive_columns(false)
+            .context("Failed to encrypt existing values")?;
+        if report.resealed > 0 {
+            info!("Encrypted {} existing values", report.resealed);
+        }
+        if report.unreadable > 0 {
+            warn!(
+                "{} stored values are encrypted with an unknown key and cannot be read",
+                report.unreadable
+            );
+        }
+        if let Err(e) = keystore::prune_retired(&database, &store, &backups).await {
+            warn!("Failed to prune retired database keys: {:#}", e);
+        }
+        Some(Arc::new(store))
+    } else {
+        None
+    };
+
+    // Stored preferences override the config file until changed again
+    let runtime_config = Arc::new(RuntimeConfig::new(loader, loaded));
+    let stored = database
+        .list_preferences()
+        .context("Failed to load preferences")?;
+    runtime_config.apply_preferences(stored.iter().map(|p| (p.key.as_str(), p.value.as_str())));
+    if !stored.is_empty() {
+        info!("Applied {} stored preferences", stored.len());
+    }
+
+    // Reload on SIGHUP (and file changes when enabled); a new log level
+    // applies immediately
+    tokio::spawn({
+        let runtime_config = runtime_config.clone();
+        async move {
+            if let Err(e) = config::watch::run(runtime_config).await {
+                error!("Configuration reloading disabled: {:#}", e);
+            }
+        }
+    });
+    tokio::spawn({
+        let mut changes = runtime_config.subscribe();
+        let mut level = config.log_level.clone();
+        let log_control = log_control.clone();
+        async move {
+            while changes.changed().await.is_ok() {
+                let current = changes.borrow_and_update().log_level.clone();
+                if current != level {
+                    log_control.follow_config(&current);
+                    level = current;
+                }
+            }
+        }
+    });
+
+    // Request handlers reach SQLite through the blocking pool and a batching
+    // audit writer, never on executor threads
+    let database = AsyncDatabase::new(Arc::new(database));
+
+    // Schedule periodic backups
+    if config.backup.enabled {
+        tokio::spawn(backup::run_schedule(
+            backups.clone(),
+            database.blocking().clone(),
+            Duration::from_secs(config.backup.interval_seconds),
+        ));
+    }
 
     // Set up IPC socket server
-    let ipc_server = IpcServer::new(&config.socket_path).context("Failed to create IPC server")?;
+    let compositor = wayland::detection::detect_compositor();
+    info!("Compositor: {:?}", compositor);
+
+    // Element operations need the accessibility bus; the rest of the daemon
+    // works without it
+    let atspi = match tokio::time::timeout(Duration::from_secs(5), atspi::AtSpiClient::new()).await
+    {
+        Ok(Ok(client)) => {
+            // Created even when disabled, so enabling it takes effect on reload
+            let desktop_id = client.desktop_id();
+            info!("Accessibility bus: {}", desktop_id);
+            match database.purge_other_desktops(desktop_id.clone()).await {
+                Ok(0) => {}
+                Ok(removed) => info!("Dropped {} elements cached by earlier sessions", removed),
+                Err(e) => warn!(
+                    "Failed to drop elements cached by earlier sessions: {:#}",
+                    e
+                ),
+            }
+            let cache = atspi::cache::ElementCache::persistent(
+                config.atspi.cache_expiration_seconds,
+                database.clone(),
+                &desktop_id,
+            );
+            Some(Arc::new(client.with_events(cache).await))
+        }
+        Ok(Err(e)) => {
+            warn!("AT-SPI unavailable, element operations disabled: {:#}", e);
+            None
+        }
+        Err(_) => {
+            warn!("AT-SPI did not answer, element operations disabled");
+            None
+        }
+    };
+
+    let context = Arc::new(ServerContext {
+        database: database.clone(),
+        config: runtime_config,
+        backups,
+        keys,
+        compositor,
+        logging: log_control,
+        atspi,
+    });
+    let ipc_server =
+        IpcServer::new(&config.socket_path, context).context("Failed to create IPC server")?;
 
     // Set up signal handlers
     let mut sigterm = signal(SignalKind::terminate())?;
@@ -68,6 +306,7 @@ async fn main() -> Result<()> {
 
     // Cleanup
     info!("Cleaning up...");
+    database.flush_audit().await;
     if let Err(e) = std::fs::remove_file(&config.socket_path) {
         error!("Failed to remove socket file: {}", e);
     }
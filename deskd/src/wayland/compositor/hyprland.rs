@@ -1,6 +1,11 @@
 // Hyprland IPC client
-use anyhow::Result;
+use super::FocusedWindow;
+use anyhow::{Context, Result};
+use serde_json::Value;
 use std::env;
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
 use tracing::{debug, info};
 
 /// Hyprland IPC client for input operations
@@ -16,7 +21,12 @@ impl HyprlandClient {
         let sig = env::var("HYPRLAND_INSTANCE_SIGNATURE")
             .map_err(|_| anyhow::anyhow!("HYPRLAND_INSTANCE_SIGNATURE not set"))?;
 
-        let socket_path = format!("/tmp/hypr/{}/.socket.sock", sig);
+        // Hyprland 0.40 moved its sockets from /tmp to the runtime directory
+        let socket_path = env::var("XDG_RUNTIME_DIR")
+            .map(|dir| format!("{}/hypr/{}/.socket.sock", dir, sig))
+            .ok()
+            .filter(|path| Path::new(path).exists())
+            .unwrap_or_else(|| format!("/tmp/hypr/{}/.socket.sock", sig));
         info!("Hyprland socket: {}", socket_path);
 
         Ok(Self { socket_path })
@@ -25,8 +35,21 @@ impl HyprlandClient {
     /// Send IPC command to Hyprland
     async fn send_command(&self, command: &str) -> Result<String> {
         debug!("Hyprland IPC command: {}", command);
-        // TODO: Implement Hyprland IPC protocol
-        Err(anyhow::anyhow!("Hyprland IPC not yet implemented"))
+        // One command per connection; the reply ends when the socket closes
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("Failed to connect to Hyprland at {}", self.socket_path))?;
+        stream.write_all(command.as_bytes()).await?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await?;
+        Ok(reply)
+    }
+
+    /// The window with keyboard focus
+    pub async fn focused_window(&self) -> Result<Option<FocusedWindow>> {
+        let window: Value = serde_json::from_str(&self.send_command("j/activewindow").await?)
+            .context("Invalid reply to activewindow")?;
+        Ok(parse_active_window(&window))
     }
 
     /// Type text using Hyprland dispatch
@@ -50,9 +73,36 @@ impl HyprlandClient {
     }
 }
 
+/// `activewindow` reports `{}` when nothing is focused. The class is the
+/// app_id for Wayland clients and WM_CLASS for XWayland ones.
+fn parse_active_window(window: &Value) -> Option<FocusedWindow> {
+    let class = window["class"].as_str()?.to_string();
+    let xwayland = window["xwayland"].as_bool().unwrap_or(false);
+    Some(FocusedWindow {
+        app_id: (!xwayland).then(|| class.clone()),
+        window_class: xwayland.then_some(class),
+        title: window["title"].as_str().map(str::to_string),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_active_window() {
+        let window = parse_active_window(&json!({
+            "class": "code", "title": "main.rs", "xwayland": false
+        }))
+        .unwrap();
+        assert_eq!(window.app_id.as_deref(), Some("code"));
+        assert_eq!(window.window_class, None);
+
+        let window = parse_active_window(&json!({ "class": "Steam", "xwayland": true })).unwrap();
+        assert_eq!(window.window_class.as_deref(), Some("Steam"));
+        assert!(parse_active_window(&json!({})).is_none());
+    }
 
     #[test]
     fn test_hyprland_client() {
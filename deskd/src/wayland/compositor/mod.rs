@@ -6,6 +6,27 @@ pub mod kde;
 
 use anyhow::Result;
 use crate::wayland::detection::CompositorType;
+use serde::Serialize;
+
+/// The window with keyboard focus, as the compositor reports it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FocusedWindow {
+    /// Wayland app_id; `None` for XWayland windows
+    pub app_id: Option<String>,
+    /// X11 WM_CLASS of XWayland windows
+    pub window_class: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Ask the compositor which window has focus. Only compositors with an IPC
+/// socket can answer; GNOME and KDE return `None`.
+pub async fn focused_window(compositor: CompositorType) -> Result<Option<FocusedWindow>> {
+    match compositor {
+        CompositorType::Sway => sway::SwayClient::new()?.focused_window().await,
+        CompositorType::Hyprland => hyprland::HyprlandClient::new()?.focused_window().await,
+        _ => Ok(None),
+    }
+}
 
 /// Trait for compositor-specific operations
 #[allow(dead_code)]
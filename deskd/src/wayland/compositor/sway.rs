@@ -1,8 +1,17 @@
 // Sway IPC client
-use anyhow::Result;
+use super::FocusedWindow;
+use anyhow::{Context, Result};
+use serde_json::Value;
 use std::env;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
 use tracing::{debug, info};
 
+/// i3/sway IPC message header magic
+const MAGIC: &[u8] = b"i3-ipc";
+const RUN_COMMAND: u32 = 0;
+const GET_TREE: u32 = 4;
+
 /// Sway IPC client for input operations
 #[allow(dead_code)]
 pub struct SwayClient {
@@ -24,9 +33,43 @@ impl SwayClient {
     /// Send IPC command to Sway
     async fn send_command(&self, command: &str) -> Result<String> {
         debug!("Sway IPC command: {}", command);
-        // TODO: Implement actual Sway IPC protocol
-        // Protocol uses JSON over Unix socket
-        Err(anyhow::anyhow!("Sway IPC not yet implemented"))
+        self.request(RUN_COMMAND, command).await
+    }
+
+    /// One IPC round trip: a header with the payload length and message
+    /// type in native byte order, then the payload; the reply has the same form
+    async fn request(&self, message_type: u32, payload: &str) -> Result<String> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("Failed to connect to Sway at {}", self.socket_path))?;
+
+        let mut message = MAGIC.to_vec();
+        message.extend((payload.len() as u32).to_ne_bytes());
+        message.extend(message_type.to_ne_bytes());
+        message.extend(payload.as_bytes());
+        stream.write_all(&message).await?;
+
+        let mut header = [0u8; 14];
+        stream.read_exact(&mut header).await?;
+        anyhow::ensure!(&header[..6] == MAGIC, "Unexpected reply from Sway IPC");
+        let length = u32::from_ne_bytes(header[6..10].try_into()?) as usize;
+        let mut reply = vec![0u8; length];
+        stream.read_exact(&mut reply).await?;
+        Ok(String::from_utf8(reply)?)
+    }
+
+    /// The window with keyboard focus
+    pub async fn focused_window(&self) -> Result<Option<FocusedWindow>> {
+        let tree: Value = serde_json::from_str(&self.request(GET_TREE, "").await?)
+            .context("Invalid tree from Sway IPC")?;
+        Ok(find_focused(&tree).map(|node| FocusedWindow {
+            app_id: node["app_id"].as_str().map(str::to_string),
+            // XWayland windows have a class instead of an app_id
+            window_class: node["window_properties"]["class"]
+                .as_str()
+                .map(str::to_string),
+            title: node["name"].as_str().map(str::to_string),
+        }))
     }
 
     /// Type text using Sway
@@ -51,9 +94,42 @@ impl SwayClient {
     }
 }
 
+/// Depth-first search for the focused node among tiled and floating nodes
+fn find_focused(node: &Value) -> Option<&Value> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[*key].as_array())
+        .flatten()
+        .find_map(find_focused)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_focused() {
+        let tree = json!({
+            "focused": false,
+            "nodes": [{
+                "focused": false,
+                "nodes": [{ "focused": false, "app_id": "foot", "nodes": [] }],
+                "floating_nodes": [{
+                    "focused": true,
+                    "app_id": null,
+                    "window_properties": { "class": "Slack" },
+                    "nodes": []
+                }]
+            }]
+        });
+        let focused = find_focused(&tree).unwrap();
+        assert_eq!(focused["window_properties"]["class"], "Slack");
+        assert!(find_focused(&json!({ "focused": false })).is_none());
+    }
 
     #[test]
     fn test_sway_client() {
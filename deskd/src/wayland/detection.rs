@@ -229,8 +229,7 @@ mod tests {
     #[tokio::test]
     async fn test_capabilities_detection() {
         let compositor = detect_compositor();
-        let caps = detect_capabilities(&compositor).await;
-        // Should return valid capabilities
-        assert!(caps.has_portal || !caps.has_portal); // Always true
+        // Detection must finish on any host; what it finds depends on the desktop
+        let _caps = detect_capabilities(&compositor).await;
     }
 }
@@ -1,18 +1,65 @@
 // Input method abstraction and priority system
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use tracing::{debug, info};
 
 /// Supported input methods in priority order
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InputMethod {
     Portal,      // XDG RemoteDesktop Portal (highest priority, most secure)
+    #[serde(rename = "compositor_ipc")]
     CompositorIPC, // Sway/Hyprland/etc IPC
     Libei,       // libei/libinput injection
     Ydotool,     // ydotool daemon
+    #[serde(rename = "xtest")]
     XTest,       // X11 fallback (XWayland)
 }
 
+impl InputMethod {
+    /// Every method, in default priority order
+    pub const ALL: [InputMethod; 5] = [
+        Self::Portal,
+        Self::CompositorIPC,
+        Self::Libei,
+        Self::Ydotool,
+        Self::XTest,
+    ];
+
+    /// Name used in the configuration file
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Portal => "portal",
+            Self::CompositorIPC => "compositor_ipc",
+            Self::Libei => "libei",
+            Self::Ydotool => "ydotool",
+            Self::XTest => "xtest",
+        }
+    }
+}
+
+impl FromStr for InputMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            // Older configs used the descriptive name from the example file
+            "compositor-specific" | "compositor" => Ok(Self::CompositorIPC),
+            _ => Self::ALL
+                .into_iter()
+                .find(|method| method.name() == s)
+                .with_context(|| {
+                    format!(
+                        "Unknown input method: {} (expected one of: {})",
+                        s,
+                        Self::ALL.map(|m| m.name()).join(", ")
+                    )
+                }),
+        }
+    }
+}
+
 /// Input method priority configuration
 #[allow(dead_code)]
 pub struct InputMethodPriority {
@@ -298,4 +345,16 @@ mod tests {
         assert_eq!(*methods[0], InputMethod::Portal);
         assert_eq!(*methods[1], InputMethod::CompositorIPC);
     }
+
+    #[test]
+    fn test_input_method_names() {
+        for method in InputMethod::ALL {
+            assert_eq!(method.name().parse::<InputMethod>().unwrap(), method);
+        }
+        assert_eq!(
+            "compositor-specific".parse::<InputMethod>().unwrap(),
+            InputMethod::CompositorIPC
+        );
+        assert!("xdotool".parse::<InputMethod>().is_err());
+    }
 }
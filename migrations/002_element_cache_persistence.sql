@@ -0,0 +1,26 @@
+-- Persistent AT-SPI element cache, keyed per desktop with hit/miss statistics.
+-- The table only holds cached data, so it is recreated rather than altered.
+
+DROP TABLE IF EXISTS element_cache;
+
+CREATE TABLE element_cache (
+    desktop_id TEXT NOT NULL,
+    cache_key TEXT NOT NULL,
+    role TEXT NOT NULL,
+    name TEXT,
+    description TEXT,
+    bus_name TEXT, -- D-Bus name of the owning application
+    path TEXT, -- AT-SPI object path
+    properties TEXT, -- JSON (full element)
+    hit_count INTEGER NOT NULL DEFAULT 0,
+    miss_count INTEGER NOT NULL DEFAULT 0,
+    last_hit_at TIMESTAMP,
+    cached_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP,
+    PRIMARY KEY (desktop_id, cache_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_element_cache_desktop_id ON element_cache(desktop_id);
+CREATE INDEX IF NOT EXISTS idx_element_cache_expires_at ON element_cache(expires_at);
+
+INSERT INTO schema_version (version) VALUES (2);
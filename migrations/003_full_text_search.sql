@@ -0,0 +1,77 @@
+-- Full-text search over task history details and clipboard history content.
+-- The FTS tables keep their own copy of the text with rowid = source row id.
+-- Redacted secure entries ("[secure input]" or "secure": true) are never
+-- indexed, and neither is encrypted clipboard content.
+
+CREATE VIRTUAL TABLE IF NOT EXISTS task_history_fts USING fts5(
+    details,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS clipboard_history_fts USING fts5(
+    content,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- task_history
+CREATE TRIGGER IF NOT EXISTS task_history_fts_insert AFTER INSERT ON task_history
+WHEN NEW.details IS NOT NULL
+    AND instr(NEW.details, '[secure input]') = 0
+    AND NOT (json_valid(NEW.details) AND json_extract(NEW.details, '$.secure') IS 1)
+BEGIN
+    INSERT INTO task_history_fts (rowid, details) VALUES (NEW.id, NEW.details);
+END;
+
+CREATE TRIGGER IF NOT EXISTS task_history_fts_delete AFTER DELETE ON task_history
+BEGIN
+    DELETE FROM task_history_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS task_history_fts_update AFTER UPDATE OF details ON task_history
+BEGIN
+    DELETE FROM task_history_fts WHERE rowid = OLD.id;
+    INSERT INTO task_history_fts (rowid, details)
+    SELECT NEW.id, NEW.details
+    WHERE NEW.details IS NOT NULL
+        AND instr(NEW.details, '[secure input]') = 0
+        AND NOT (json_valid(NEW.details) AND json_extract(NEW.details, '$.secure') IS 1);
+END;
+
+-- clipboard_history (text only)
+CREATE TRIGGER IF NOT EXISTS clipboard_history_fts_insert AFTER INSERT ON clipboard_history
+WHEN (NEW.content_type IS NULL OR NEW.content_type LIKE 'text/%')
+    AND NEW.content NOT LIKE 'enc:v1:%'
+    AND instr(NEW.content, '[secure input]') = 0
+BEGIN
+    INSERT INTO clipboard_history_fts (rowid, content) VALUES (NEW.id, NEW.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS clipboard_history_fts_delete AFTER DELETE ON clipboard_history
+BEGIN
+    DELETE FROM clipboard_history_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS clipboard_history_fts_update AFTER UPDATE OF content, content_type ON clipboard_history
+BEGIN
+    DELETE FROM clipboard_history_fts WHERE rowid = OLD.id;
+    INSERT INTO clipboard_history_fts (rowid, content)
+    SELECT NEW.id, NEW.content
+    WHERE (NEW.content_type IS NULL OR NEW.content_type LIKE 'text/%')
+        AND NEW.content NOT LIKE 'enc:v1:%'
+        AND instr(NEW.content, '[secure input]') = 0;
+END;
+
+-- Index rows that already exist
+INSERT INTO task_history_fts (rowid, details)
+SELECT id, details FROM task_history
+WHERE details IS NOT NULL
+    AND instr(details, '[secure input]') = 0
+    AND NOT (json_valid(details) AND json_extract(details, '$.secure') IS 1);
+
+INSERT INTO clipboard_history_fts (rowid, content)
+SELECT id, content FROM clipboard_history
+WHERE (content_type IS NULL OR content_type LIKE 'text/%')
+    AND content NOT LIKE 'enc:v1:%'
+    AND instr(content, '[secure input]') = 0;
+
+INSERT INTO schema_version (version) VALUES (3);
@@ -0,0 +1,15 @@
+-- Accessibility tree snapshots taken with GetTree, kept for later comparison.
+-- The tree is encrypted at rest when a database key is configured.
+
+CREATE TABLE IF NOT EXISTS tree_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    scope TEXT NOT NULL, -- what was dumped, e.g. "app=gedit window=Save"
+    node_count INTEGER NOT NULL,
+    tree TEXT NOT NULL, -- JSON array of root nodes
+    taken_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_tree_snapshots_name ON tree_snapshots(name);
+
+INSERT INTO schema_version (version) VALUES (4);
@@ -0,0 +1,6 @@
+-- Ancestry of cached elements, so accessibility events can invalidate the
+-- entries below a changed object without dropping the rest of its application.
+
+ALTER TABLE element_cache ADD COLUMN ancestors TEXT; -- JSON array of object paths, root first; NULL when unknown
+
+INSERT INTO schema_version (version) VALUES (5);